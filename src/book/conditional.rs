@@ -0,0 +1,86 @@
+//! Conditional formatting rules evaluated when rendering a cell.
+use serde_json::{Value, json};
+
+use super::sidecar::{address_from_json, address_to_json, range_contains};
+use crate::ui::Address;
+
+/// A condition a cell's numeric value is tested against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    GreaterThan(f64),
+    LessThan(f64),
+    /// Inclusive of both ends.
+    Between(f64, f64),
+}
+
+impl Condition {
+    pub fn matches(&self, value: f64) -> bool {
+        match self {
+            Condition::GreaterThan(n) => value > *n,
+            Condition::LessThan(n) => value < *n,
+            Condition::Between(low, high) => value >= *low && value <= *high,
+        }
+    }
+}
+
+/// A rule that styles the cells in a range when their value matches a condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalFormat {
+    pub start: Address,
+    pub end: Address,
+    pub condition: Condition,
+    /// Background color as a hex string.
+    pub bg_color: Option<String>,
+    /// Foreground color as a hex string.
+    pub fg_color: Option<String>,
+}
+
+impl ConditionalFormat {
+    pub fn contains(&self, addr: &Address) -> bool {
+        range_contains(&self.start, &self.end, addr)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (op, values) = match self.condition {
+            Condition::GreaterThan(n) => ("gt", vec![n]),
+            Condition::LessThan(n) => ("lt", vec![n]),
+            Condition::Between(low, high) => ("between", vec![low, high]),
+        };
+        json!({
+            "start": Value::Object(address_to_json(&self.start)),
+            "end": Value::Object(address_to_json(&self.end)),
+            "op": op,
+            "values": values,
+            "bg_color": self.bg_color,
+            "fg_color": self.fg_color,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let values: Vec<f64> = value
+            .get("values")?
+            .as_array()?
+            .iter()
+            .filter_map(Value::as_f64)
+            .collect();
+        let condition = match (value.get("op")?.as_str()?, values.as_slice()) {
+            ("gt", [n]) => Condition::GreaterThan(*n),
+            ("lt", [n]) => Condition::LessThan(*n),
+            ("between", [low, high]) => Condition::Between(*low, *high),
+            _ => return None,
+        };
+        Some(Self {
+            start: address_from_json(value.get("start")?)?,
+            end: address_from_json(value.get("end")?)?,
+            condition,
+            bg_color: value
+                .get("bg_color")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            fg_color: value
+                .get("fg_color")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        })
+    }
+}
@@ -0,0 +1,85 @@
+//! Number formats and text alignment for displaying cells.
+
+/// How a numeric cell is displayed. Formats only change how a value is shown.
+/// The value in the cell stays the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellFormat {
+    /// Show the value as ironcalc would by default.
+    General,
+    /// A dollar amount with thousands separators and two decimals like `$1,234.50`.
+    Currency,
+    /// The value times 100 with a percent sign like `25%`.
+    Percent,
+    /// A fixed number of decimal places.
+    Decimals(usize),
+}
+
+impl CellFormat {
+    /// Parse a format description like `currency`, `percent`, `decimals 2`, or
+    /// `general`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let format = match words.next()?.to_lowercase().as_str() {
+            "general" => CellFormat::General,
+            "currency" => CellFormat::Currency,
+            "percent" => CellFormat::Percent,
+            "decimals" => CellFormat::Decimals(words.next()?.parse().ok()?),
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(format)
+    }
+
+    /// The xlsx number format string for this format.
+    pub fn num_fmt(&self) -> String {
+        match self {
+            CellFormat::General => "general".to_owned(),
+            CellFormat::Currency => "$#,##0.00".to_owned(),
+            CellFormat::Percent => "0%".to_owned(),
+            CellFormat::Decimals(0) => "0".to_owned(),
+            CellFormat::Decimals(places) => format!("0.{}", "0".repeat(*places)),
+        }
+    }
+
+    /// The horizontal alignment cells with this format get. Formatted numbers
+    /// are right aligned.
+    pub fn alignment(&self) -> &'static str {
+        match self {
+            CellFormat::General => "general",
+            _ => "right",
+        }
+    }
+}
+
+/// Where a cell's text sits within its column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellAlignment {
+    Left,
+    /// Padding is split evenly. When it can't be the extra space goes on the
+    /// right.
+    Center,
+    Right,
+}
+
+impl CellAlignment {
+    /// Parse an alignment like `left`, `center`, or `right`.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "left" => Some(CellAlignment::Left),
+            "center" | "centre" => Some(CellAlignment::Center),
+            "right" => Some(CellAlignment::Right),
+            _ => None,
+        }
+    }
+
+    /// The xlsx horizontal alignment for this alignment.
+    pub fn horizontal(&self) -> &'static str {
+        match self {
+            CellAlignment::Left => "left",
+            CellAlignment::Center => "center",
+            CellAlignment::Right => "right",
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Undo and redo history for cell edits.
+use crate::ui::Address;
+
+/// The most edits we remember. Older edits are dropped once we go past it.
+pub(crate) const HISTORY_LIMIT: usize = 100;
+
+/// A single cell's contents before and after an edit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+    pub address: Address,
+    pub old: String,
+    pub new: String,
+}
+
+/// Stacks of edits that can be undone and redone. Each entry is every cell
+/// change made by one user operation so a range paste undoes in one step.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    undo: Vec<Vec<CellChange>>,
+    redo: Vec<Vec<CellChange>>,
+    /// Changes collected for an operation that hasn't finished yet.
+    group: Option<Vec<CellChange>>,
+    /// How many groups have been started but not ended. Nested groups are
+    /// folded into the outermost one.
+    depth: usize,
+}
+
+impl History {
+    /// Record a cell change. A new edit means there is nothing left to redo.
+    pub fn record(&mut self, change: CellChange) {
+        if change.old == change.new {
+            return;
+        }
+        self.redo.clear();
+        match self.group.as_mut() {
+            Some(group) => group.push(change),
+            None => self.push_undo(vec![change]),
+        }
+    }
+
+    /// Start collecting changes into one entry until `end_group` is called.
+    pub fn begin_group(&mut self) {
+        self.depth += 1;
+        if self.group.is_none() {
+            self.group = Some(Vec::new());
+        }
+    }
+
+    /// Finish the entry started by `begin_group`.
+    pub fn end_group(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth > 0 {
+            return;
+        }
+        if let Some(group) = self.group.take() {
+            if !group.is_empty() {
+                self.push_undo(group);
+            }
+        }
+    }
+
+    /// Take the most recent entry to undo and remember it for redo.
+    pub fn undo(&mut self) -> Option<Vec<CellChange>> {
+        let entry = self.undo.pop()?;
+        self.redo.push(entry.clone());
+        Some(entry)
+    }
+
+    /// Take the most recently undone entry and remember it for undo.
+    pub fn redo(&mut self) -> Option<Vec<CellChange>> {
+        let entry = self.redo.pop()?;
+        self.undo.push(entry.clone());
+        Some(entry)
+    }
+
+    fn push_undo(&mut self, entry: Vec<CellChange>) {
+        self.undo.push(entry);
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+}
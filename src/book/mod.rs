@@ -1,20 +1,40 @@
-use std::cmp::max;
+use std::cmp::{Ordering, max};
 
 use anyhow::{Result, anyhow};
 use ironcalc::{
     base::{
         Model, UserModel,
+        cell::CellValue,
         expressions::types::Area,
-        types::{SheetData, Style, Worksheet},
+        types::{HorizontalAlignment, SheetData, Style, Worksheet},
         worksheet::WorksheetDimension,
     },
     export::save_xlsx_to_writer,
     import::load_from_xlsx,
 };
+use unicode_width::UnicodeWidthStr;
 
 use crate::ui::Address;
 
+pub mod conditional;
+pub mod format;
+pub mod history;
+pub mod sidecar;
+#[cfg(test)]
+mod test;
+use conditional::ConditionalFormat;
+use format::{CellAlignment, CellFormat};
+use history::{CellChange, History};
+use sidecar::{Sidecar, range_contains, ranges_overlap};
+
 pub(crate) const COL_PIXELS: f64 = 5.0;
+/// The widest a column gets when it is auto fit to its contents.
+pub(crate) const AUTO_FIT_MAX_WIDTH: usize = 60;
+/// The values ironcalc renders for cells whose formula failed to evaluate.
+pub(crate) const ERROR_VALUES: [&'static str; 12] = [
+    "#ERROR!", "#REF!", "#NAME?", "#VALUE!", "#DIV/0!", "#N/A", "#NUM!", "#NULL!", "#CIRC!",
+    "#SPILL!", "#CALC!", "#N/IMPL!",
+];
 // NOTE(zaphar): This is stolen from ironcalc but ironcalc doesn't expose it
 // publically.
 pub(crate) const LAST_COLUMN: i32 = 16_384;
@@ -86,11 +106,35 @@ impl<'book> AddressRange<'book> {
     }
 }
 
+/// Summary statistics for the numeric cells in a range.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RangeStats {
+    pub sum: f64,
+    pub count: usize,
+}
+
+impl RangeStats {
+    pub fn average(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum / self.count as f64)
+        }
+    }
+}
+
 /// A spreadsheet book with some internal state tracking.
 pub struct Book<'a> {
     pub(crate) model: UserModel<'a>,
     pub location: crate::ui::Address,
     pub dirty: bool,
+    /// Metadata that we persist in a sidecar file next to the workbook.
+    pub(crate) meta: Sidecar,
+    /// Cell edits that can be undone and redone.
+    pub(crate) history: History,
+    /// The delimiter of the delimited file this book was loaded from so saving
+    /// keeps the file's dialect.
+    pub(crate) delimiter: Option<u8>,
 }
 
 impl<'a> Book<'a> {
@@ -100,6 +144,9 @@ impl<'a> Book<'a> {
             model,
             location: Address::default(),
             dirty: false,
+            meta: Sidecar::default(),
+            history: History::default(),
+            delimiter: None,
         }
     }
 
@@ -117,16 +164,148 @@ impl<'a> Book<'a> {
         )?))
     }
 
-    pub fn csv_for_sheet<W>(&self, sheet: u32, sink: W) -> Result<()>
+    /// Construct a new book from a delimited text file like csv or tsv.
+    /// The delimiter is detected by sampling the first few lines of the file.
+    pub fn new_from_delimited_with_locale(path: &str, locale: &str, tz: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let delimiter = detect_delimiter(&contents);
+        Self::new_from_delimited_str(&contents, delimiter, locale, tz)
+    }
+
+    /// Construct a new book from delimited text content using the provided delimiter.
+    pub fn new_from_delimited_str(
+        contents: &str,
+        delimiter: u8,
+        locale: &str,
+        tz: &str,
+    ) -> Result<Self> {
+        let mut book = Self::from_model(
+            Model::new_empty("default_name", locale, tz, "en").map_err(|e| anyhow!(e))?,
+        );
+        book.import_delimited(0, contents, delimiter)?;
+        book.dirty = false;
+        book.history = History::default();
+        book.delimiter = Some(delimiter);
+        Ok(book)
+    }
+
+    /// Import delimited text into a sheet with the first field at A1. If the
+    /// first row looks like a header it is made bold. The import is undone in
+    /// one step.
+    pub fn import_delimited(&mut self, sheet: u32, contents: &str, delimiter: u8) -> Result<()> {
+        let rows = read_delimited_rows(contents, delimiter)?;
+        self.begin_edit();
+        let result = self.write_rows(sheet, &rows);
+        self.end_edit();
+        result?;
+        if looks_like_header(&rows) {
+            let width = rows[0].len();
+            self.set_cell_style(
+                &[("font.b", "true")],
+                &Area {
+                    sheet,
+                    row: 1,
+                    column: 1,
+                    width: width as i32,
+                    height: 1,
+                },
+            )?;
+        }
+        self.evaluate();
+        Ok(())
+    }
+
+    fn write_rows(&mut self, sheet: u32, rows: &[Vec<String>]) -> Result<()> {
+        for (ri, row) in rows.iter().enumerate() {
+            for (ci, field) in row.iter().enumerate() {
+                let address = Address {
+                    sheet,
+                    row: ri + 1,
+                    col: ci + 1,
+                };
+                if !field.is_empty() {
+                    self.update_cell(&address, field)?;
+                } else if !self.get_cell_addr_contents(&address)?.is_empty() {
+                    self.clear_cell_contents(address)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delimited_for_sheet<W>(&self, sheet: u32, delimiter: u8, sink: W) -> Result<()>
     where
         W: std::io::Write,
     {
         let rows = self.get_export_rows_for_sheet(sheet)?;
-        let mut writer = csv::Writer::from_writer(sink);
-        for row in rows {
-            writer.write_record(row)?;
+        write_delimited(&rows, delimiter, sink)
+    }
+
+    /// Get the rows to export as delimited text for a range. If `header` is
+    /// true the first row of the sheet is added above a range that starts
+    /// below it. Otherwise only the range is exported.
+    pub fn get_delimited_rows_for_range(
+        &self,
+        range: AddressRange,
+        header: bool,
+    ) -> Result<Vec<Vec<String>>> {
+        let top = std::cmp::min(range.start.row, range.end.row);
+        let mut rows = Vec::new();
+        if header && top > 1 {
+            let start = Address {
+                row: 1,
+                ..range.start.clone()
+            };
+            let end = Address {
+                row: 1,
+                ..range.end.clone()
+            };
+            rows.extend(self.get_rows_for_range(&AddressRange {
+                start: &start,
+                end: &end,
+            })?);
         }
-        Ok(())
+        rows.extend(self.get_rows_for_range(&range)?);
+        Ok(trim_blank_edges(rows))
+    }
+
+    /// Save a sheet or a range of it to a delimited text file. See
+    /// `get_delimited_rows_for_range` for how `header` is handled.
+    pub fn save_range_to_delimited(
+        &self,
+        sheet: u32,
+        range: Option<(Address, Address)>,
+        delimiter: u8,
+        header: bool,
+        path: &str,
+    ) -> Result<()> {
+        let (start, end) = match range {
+            Some(range) => range,
+            None => {
+                let (rows, cols) = self.get_sheet_size(sheet)?;
+                (
+                    Address {
+                        sheet,
+                        row: 1,
+                        col: 1,
+                    },
+                    Address {
+                        sheet,
+                        row: max(rows, 1),
+                        col: max(cols, 1),
+                    },
+                )
+            }
+        };
+        let rows = self.get_delimited_rows_for_range(
+            AddressRange {
+                start: &start,
+                end: &end,
+            },
+            header,
+        )?;
+        let file = std::fs::File::create(std::path::Path::new(path))?;
+        write_delimited(&rows, delimiter, std::io::BufWriter::new(file))
     }
 
     /// Construct a payload of (html, csv_text) for a sheet.
@@ -147,6 +326,18 @@ impl<'a> Book<'a> {
         rows_to_clipboard_content(&rows)
     }
 
+    /// Render a sheet as a GFM markdown table using the first row as the header.
+    pub fn sheet_to_markdown(&self, sheet: u32) -> Result<String> {
+        let rows = self.get_export_rows_for_sheet(sheet)?;
+        Ok(rows_to_markdown_table(&rows))
+    }
+
+    /// Render an address range as a GFM markdown table using the first row as the header.
+    pub fn range_to_markdown(&self, range: AddressRange) -> Result<String> {
+        let rows = self.get_rows_for_range(&range)?;
+        Ok(rows_to_markdown_table(&rows))
+    }
+
     /// Get rows for current sheet to export.
     pub fn get_export_rows(&self) -> Result<Vec<Vec<String>>> {
         let sheet = self.location.sheet;
@@ -167,6 +358,71 @@ impl<'a> Book<'a> {
     }
 
     pub fn get_export_rows_for_sheet(&self, sheet: u32) -> Result<Vec<Vec<String>>, anyhow::Error> {
+        let (max_row, max_col) = self.get_sheet_size(sheet)?;
+        let mut rows = Vec::new();
+        // NOTE(zaphar): ironcalc rows and columns start at 1.
+        for ri in 1..=max_row {
+            let mut row = Vec::new();
+            for ci in 1..=max_col {
+                let cell_content = self.get_cell_addr_rendered(&Address {
+                    sheet,
+                    row: ri as usize,
+                    col: ci as usize,
+                })?;
+                row.push(cell_content);
+            }
+            rows.push(row);
+        }
+        Ok(trim_blank_edges(rows))
+    }
+
+    /// Find the cells in a sheet whose displayed value contains `query`. The
+    /// matches are in row then column order.
+    pub fn find_cells(
+        &self,
+        sheet: u32,
+        query: &str,
+        case_sensitive: bool,
+    ) -> Result<Vec<Address>> {
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let worksheet = self
+            .model
+            .get_model()
+            .workbook
+            .worksheet(sheet)
+            .map_err(|e| anyhow!(e))?;
+        let query = if case_sensitive {
+            query.to_owned()
+        } else {
+            query.to_lowercase()
+        };
+        let mut matches = Vec::new();
+        for (row, cols) in worksheet.sheet_data.iter() {
+            for col in cols.keys() {
+                let address = Address {
+                    sheet,
+                    row: *row as usize,
+                    col: *col as usize,
+                };
+                let value = self.get_cell_addr_rendered(&address)?;
+                let found = if case_sensitive {
+                    value.contains(&query)
+                } else {
+                    value.to_lowercase().contains(&query)
+                };
+                if found {
+                    matches.push(address);
+                }
+            }
+        }
+        matches.sort();
+        Ok(matches)
+    }
+
+    /// The last (row, column) with any data in a sheet.
+    fn get_sheet_size(&self, sheet: u32) -> Result<(usize, usize)> {
         let worksheet = self
             .model
             .get_model()
@@ -176,29 +432,12 @@ impl<'a> Book<'a> {
         let mut max_row = 0;
         let mut max_col = 0;
         for (r, cols) in worksheet.sheet_data.iter() {
-            if max_row <= *r {
-                max_row = *r;
-            }
+            max_row = max(*r, max_row);
             for (c, _) in cols.iter() {
-                if max_col <= *c {
-                    max_col = *c;
-                }
+                max_col = max(*c, max_col);
             }
         }
-        let mut rows = Vec::new();
-        for ri in 0..=max_row {
-            let mut row = Vec::new();
-            for ci in 0..=max_col {
-                let cell_content = self.get_cell_addr_rendered(&Address {
-                    sheet,
-                    row: ri as usize,
-                    col: ci as usize,
-                })?;
-                row.push(cell_content);
-            }
-            rows.push(row);
-        }
-        Ok(rows)
+        Ok((max_row as usize, max_col as usize))
     }
 
     /// Evaluate the spreadsheet calculating formulas and style changes.
@@ -212,12 +451,12 @@ impl<'a> Book<'a> {
         Ok(Self::from_model(load_from_xlsx(path, locale, tz, "en")?))
     }
 
-    /// Save a sheet in the book to a csv file
-    pub fn save_sheet_to_csv(&self, sheet: u32, path: &str) -> Result<()> {
+    /// Save a sheet in the book to a delimited text file using the provided delimiter.
+    pub fn save_sheet_to_delimited(&self, sheet: u32, delimiter: u8, path: &str) -> Result<()> {
         let file_path = std::path::Path::new(path);
         let file = std::fs::File::create(file_path)?;
         let writer = std::io::BufWriter::new(file);
-        self.csv_for_sheet(sheet, writer)?;
+        self.delimited_for_sheet(sheet, delimiter, writer)?;
         Ok(())
     }
 
@@ -268,17 +507,37 @@ impl<'a> Book<'a> {
         Ok(())
     }
 
+    /// Delete the current sheet and select the sheet that takes its place.
+    /// The last sheet in a book can't be deleted.
+    pub fn delete_sheet(&mut self) -> Result<()> {
+        let len = self.model.get_model().workbook.worksheets.len() as u32;
+        if len <= 1 {
+            return Err(anyhow!("Unable to delete the last sheet in the workbook"));
+        }
+        let sheet = self.location.sheet;
+        self.model.delete_sheet(sheet).map_err(|e| anyhow!(e))?;
+        self.meta.remove_sheet(sheet);
+        self.forget_history();
+        let next = std::cmp::min(sheet, len - 2);
+        self.model
+            .set_selected_sheet(next)
+            .map_err(|e| anyhow!(e))?;
+        self.location.sheet = next;
+        self.dirty = true;
+        Ok(())
+    }
+
     /// Get the sheet data for the current worksheet.
     pub fn get_sheet_data(&self) -> Result<&SheetData> {
         Ok(&self.get_sheet()?.sheet_data)
     }
 
-    /// Move to a specific sheet location in the current sheet
+    /// Move to a specific sheet location in the current sheet. Moving isn't an
+    /// edit so it doesn't make the book dirty.
     pub fn move_to(&mut self, Address { sheet: _, row, col }: &Address) -> Result<()> {
         // FIXME(zaphar): Check that this is safe first.
         self.location.row = *row;
         self.location.col = *col;
-        self.dirty = true;
         Ok(())
     }
 
@@ -292,6 +551,9 @@ impl<'a> Book<'a> {
         .iter()
         .skip(1)
         {
+            if self.is_locked(cell) {
+                continue;
+            }
             let contents = self
                 .model
                 .get_model()
@@ -303,20 +565,322 @@ impl<'a> Book<'a> {
                     cell.col as i32,
                 )
                 .map_err(|e| anyhow!(e))?;
-            self.model
-                .set_user_input(
-                    self.location.sheet,
+            self.update_cell(cell, &contents)?;
+        }
+        self.evaluate();
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Transpose the range in place so its rows become columns. The top left
+    /// corner stays put and relative references in formulas are adjusted for
+    /// where each cell lands. The whole transpose is undone in one step.
+    pub fn transpose_range(&mut self, start: &Address, end: &Address) -> Result<()> {
+        let (start, end) = (
+            Address {
+                sheet: start.sheet,
+                row: std::cmp::min(start.row, end.row),
+                col: std::cmp::min(start.col, end.col),
+            },
+            Address {
+                sheet: start.sheet,
+                row: max(start.row, end.row),
+                col: max(start.col, end.col),
+            },
+        );
+        let dest_end = Address {
+            sheet: start.sheet,
+            row: start.row + (end.col - start.col),
+            col: start.col + (end.row - start.row),
+        };
+        self.ensure_range_unlocked(&start, &end)?;
+        self.ensure_range_unlocked(&start, &dest_end)?;
+        let mut moved = Vec::new();
+        for cell in (AddressRange {
+            start: &start,
+            end: &end,
+        })
+        .as_series()
+        {
+            let target = Address {
+                sheet: start.sheet,
+                row: start.row + (cell.col - start.col),
+                col: start.col + (cell.row - start.row),
+            };
+            let contents = self
+                .model
+                .get_model()
+                .extend_to(
+                    cell.sheet,
                     cell.row as i32,
                     cell.col as i32,
-                    &contents,
+                    target.row as i32,
+                    target.col as i32,
                 )
                 .map_err(|e| anyhow!(e))?;
+            // Cells of the range that nothing lands on are cleared.
+            if !range_contains(&start, &dest_end, &cell) {
+                moved.push((cell, String::new()));
+            }
+            moved.push((target, contents));
         }
+        self.begin_edit();
+        let result = moved.into_iter().try_for_each(|(address, contents)| {
+            if contents.is_empty() {
+                self.clear_cell_contents(address)
+            } else {
+                self.update_cell(&address, contents)
+            }
+        });
+        self.end_edit();
+        result?;
         self.evaluate();
         self.dirty = true;
         Ok(())
     }
 
+    /// Sort the rows of a range, or all of a sheet's data when there is no
+    /// range, by their values in column `col`. Values are compared as numbers
+    /// when every value in the column is a number and as text ignoring case
+    /// otherwise. Empty values sort last and rows with equal values keep their
+    /// order. A header row stays put. Formulas move with their row and have
+    /// their relative references adjusted.
+    pub fn sort_rows(
+        &mut self,
+        sheet: u32,
+        range: Option<(Address, Address)>,
+        col: usize,
+        descending: bool,
+    ) -> Result<()> {
+        let (start, end) = match range {
+            Some(range) => range,
+            None => {
+                let (rows, cols) = self.get_sheet_size(sheet)?;
+                if rows == 0 {
+                    return Ok(());
+                }
+                (
+                    Address {
+                        sheet,
+                        row: 1,
+                        col: 1,
+                    },
+                    Address {
+                        sheet,
+                        row: rows,
+                        col: max(cols, 1),
+                    },
+                )
+            }
+        };
+        if col < start.col || col > end.col {
+            return Err(anyhow!(
+                "The current column is outside the rows being sorted"
+            ));
+        }
+        self.ensure_range_unlocked(&start, &end)?;
+        let rendered = (start.row..=end.row)
+            .map(|row| {
+                (start.col..=end.col)
+                    .map(|col| self.get_cell_addr_rendered(&Address { sheet, row, col }))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let first = if looks_like_header(&rendered) {
+            start.row + 1
+        } else {
+            start.row
+        };
+        let mut keys = Vec::new();
+        for row in first..=end.row {
+            let address = Address { sheet, row, col };
+            let text = rendered[row - start.row][col - start.col].to_lowercase();
+            keys.push((row, text, self.get_cell_addr_value_number(&address)?));
+        }
+        let numeric = keys
+            .iter()
+            .all(|(_, text, number)| text.is_empty() || number.is_some());
+        // NOTE(zaphar): sort_by is stable so ties keep their order in either
+        // direction.
+        keys.sort_by(|(_, a_text, a_number), (_, b_text, b_number)| {
+            match (a_text.is_empty(), b_text.is_empty()) {
+                (true, true) => return Ordering::Equal,
+                (true, false) => return Ordering::Greater,
+                (false, true) => return Ordering::Less,
+                (false, false) => {}
+            }
+            let ordering = if numeric {
+                a_number.partial_cmp(b_number).unwrap_or(Ordering::Equal)
+            } else {
+                a_text.cmp(b_text)
+            };
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        let mut moved = Vec::new();
+        for (offset, (source, _, _)) in keys.iter().enumerate() {
+            let row = first + offset;
+            if *source == row {
+                continue;
+            }
+            for col in start.col..=end.col {
+                let contents = self
+                    .model
+                    .get_model()
+                    .extend_to(sheet, *source as i32, col as i32, row as i32, col as i32)
+                    .map_err(|e| anyhow!(e))?;
+                moved.push((Address { sheet, row, col }, contents));
+            }
+        }
+        // The whole sort is undone in one step.
+        self.begin_edit();
+        let result = moved.into_iter().try_for_each(|(address, contents)| {
+            if contents.is_empty() {
+                self.clear_cell_contents(address)
+            } else {
+                self.update_cell(&address, contents)
+            }
+        });
+        self.end_edit();
+        result?;
+        self.evaluate();
+        Ok(())
+    }
+
+    /// Fill the top row of the range down through the rest of the range.
+    /// Relative references in formulas are adjusted for each row. If `series`
+    /// is true then columns starting with two numbers are extrapolated instead.
+    pub fn fill_down(&mut self, start: &Address, end: &Address, series: bool) -> Result<()> {
+        // The whole fill is undone in one step.
+        self.begin_edit();
+        let result = (start.col..=end.col).try_for_each(|col| {
+            let from = Address {
+                sheet: start.sheet,
+                row: start.row,
+                col,
+            };
+            let to = Address {
+                sheet: start.sheet,
+                row: end.row,
+                col,
+            };
+            self.fill(&from, &to, series)
+        });
+        self.end_edit();
+        result
+    }
+
+    /// Fill the leftmost column of the range right through the rest of the range.
+    /// Relative references in formulas are adjusted for each column. If `series`
+    /// is true then rows starting with two numbers are extrapolated instead.
+    pub fn fill_right(&mut self, start: &Address, end: &Address, series: bool) -> Result<()> {
+        // The whole fill is undone in one step.
+        self.begin_edit();
+        let result = (start.row..=end.row).try_for_each(|row| {
+            let from = Address {
+                sheet: start.sheet,
+                row,
+                col: start.col,
+            };
+            let to = Address {
+                sheet: start.sheet,
+                row,
+                col: end.col,
+            };
+            self.fill(&from, &to, series)
+        });
+        self.end_edit();
+        result
+    }
+
+    fn fill(&mut self, from: &Address, to: &Address, series: bool) -> Result<()> {
+        let cells = AddressRange {
+            start: from,
+            end: to,
+        }
+        .as_series();
+        if series && cells.len() > 2 {
+            if let (Some(first), Some(second)) = (
+                self.get_cell_addr_number(&cells[0])?,
+                self.get_cell_addr_number(&cells[1])?,
+            ) {
+                let step = second - first;
+                for (i, cell) in cells.iter().enumerate().skip(2) {
+                    if self.is_locked(cell) {
+                        continue;
+                    }
+                    self.update_cell(cell, format!("{}", first + step * i as f64))?;
+                }
+                self.evaluate();
+                return Ok(());
+            }
+        }
+        self.extend_to(from, to)
+    }
+
+    /// Insert `=SUM(...)` formulas for the range like a spreadsheet's AutoSum.
+    /// A single row range is summed into the first empty cell to its right.
+    /// Otherwise each column of the range is summed into the first empty cell
+    /// below it. Returns the addresses of the inserted formulas.
+    pub fn auto_sum(&mut self, start: &Address, end: &Address) -> Result<Vec<Address>> {
+        let mut inserted = Vec::new();
+        if start.row == end.row && start.col != end.col {
+            let target = self.first_empty_cell(end, 0, 1)?;
+            self.update_cell(&target, sum_formula(start, end))?;
+            inserted.push(target);
+        } else {
+            for col in start.col..=end.col {
+                let top = Address {
+                    sheet: start.sheet,
+                    row: start.row,
+                    col,
+                };
+                let bottom = Address {
+                    sheet: start.sheet,
+                    row: end.row,
+                    col,
+                };
+                let target = self.first_empty_cell(&bottom, 1, 0)?;
+                self.update_cell(&target, sum_formula(&top, &bottom))?;
+                inserted.push(target);
+            }
+        }
+        self.evaluate();
+        Ok(inserted)
+    }
+
+    /// Find the first empty unlocked cell stepping away from `from`.
+    fn first_empty_cell(
+        &self,
+        from: &Address,
+        row_step: usize,
+        col_step: usize,
+    ) -> Result<Address> {
+        let mut addr = from.clone();
+        loop {
+            addr.row += row_step;
+            addr.col += col_step;
+            if addr.row > LAST_ROW as usize || addr.col > LAST_COLUMN as usize {
+                return Err(anyhow!("No empty cell to put the sum in"));
+            }
+            if !self.is_locked(&addr) && self.get_cell_addr_contents(&addr)?.is_empty() {
+                return Ok(addr);
+            }
+        }
+    }
+
+    /// Get a cells content as a number if it is a numeric literal.
+    fn get_cell_addr_number(&self, addr: &Address) -> Result<Option<f64>> {
+        Ok(self
+            .get_cell_addr_contents(addr)?
+            .trim()
+            .parse::<f64>()
+            .ok())
+    }
+
     pub fn clear_current_cell(&mut self) -> Result<()> {
         self.dirty = true;
         self.clear_cell_contents(self.location.clone())
@@ -328,9 +892,11 @@ impl<'a> Book<'a> {
     }
 
     pub fn clear_cell_contents(&mut self, Address { sheet, row, col }: Address) -> Result<()> {
+        let address = Address { sheet, row, col };
+        self.ensure_unlocked(&address)?;
         self.dirty = true;
-        Ok(self
-            .model
+        let old = self.get_cell_addr_contents(&address)?;
+        self.model
             .range_clear_contents(&Area {
                 sheet,
                 row: row as i32,
@@ -338,22 +904,33 @@ impl<'a> Book<'a> {
                 width: 1,
                 height: 1,
             })
-            .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?)
+            .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
+        self.history.record(CellChange {
+            address,
+            old,
+            new: String::new(),
+        });
+        Ok(())
     }
 
     pub fn clear_cell_range(&mut self, start: Address, end: Address) -> Result<()> {
+        self.ensure_range_unlocked(&start, &end)?;
+        let cleared = self.get_range_contents(&start, &end)?;
         let area = calculate_area(start.sheet, &start, &end);
         self.model
             .range_clear_contents(&area)
             .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
+        self.record_cleared(cleared);
         self.dirty = true;
         Ok(())
     }
 
     pub fn clear_cell_all(&mut self, Address { sheet, row, col }: Address) -> Result<()> {
+        let address = Address { sheet, row, col };
+        self.ensure_unlocked(&address)?;
         self.dirty = true;
-        Ok(self
-            .model
+        let old = self.get_cell_addr_contents(&address)?;
+        self.model
             .range_clear_all(&Area {
                 sheet,
                 row: row as i32,
@@ -361,18 +938,62 @@ impl<'a> Book<'a> {
                 width: 1,
                 height: 1,
             })
-            .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?)
+            .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
+        self.record_cleared(vec![(address, old)]);
+        Ok(())
     }
 
     pub fn clear_cell_range_all(&mut self, start: Address, end: Address) -> Result<()> {
+        self.ensure_range_unlocked(&start, &end)?;
+        let cleared = self.get_range_contents(&start, &end)?;
         let area = calculate_area(start.sheet, &start, &end);
         self.model
             .range_clear_all(&area)
             .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
+        self.record_cleared(cleared);
         self.dirty = true;
         Ok(())
     }
 
+    /// The contents of every cell with data in an inclusive range.
+    fn get_range_contents(&self, start: &Address, end: &Address) -> Result<Vec<(Address, String)>> {
+        let worksheet = self
+            .model
+            .get_model()
+            .workbook
+            .worksheet(start.sheet)
+            .map_err(|e| anyhow!(e))?;
+        let mut contents = Vec::new();
+        for (r, cols) in worksheet.sheet_data.iter() {
+            for (c, _) in cols.iter() {
+                let address = Address {
+                    sheet: start.sheet,
+                    row: *r as usize,
+                    col: *c as usize,
+                };
+                if range_contains(start, end, &address) {
+                    let value = self.get_cell_addr_contents(&address)?;
+                    contents.push((address, value));
+                }
+            }
+        }
+        Ok(contents)
+    }
+
+    /// Record clearing cells as one undo step. Only contents are restored by
+    /// an undo, not styles.
+    fn record_cleared(&mut self, cleared: Vec<(Address, String)>) {
+        self.begin_edit();
+        for (address, old) in cleared {
+            self.history.record(CellChange {
+                address,
+                old,
+                new: String::new(),
+            });
+        }
+        self.end_edit();
+    }
+
     /// Get a cells formatted content.
     pub fn get_current_cell_rendered(&self) -> Result<String> {
         Ok(self.get_cell_addr_rendered(&self.location)?)
@@ -391,6 +1012,54 @@ impl<'a> Book<'a> {
         }
     }
 
+    /// Returns true if the cell's text should be wrapped to the column width.
+    pub fn is_cell_wrapped(&self, cell: &Address) -> bool {
+        self.get_cell_style(cell)
+            .and_then(|s| s.alignment)
+            .map(|a| a.wrap_text)
+            .unwrap_or(false)
+    }
+
+    /// The horizontal alignment of a cell if it has one.
+    pub fn get_cell_alignment(&self, cell: &Address) -> Option<HorizontalAlignment> {
+        self.get_cell_style(cell)
+            .and_then(|s| s.alignment)
+            .map(|a| a.horizontal)
+    }
+
+    /// Apply a number format to an area.
+    pub fn set_cell_format(&mut self, format: CellFormat, area: &Area) -> Result<()> {
+        self.set_cell_style(
+            &[
+                ("num_fmt", &format.num_fmt()),
+                ("alignment.horizontal", format.alignment()),
+            ],
+            area,
+        )
+    }
+
+    /// Apply a number format to a column.
+    pub fn set_col_format(&mut self, format: CellFormat, sheet: u32, col_idx: usize) -> Result<()> {
+        let area = self.get_col_range(sheet, col_idx);
+        self.set_cell_format(format, &area)
+    }
+
+    /// Align the text in an area. This leaves any number format alone.
+    pub fn set_cell_alignment(&mut self, alignment: CellAlignment, area: &Area) -> Result<()> {
+        self.set_cell_style(&[("alignment.horizontal", alignment.horizontal())], area)
+    }
+
+    /// Align the text in a column.
+    pub fn set_col_alignment(
+        &mut self,
+        alignment: CellAlignment,
+        sheet: u32,
+        col_idx: usize,
+    ) -> Result<()> {
+        let area = self.get_col_range(sheet, col_idx);
+        self.set_cell_alignment(alignment, &area)
+    }
+
     /// Set the cell style
     /// Valid style paths are:
     /// * fill.bg_color background color
@@ -492,6 +1161,61 @@ impl<'a> Book<'a> {
             .map_err(|s| anyhow!("Unable to format cell {}", s))?)
     }
 
+    /// Get a cells evaluated value as a number if it is numeric.
+    pub fn get_cell_addr_value_number(
+        &self,
+        Address { sheet, row, col }: &Address,
+    ) -> Result<Option<f64>> {
+        Ok(
+            match self
+                .model
+                .get_model()
+                .get_cell_value_by_index(*sheet, *row as i32, *col as i32)
+                .map_err(|s| anyhow!("Unable to get cell value {}", s))?
+            {
+                CellValue::Number(n) => Some(n),
+                _ => None,
+            },
+        )
+    }
+
+    /// Compute the sum and count of the numeric cells in a range.
+    /// Text and empty cells are skipped.
+    pub fn get_range_stats(&self, range: &AddressRange) -> Result<RangeStats> {
+        let sheet = range.start.sheet;
+        let rows =
+            std::cmp::min(range.start.row, range.end.row)..=max(range.start.row, range.end.row);
+        let cols =
+            std::cmp::min(range.start.col, range.end.col)..=max(range.start.col, range.end.col);
+        // NOTE(zaphar): This runs on every render and a selection can cover
+        // whole columns so we only visit the cells that have data. They are
+        // summed in order so the total doesn't change between renders.
+        let worksheet = self
+            .model
+            .get_model()
+            .workbook
+            .worksheet(sheet)
+            .map_err(|e| anyhow!(e))?;
+        let mut populated = Vec::new();
+        for (r, columns) in worksheet.sheet_data.iter() {
+            for c in columns.keys() {
+                let (row, col) = (*r as usize, *c as usize);
+                if rows.contains(&row) && cols.contains(&col) {
+                    populated.push((row, col));
+                }
+            }
+        }
+        populated.sort_unstable();
+        let mut stats = RangeStats::default();
+        for (row, col) in populated {
+            if let Some(n) = self.get_cell_addr_value_number(&Address { sheet, row, col })? {
+                stats.sum += n;
+                stats.count += 1;
+            }
+        }
+        Ok(stats)
+    }
+
     /// Get a cells actual content unformatted as a string.
     pub fn get_cell_addr_contents(&self, Address { sheet, row, col }: &Address) -> Result<String> {
         Ok(self
@@ -523,24 +1247,292 @@ impl<'a> Book<'a> {
     /// Update an entry in the current sheet for a book.
     /// This update won't be reflected until you call `Book::evaluate`.
     pub fn update_cell<S: AsRef<str>>(&mut self, location: &Address, value: S) -> Result<()> {
+        self.ensure_unlocked(location)?;
+        let old = self.get_cell_addr_contents(location)?;
+        self.set_cell_input(location, value.as_ref())?;
+        self.history.record(CellChange {
+            address: location.clone(),
+            old,
+            new: value.as_ref().to_owned(),
+        });
+        Ok(())
+    }
+
+    /// Set a cell's contents without recording it in the undo history.
+    fn set_cell_input(&mut self, location: &Address, value: &str) -> Result<()> {
         self.model
             .set_user_input(
                 location.sheet,
                 location.row as i32,
                 location.col as i32,
                 // TODO(jwall): This could probably be made more efficient
-                value.as_ref(),
+                value,
             )
             .map_err(|e| anyhow!("Invalid cell contents: {}", e))?;
         self.dirty = true;
         Ok(())
     }
 
+    /// Group every cell change made until `end_edit` into one undo step.
+    pub fn begin_edit(&mut self) {
+        self.history.begin_group();
+    }
+
+    /// Finish the undo step started by `begin_edit`.
+    pub fn end_edit(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Undo the most recent edit. Returns false if there was nothing to undo.
+    /// An edit touching a cell that has since been locked is refused and left
+    /// to undo later.
+    pub fn undo(&mut self) -> Result<bool> {
+        let Some(changes) = self.history.undo() else {
+            return Ok(false);
+        };
+        if let Err(e) = self.ensure_changes_unlocked(&changes) {
+            self.history.redo();
+            return Err(e);
+        }
+        for change in changes.iter().rev() {
+            self.restore_cell(&change.address, &change.old)?;
+        }
+        self.evaluate();
+        Ok(true)
+    }
+
+    /// Redo the most recently undone edit. Returns false if there was nothing
+    /// to redo.
+    pub fn redo(&mut self) -> Result<bool> {
+        let Some(changes) = self.history.redo() else {
+            return Ok(false);
+        };
+        if let Err(e) = self.ensure_changes_unlocked(&changes) {
+            self.history.undo();
+            return Err(e);
+        }
+        for change in changes.iter() {
+            self.restore_cell(&change.address, &change.new)?;
+        }
+        self.evaluate();
+        Ok(true)
+    }
+
+    /// Drop the undo history. Inserting or deleting rows and columns or
+    /// deleting a sheet shifts the addresses the history refers to so it can't
+    /// be undone past.
+    fn forget_history(&mut self) {
+        self.history = History::default();
+    }
+
+    fn ensure_changes_unlocked(&self, changes: &[CellChange]) -> Result<()> {
+        changes
+            .iter()
+            .try_for_each(|change| self.ensure_unlocked(&change.address))
+    }
+
+    /// Put back a cell's contents from the undo history. Empty contents clear
+    /// the cell rather than leaving an empty string in it.
+    fn restore_cell(&mut self, location: &Address, value: &str) -> Result<()> {
+        self.dirty = true;
+        if !value.is_empty() {
+            return self.set_cell_input(location, value);
+        }
+        self.model
+            .range_clear_contents(&Area {
+                sheet: location.sheet,
+                row: location.row as i32,
+                column: location.col as i32,
+                width: 1,
+                height: 1,
+            })
+            .map_err(|s| anyhow!("Unable to clear cell contents {}", s))
+    }
+
+    /// Returns true if the cell is locked against edits.
+    pub fn is_locked(&self, addr: &Address) -> bool {
+        self.meta
+            .locked
+            .iter()
+            .any(|(start, end)| range_contains(start, end, addr))
+    }
+
+    fn ensure_unlocked(&self, addr: &Address) -> Result<()> {
+        if self.is_locked(addr) {
+            return Err(anyhow!("Cell {} is locked", addr.to_range_part()));
+        }
+        Ok(())
+    }
+
+    /// Returns true if any cell in the inclusive range is locked.
+    pub fn is_range_locked(&self, start: &Address, end: &Address) -> bool {
+        self.meta
+            .locked
+            .iter()
+            .any(|(lstart, lend)| ranges_overlap((start, end), (lstart, lend)))
+    }
+
+    fn ensure_range_unlocked(&self, start: &Address, end: &Address) -> Result<()> {
+        if self.is_range_locked(start, end) {
+            return Err(anyhow!(
+                "Range {}:{} contains locked cells",
+                start.to_range_part(),
+                end.to_range_part()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lock the cells in an inclusive range against edits.
+    pub fn lock_range(&mut self, start: &Address, end: &Address) {
+        self.meta.locked.push((start.clone(), end.clone()));
+        self.dirty = true;
+    }
+
+    /// Unlock the cells in an inclusive range. Locked ranges that overlap the
+    /// range are removed entirely.
+    pub fn unlock_range(&mut self, start: &Address, end: &Address) {
+        self.meta
+            .locked
+            .retain(|(lstart, lend)| !ranges_overlap((start, end), (lstart, lend)));
+        self.dirty = true;
+    }
+
+    /// Hide `count` rows starting at `row_idx` in the current sheet. Hidden rows
+    /// keep their data and still participate in formulas.
+    pub fn hide_rows(&mut self, row_idx: usize, count: usize) {
+        let sheet = self.location.sheet;
+        self.meta
+            .hidden_rows
+            .extend((row_idx..row_idx + count).map(|r| (sheet, r)));
+        self.dirty = true;
+    }
+
+    /// Hide `count` columns starting at `col_idx` in the current sheet. Hidden
+    /// columns keep their data and still participate in formulas.
+    pub fn hide_columns(&mut self, col_idx: usize, count: usize) {
+        let sheet = self.location.sheet;
+        self.meta
+            .hidden_cols
+            .extend((col_idx..col_idx + count).map(|c| (sheet, c)));
+        self.dirty = true;
+    }
+
+    /// Show all the hidden rows in the current sheet.
+    pub fn unhide_rows(&mut self) {
+        let sheet = self.location.sheet;
+        self.meta.hidden_rows.retain(|(s, _)| *s != sheet);
+        self.dirty = true;
+    }
+
+    /// Show all the hidden columns in the current sheet.
+    pub fn unhide_columns(&mut self) {
+        let sheet = self.location.sheet;
+        self.meta.hidden_cols.retain(|(s, _)| *s != sheet);
+        self.dirty = true;
+    }
+
+    /// Freeze the top `rows` rows and left `cols` columns of the current sheet
+    /// so they stay in view while the rest of the sheet scrolls. Freezing
+    /// zero rows and columns unfreezes the sheet.
+    pub fn freeze(&mut self, rows: usize, cols: usize) {
+        let sheet = self.location.sheet;
+        if rows == 0 && cols == 0 {
+            self.meta.frozen.remove(&sheet);
+        } else {
+            self.meta.frozen.insert(sheet, (rows, cols));
+        }
+        self.dirty = true;
+    }
+
+    /// The number of (rows, columns) frozen in a sheet.
+    pub fn get_frozen(&self, sheet: u32) -> (usize, usize) {
+        self.meta.frozen.get(&sheet).copied().unwrap_or_default()
+    }
+
+    pub fn is_row_hidden(&self, sheet: u32, row: usize) -> bool {
+        self.meta.hidden_rows.contains(&(sheet, row))
+    }
+
+    pub fn is_col_hidden(&self, sheet: u32, col: usize) -> bool {
+        self.meta.hidden_cols.contains(&(sheet, col))
+    }
+
+    /// The first row after `row` that isn't hidden.
+    pub fn next_visible_row(&self, sheet: u32, row: usize) -> Option<usize> {
+        ((row + 1)..=(LAST_ROW as usize)).find(|r| !self.is_row_hidden(sheet, *r))
+    }
+
+    /// The first row before `row` that isn't hidden.
+    pub fn prev_visible_row(&self, sheet: u32, row: usize) -> Option<usize> {
+        (1..row).rev().find(|r| !self.is_row_hidden(sheet, *r))
+    }
+
+    /// The first column after `col` that isn't hidden.
+    pub fn next_visible_col(&self, sheet: u32, col: usize) -> Option<usize> {
+        ((col + 1)..=(LAST_COLUMN as usize)).find(|c| !self.is_col_hidden(sheet, *c))
+    }
+
+    /// The first column before `col` that isn't hidden.
+    pub fn prev_visible_col(&self, sheet: u32, col: usize) -> Option<usize> {
+        (1..col).rev().find(|c| !self.is_col_hidden(sheet, *c))
+    }
+
+    /// Get the note attached to a cell if there is one.
+    pub fn get_note(&self, addr: &Address) -> Option<&str> {
+        self.meta.notes.get(addr).map(|s| s.as_str())
+    }
+
+    /// Attach a note to a cell replacing any note that was already there.
+    pub fn set_note<S: Into<String>>(&mut self, addr: &Address, note: S) {
+        self.meta.notes.insert(addr.clone(), note.into());
+        self.dirty = true;
+    }
+
+    /// Remove the note attached to a cell returning it if it existed.
+    pub fn remove_note(&mut self, addr: &Address) -> Option<String> {
+        let removed = self.meta.notes.remove(addr);
+        if removed.is_some() {
+            self.dirty = true;
+        }
+        removed
+    }
+
+    /// Add a conditional formatting rule. Later rules take precedence over
+    /// earlier ones.
+    pub fn add_conditional_format(&mut self, format: ConditionalFormat) {
+        self.meta.conditional_formats.push(format);
+        self.dirty = true;
+    }
+
+    /// Remove all the conditional formatting rules that apply to a cell.
+    pub fn clear_conditional_formats(&mut self, addr: &Address) {
+        self.meta.conditional_formats.retain(|f| !f.contains(addr));
+        self.dirty = true;
+    }
+
+    /// Get the conditional format that applies to a cell's current value if any.
+    pub fn get_conditional_format(&self, addr: &Address) -> Option<&ConditionalFormat> {
+        let mut candidates = self
+            .meta
+            .conditional_formats
+            .iter()
+            .rev()
+            .filter(|f| f.contains(addr))
+            .peekable();
+        // Avoid the cost of looking up the value for the common case.
+        candidates.peek()?;
+        let value = self.get_cell_addr_value_number(addr).ok()??;
+        candidates.find(|f| f.condition.matches(value))
+    }
+
     /// Insert `count` rows at a `row_idx`.
     pub fn insert_rows(&mut self, row_idx: usize, count: usize) -> Result<()> {
         self.model
             .insert_rows(self.location.sheet, row_idx as i32, count as i32)
             .map_err(|e| anyhow!("Unable to insert row(s): {}", e))?;
+        self.forget_history();
+        self.meta.insert_rows(self.location.sheet, row_idx, count);
 
         if self.location.row >= row_idx {
             self.move_to(&Address {
@@ -558,6 +1550,9 @@ impl<'a> Book<'a> {
         self.model
             .insert_columns(self.location.sheet, col_idx as i32, count as i32)
             .map_err(|e| anyhow!("Unable to insert column(s): {}", e))?;
+        self.forget_history();
+        self.meta
+            .insert_columns(self.location.sheet, col_idx, count);
 
         if self.location.col >= col_idx {
             self.move_to(&Address {
@@ -570,6 +1565,73 @@ impl<'a> Book<'a> {
         Ok(())
     }
 
+    /// Delete `count` rows starting at `row_idx`.
+    /// Formula references into the deleted rows become `#REF!` errors.
+    /// Rows with locked cells can't be deleted.
+    pub fn delete_rows(&mut self, row_idx: usize, count: usize) -> Result<()> {
+        let sheet = self.location.sheet;
+        self.ensure_range_unlocked(
+            &Address {
+                sheet,
+                row: row_idx,
+                col: 1,
+            },
+            &Address {
+                sheet,
+                row: row_idx + count.saturating_sub(1),
+                col: LAST_COLUMN as usize,
+            },
+        )?;
+        self.model
+            .delete_rows(self.location.sheet, row_idx as i32, count as i32)
+            .map_err(|e| anyhow!("Unable to delete row(s): {}", e))?;
+        self.forget_history();
+        self.meta.delete_rows(self.location.sheet, row_idx, count);
+        if self.location.row >= row_idx + count {
+            self.move_to(&Address {
+                sheet: self.location.sheet,
+                row: self.location.row - count,
+                col: self.location.col,
+            })?;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Delete `count` columns starting at `col_idx`.
+    /// Formula references into the deleted columns become `#REF!` errors.
+    /// Columns with locked cells can't be deleted.
+    pub fn delete_columns(&mut self, col_idx: usize, count: usize) -> Result<()> {
+        let sheet = self.location.sheet;
+        self.ensure_range_unlocked(
+            &Address {
+                sheet,
+                row: 1,
+                col: col_idx,
+            },
+            &Address {
+                sheet,
+                row: LAST_ROW as usize,
+                col: col_idx + count.saturating_sub(1),
+            },
+        )?;
+        self.model
+            .delete_columns(self.location.sheet, col_idx as i32, count as i32)
+            .map_err(|e| anyhow!("Unable to delete column(s): {}", e))?;
+        self.forget_history();
+        self.meta
+            .delete_columns(self.location.sheet, col_idx, count);
+        if self.location.col >= col_idx + count {
+            self.move_to(&Address {
+                sheet: self.location.sheet,
+                row: self.location.row,
+                col: self.location.col - count,
+            })?;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
     /// Get the current sheets dimensions. This is a somewhat expensive calculation.
     pub fn get_dimensions(&self) -> Result<WorksheetDimension> {
         Ok(self.get_sheet()?.dimension())
@@ -592,6 +1654,31 @@ impl<'a> Book<'a> {
             / COL_PIXELS) as usize)
     }
 
+    /// Size a column in the current sheet to its widest displayed value capped
+    /// at `AUTO_FIT_MAX_WIDTH`. Wide characters count as two columns. Empty
+    /// columns are left alone. Returns the new width if it changed.
+    pub fn auto_fit_column(&mut self, col: usize) -> Result<Option<usize>> {
+        let sheet = self.location.sheet;
+        let mut widest = 0;
+        for (row, cols) in self.get_sheet()?.sheet_data.iter() {
+            if !cols.contains_key(&(col as i32)) {
+                continue;
+            }
+            let content = self.get_cell_addr_rendered(&Address {
+                sheet,
+                row: *row as usize,
+                col,
+            })?;
+            widest = max(widest, display_width(&content));
+        }
+        if widest == 0 {
+            return Ok(None);
+        }
+        let width = std::cmp::min(widest, AUTO_FIT_MAX_WIDTH);
+        self.set_column_size_for_sheet(sheet, col, width)?;
+        Ok(Some(width))
+    }
+
     pub fn set_col_size(&mut self, col: usize, width: usize) -> Result<()> {
         self.set_column_size_for_sheet(self.location.sheet, col, width)
     }
@@ -670,6 +1757,19 @@ impl<'a> Book<'a> {
         self.location.sheet = next;
     }
 
+    /// Select a sheet by its index. Returns false if there is no sheet at that index.
+    pub fn select_sheet_by_idx(&mut self, idx: usize) -> bool {
+        let len = self.model.get_model().workbook.worksheets.len();
+        if idx >= len {
+            return false;
+        }
+        self.model
+            .set_selected_sheet(idx as u32)
+            .expect("Unexpected error selecting sheet");
+        self.location.sheet = idx as u32;
+        true
+    }
+
     /// Select a sheet by id.
     pub fn select_sheet_by_id(&mut self, id: u32) -> bool {
         if let Some((idx, _sheet)) = self
@@ -728,7 +1828,9 @@ pub fn rows_to_clipboard_content(
 ) -> std::result::Result<(String, String), anyhow::Error> {
     use htmf::prelude::*;
     let table = table([]);
-    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(vec![]);
     let mut table_rows = vec![];
     for row in rows {
         let table_row = tr([]);
@@ -740,13 +1842,187 @@ pub fn rows_to_clipboard_content(
         table_rows.push(table_row.with(row_cells));
     }
 
-    let csv_content = writer.into_inner().expect("Failed to get the csv content");
+    let tsv_content = writer.into_inner().expect("Failed to get the tsv content");
     Ok((
         table.with(table_rows).to_html(),
-        String::from_utf8_lossy(&csv_content).to_string(),
+        String::from_utf8_lossy(&tsv_content).to_string(),
     ))
 }
 
+/// Write rows as delimited text. Fields containing the delimiter, quotes, or
+/// newlines are quoted per RFC 4180.
+fn write_delimited<W: std::io::Write>(rows: &[Vec<String>], delimiter: u8, sink: W) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_writer(sink);
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Drop empty rows from the bottom and empty columns from the right so exports
+/// aren't padded with blanks.
+fn trim_blank_edges(mut rows: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    while rows
+        .last()
+        .is_some_and(|row| row.iter().all(String::is_empty))
+    {
+        rows.pop();
+    }
+    let width = rows
+        .iter()
+        .filter_map(|row| row.iter().rposition(|cell| !cell.is_empty()))
+        .max()
+        .map_or(0, |last| last + 1);
+    for row in rows.iter_mut() {
+        row.truncate(width);
+    }
+    rows
+}
+
+/// The number of terminal columns the widest line of some text takes up.
+pub fn display_width(text: &str) -> usize {
+    text.lines().map(UnicodeWidthStr::width).max().unwrap_or(0)
+}
+
+/// Read delimited text into rows of fields. Quoted fields may contain the
+/// delimiter and newlines. A row with a different number of fields than the
+/// first row is an error.
+pub fn read_delimited_rows(contents: &str, delimiter: u8) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if let Some(expected) = rows.first().map(Vec::len) {
+            if record.len() != expected {
+                let line = record
+                    .position()
+                    .map(|p| p.line())
+                    .unwrap_or(rows.len() as u64 + 1);
+                return Err(anyhow!(
+                    "Malformed row on line {}: expected {} fields but found {}",
+                    line,
+                    expected,
+                    record.len()
+                ));
+            }
+        }
+        rows.push(record.iter().map(str::to_owned).collect());
+    }
+    Ok(rows)
+}
+
+/// Guess whether the first row of delimited text is a header. It is when none
+/// of its fields are empty or numeric but a field in a later row is numeric.
+fn looks_like_header(rows: &[Vec<String>]) -> bool {
+    let is_number = |field: &String| field.trim().parse::<f64>().is_ok();
+    match rows.split_first() {
+        Some((first, rest)) => {
+            !first.is_empty()
+                && first.iter().all(|f| !f.trim().is_empty() && !is_number(f))
+                && rest.iter().flatten().any(is_number)
+        }
+        None => false,
+    }
+}
+
+/// The delimiters we know how to detect in a delimited text file.
+const CANDIDATE_DELIMITERS: [u8; 3] = [b',', b'\t', b';'];
+/// The number of lines sampled when detecting a delimiter.
+const DELIMITER_SAMPLE_LINES: usize = 5;
+
+/// Detect the delimiter of delimited text content by sampling the first few lines.
+///
+/// A candidate that appears the same number of times on every sampled line wins
+/// over one that doesn't. Ties are broken by the number of occurrences. Delimiters
+/// inside of quoted fields are not counted. Defaults to a comma.
+pub fn detect_delimiter(contents: &str) -> u8 {
+    let sample: Vec<&str> = contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .take(DELIMITER_SAMPLE_LINES)
+        .collect();
+    let mut best = (b',', false, 0);
+    for candidate in CANDIDATE_DELIMITERS {
+        let counts: Vec<usize> = sample
+            .iter()
+            .map(|line| count_unquoted(line, candidate))
+            .collect();
+        let total: usize = counts.iter().sum();
+        if total == 0 {
+            continue;
+        }
+        let consistent = counts.windows(2).all(|w| w[0] == w[1]);
+        if (consistent, total) > (best.1, best.2) {
+            best = (candidate, consistent, total);
+        }
+    }
+    best.0
+}
+
+fn count_unquoted(line: &str, delimiter: u8) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for b in line.bytes() {
+        if b == b'"' {
+            in_quotes = !in_quotes;
+        } else if b == delimiter && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Parse delimited text from the clipboard into rows of cells. The delimiter is
+/// detected so both TSV and CSV can be pasted.
+pub fn clipboard_text_to_rows(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(detect_delimiter(text))
+        .from_reader(text.as_bytes());
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        rows.push(record?.iter().map(str::to_owned).collect());
+    }
+    Ok(rows)
+}
+
+/// Render rows as a GFM markdown table. The first row is used as the header.
+pub fn rows_to_markdown_table(rows: &Vec<Vec<String>>) -> String {
+    let escape = |cell: &String| cell.replace('|', "\\|").replace('\n', " ");
+    let mut out = String::new();
+    for (idx, row) in rows.iter().enumerate() {
+        out.push_str("| ");
+        out.push_str(&row.iter().map(escape).collect::<Vec<String>>().join(" | "));
+        out.push_str(" |\n");
+        if idx == 0 {
+            out.push('|');
+            out.push_str(&" --- |".repeat(row.len()));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Returns true if rendered cell content is a formula error value.
+// NOTE(zaphar): ironcalc doesn't tell us the type of a rendered value so a text
+// cell containing exactly an error string is styled as an error too.
+pub fn is_error_value(rendered: &str) -> bool {
+    ERROR_VALUES.contains(&rendered)
+}
+
+fn sum_formula(start: &Address, end: &Address) -> String {
+    format!("=SUM({}:{})", start.to_range_part(), end.to_range_part())
+}
+
 fn calculate_area(sheet: u32, start: &Address, end: &Address) -> Area {
     let area = Area {
         sheet,
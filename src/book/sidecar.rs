@@ -0,0 +1,412 @@
+//! Sidecar metadata stored next to a workbook.
+//!
+//! Some of our state has no home in the xlsx format as ironcalc exposes it. We
+//! store that state in a small json file alongside the workbook instead.
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde_json::{Map, Value, json};
+
+use super::conditional::ConditionalFormat;
+use crate::ui::Address;
+
+/// Metadata for a workbook that lives outside of the workbook file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Sidecar {
+    pub notes: BTreeMap<Address, String>,
+    pub conditional_formats: Vec<ConditionalFormat>,
+    /// Inclusive (start, end) ranges of cells that can't be edited.
+    pub locked: Vec<(Address, Address)>,
+    /// Hidden (sheet, row) pairs.
+    pub hidden_rows: BTreeSet<(u32, usize)>,
+    /// Hidden (sheet, column) pairs.
+    pub hidden_cols: BTreeSet<(u32, usize)>,
+    /// The number of (rows, columns) frozen at the top left of each sheet.
+    pub frozen: BTreeMap<u32, (usize, usize)>,
+    /// The cursor location when the workbook was last saved.
+    pub location: Option<Address>,
+    /// The top left corner of the viewport when the workbook was last saved.
+    pub viewport_corner: Option<Address>,
+}
+
+impl Sidecar {
+    /// The path of the sidecar file for a workbook path.
+    /// `some/dir/book.xlsx` has a sidecar at `some/dir/.book.xlsx.sheetui.json`.
+    pub fn path_for(workbook: &Path) -> PathBuf {
+        let file_name = workbook
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        workbook.with_file_name(format!(".{}.sheetui.json", file_name))
+    }
+
+    /// Load the sidecar for a workbook. A missing or corrupt sidecar is not an
+    /// error, we just start with empty metadata.
+    pub fn load(workbook: &Path) -> Self {
+        std::fs::read_to_string(Self::path_for(workbook))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Value>(&contents).ok())
+            .map(|value| Self::from_json(&value))
+            .unwrap_or_default()
+    }
+
+    /// Returns true if there is metadata besides the cursor and viewport
+    /// position.
+    pub fn has_metadata(&self) -> bool {
+        let positionless = Self {
+            location: None,
+            viewport_corner: None,
+            ..self.clone()
+        };
+        positionless != Self::default()
+    }
+
+    /// Save the sidecar for a workbook.
+    pub fn save(&self, workbook: &Path) -> Result<()> {
+        let file = std::fs::File::create(Self::path_for(workbook))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &self.to_json())?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let notes: Vec<Value> = self
+            .notes
+            .iter()
+            .map(|(addr, text)| {
+                let mut note = address_to_json(addr);
+                note.insert("text".to_owned(), json!(text));
+                Value::Object(note)
+            })
+            .collect();
+        let conditional_formats: Vec<Value> = self
+            .conditional_formats
+            .iter()
+            .map(ConditionalFormat::to_json)
+            .collect();
+        let locked: Vec<Value> = self
+            .locked
+            .iter()
+            .map(|(start, end)| {
+                json!({
+                    "start": Value::Object(address_to_json(start)),
+                    "end": Value::Object(address_to_json(end)),
+                })
+            })
+            .collect();
+        let hidden_rows: Vec<Value> = self
+            .hidden_rows
+            .iter()
+            .map(|(sheet, row)| json!({ "sheet": sheet, "row": row }))
+            .collect();
+        let hidden_cols: Vec<Value> = self
+            .hidden_cols
+            .iter()
+            .map(|(sheet, col)| json!({ "sheet": sheet, "col": col }))
+            .collect();
+        let frozen: Vec<Value> = self
+            .frozen
+            .iter()
+            .map(|(sheet, (rows, cols))| json!({ "sheet": sheet, "rows": rows, "cols": cols }))
+            .collect();
+        json!({
+            "notes": notes,
+            "conditional_formats": conditional_formats,
+            "locked": locked,
+            "hidden_rows": hidden_rows,
+            "hidden_cols": hidden_cols,
+            "frozen": frozen,
+            "location": self.location.as_ref().map(|a| Value::Object(address_to_json(a))),
+            "viewport_corner": self
+                .viewport_corner
+                .as_ref()
+                .map(|a| Value::Object(address_to_json(a))),
+        })
+    }
+
+    /// Drop the metadata for a deleted sheet and shift the metadata for the
+    /// sheets after it down by one.
+    pub fn remove_sheet(&mut self, sheet: u32) {
+        let shift = |addr: &Address| -> Option<Address> {
+            match addr.sheet.cmp(&sheet) {
+                Ordering::Less => Some(addr.clone()),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(Address {
+                    sheet: addr.sheet - 1,
+                    ..addr.clone()
+                }),
+            }
+        };
+        let shift_idx = |idx: u32| -> Option<u32> {
+            match idx.cmp(&sheet) {
+                Ordering::Less => Some(idx),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(idx - 1),
+            }
+        };
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .filter_map(|(addr, text)| Some((shift(&addr)?, text)))
+            .collect();
+        self.conditional_formats = std::mem::take(&mut self.conditional_formats)
+            .into_iter()
+            .filter_map(|format| {
+                Some(ConditionalFormat {
+                    start: shift(&format.start)?,
+                    end: shift(&format.end)?,
+                    ..format
+                })
+            })
+            .collect();
+        self.locked = std::mem::take(&mut self.locked)
+            .into_iter()
+            .filter_map(|(start, end)| Some((shift(&start)?, shift(&end)?)))
+            .collect();
+        self.hidden_rows = std::mem::take(&mut self.hidden_rows)
+            .into_iter()
+            .filter_map(|(s, row)| Some((shift_idx(s)?, row)))
+            .collect();
+        self.hidden_cols = std::mem::take(&mut self.hidden_cols)
+            .into_iter()
+            .filter_map(|(s, col)| Some((shift_idx(s)?, col)))
+            .collect();
+        self.frozen = std::mem::take(&mut self.frozen)
+            .into_iter()
+            .filter_map(|(s, counts)| Some((shift_idx(s)?, counts)))
+            .collect();
+        self.location = self.location.as_ref().and_then(shift);
+        self.viewport_corner = self.viewport_corner.as_ref().and_then(shift);
+    }
+
+    /// Shift the metadata at or below `row` down after inserting `count` rows.
+    pub fn insert_rows(&mut self, sheet: u32, row: usize, count: usize) {
+        self.shift_lines(sheet, Axis::Row, row, count, false);
+    }
+
+    /// Drop the metadata inside `count` deleted rows starting at `row` and
+    /// shift the metadata below them up.
+    pub fn delete_rows(&mut self, sheet: u32, row: usize, count: usize) {
+        self.shift_lines(sheet, Axis::Row, row, count, true);
+    }
+
+    /// Shift the metadata at or right of `col` over after inserting `count`
+    /// columns.
+    pub fn insert_columns(&mut self, sheet: u32, col: usize, count: usize) {
+        self.shift_lines(sheet, Axis::Col, col, count, false);
+    }
+
+    /// Drop the metadata inside `count` deleted columns starting at `col` and
+    /// shift the metadata right of them over.
+    pub fn delete_columns(&mut self, sheet: u32, col: usize, count: usize) {
+        self.shift_lines(sheet, Axis::Col, col, count, true);
+    }
+
+    fn shift_lines(&mut self, sheet: u32, axis: Axis, at: usize, count: usize, delete: bool) {
+        let shift = |idx: usize| -> Option<usize> {
+            if idx < at {
+                Some(idx)
+            } else if !delete {
+                Some(idx + count)
+            } else if idx >= at + count {
+                Some(idx - count)
+            } else {
+                None
+            }
+        };
+        // NOTE(zaphar): A range that starts in a deleted span starts after it
+        // and one that ends in a deleted span ends before it. Ranges entirely
+        // inside the span go away.
+        let shift_span = |start: usize, end: usize| -> Option<(usize, usize)> {
+            if !delete {
+                return Some((shift(start)?, shift(end)?));
+            }
+            let start = shift(start).unwrap_or(at);
+            let end = match shift(end) {
+                Some(end) => end,
+                None => at.checked_sub(1)?,
+            };
+            (start <= end).then_some((start, end))
+        };
+        let line = |addr: &Address| match axis {
+            Axis::Row => addr.row,
+            Axis::Col => addr.col,
+        };
+        let with_line = |addr: &Address, idx: usize| match axis {
+            Axis::Row => Address {
+                row: idx,
+                ..addr.clone()
+            },
+            Axis::Col => Address {
+                col: idx,
+                ..addr.clone()
+            },
+        };
+        let span = |start: &Address, end: &Address| -> Option<(Address, Address)> {
+            if start.sheet != sheet {
+                return Some((start.clone(), end.clone()));
+            }
+            let (s, e) = shift_span(line(start), line(end))?;
+            Some((with_line(start, s), with_line(end, e)))
+        };
+        self.notes = std::mem::take(&mut self.notes)
+            .into_iter()
+            .filter_map(|(addr, text)| {
+                if addr.sheet != sheet {
+                    return Some((addr, text));
+                }
+                Some((with_line(&addr, shift(line(&addr))?), text))
+            })
+            .collect();
+        self.conditional_formats = std::mem::take(&mut self.conditional_formats)
+            .into_iter()
+            .filter_map(|format| {
+                let (start, end) = span(&format.start, &format.end)?;
+                Some(ConditionalFormat {
+                    start,
+                    end,
+                    ..format
+                })
+            })
+            .collect();
+        self.locked = std::mem::take(&mut self.locked)
+            .into_iter()
+            .filter_map(|(start, end)| span(&start, &end))
+            .collect();
+        let hidden = match axis {
+            Axis::Row => &mut self.hidden_rows,
+            Axis::Col => &mut self.hidden_cols,
+        };
+        *hidden = std::mem::take(hidden)
+            .into_iter()
+            .filter_map(|(s, idx)| {
+                if s != sheet {
+                    return Some((s, idx));
+                }
+                Some((s, shift(idx)?))
+            })
+            .collect();
+        if let Some((rows, cols)) = self.frozen.get_mut(&sheet) {
+            let frozen = match axis {
+                Axis::Row => rows,
+                Axis::Col => cols,
+            };
+            // The frozen lines are the span from the first line to the count.
+            if *frozen > 0 {
+                *frozen = shift_span(1, *frozen).map(|(_, end)| end).unwrap_or(0);
+            }
+        }
+    }
+
+    /// Construct a sidecar from json. Entries that don't have the expected
+    /// shape are skipped.
+    pub fn from_json(value: &Value) -> Self {
+        let mut sidecar = Self::default();
+        if let Some(notes) = value.get("notes").and_then(Value::as_array) {
+            for note in notes {
+                if let (Some(addr), Some(text)) = (
+                    address_from_json(note),
+                    note.get("text").and_then(Value::as_str),
+                ) {
+                    sidecar.notes.insert(addr, text.to_owned());
+                }
+            }
+        }
+        if let Some(formats) = value.get("conditional_formats").and_then(Value::as_array) {
+            sidecar.conditional_formats = formats
+                .iter()
+                .filter_map(ConditionalFormat::from_json)
+                .collect();
+        }
+        if let Some(locked) = value.get("locked").and_then(Value::as_array) {
+            sidecar.locked = locked
+                .iter()
+                .filter_map(|range| {
+                    Some((
+                        address_from_json(range.get("start")?)?,
+                        address_from_json(range.get("end")?)?,
+                    ))
+                })
+                .collect();
+        }
+        sidecar.hidden_rows = hidden_from_json(value.get("hidden_rows"), "row");
+        sidecar.hidden_cols = hidden_from_json(value.get("hidden_cols"), "col");
+        if let Some(frozen) = value.get("frozen").and_then(Value::as_array) {
+            sidecar.frozen = frozen
+                .iter()
+                .filter_map(|entry| {
+                    Some((
+                        entry.get("sheet")?.as_u64()? as u32,
+                        (
+                            entry.get("rows")?.as_u64()? as usize,
+                            entry.get("cols")?.as_u64()? as usize,
+                        ),
+                    ))
+                })
+                .collect();
+        }
+        sidecar.location = value.get("location").and_then(address_from_json);
+        sidecar.viewport_corner = value.get("viewport_corner").and_then(address_from_json);
+        sidecar
+    }
+}
+
+/// The direction rows or columns were inserted or deleted in.
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    Row,
+    Col,
+}
+
+fn hidden_from_json(value: Option<&Value>, key: &str) -> BTreeSet<(u32, usize)> {
+    value
+        .and_then(Value::as_array)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    Some((
+                        entry.get("sheet")?.as_u64()? as u32,
+                        entry.get(key)?.as_u64()? as usize,
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns true if the address is inside of the inclusive range.
+pub(crate) fn range_contains(start: &Address, end: &Address, addr: &Address) -> bool {
+    addr.sheet == start.sheet
+        && addr.row >= start.row
+        && addr.row <= end.row
+        && addr.col >= start.col
+        && addr.col <= end.col
+}
+
+/// Returns true if two inclusive ranges share any cells.
+pub(crate) fn ranges_overlap(a: (&Address, &Address), b: (&Address, &Address)) -> bool {
+    a.0.sheet == b.0.sheet
+        && a.0.row <= b.1.row
+        && b.0.row <= a.1.row
+        && a.0.col <= b.1.col
+        && b.0.col <= a.1.col
+}
+
+pub(crate) fn address_to_json(addr: &Address) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("sheet".to_owned(), json!(addr.sheet));
+    map.insert("row".to_owned(), json!(addr.row));
+    map.insert("col".to_owned(), json!(addr.col));
+    map
+}
+
+pub(crate) fn address_from_json(value: &Value) -> Option<Address> {
+    Some(Address {
+        sheet: value.get("sheet")?.as_u64()? as u32,
+        row: value.get("row")?.as_u64()? as usize,
+        col: value.get("col")?.as_u64()? as usize,
+    })
+}
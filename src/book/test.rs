@@ -0,0 +1,949 @@
+use ironcalc::base::expressions::types::Area;
+
+use crate::ui::{Address, render::markdown::Markdown};
+
+use super::{
+    AUTO_FIT_MAX_WIDTH, AddressRange, Book, clipboard_text_to_rows, detect_delimiter,
+    display_width, format::CellFormat, history::HISTORY_LIMIT, looks_like_header,
+    read_delimited_rows, rows_to_clipboard_content, rows_to_markdown_table, sidecar::Sidecar,
+    trim_blank_edges,
+};
+
+fn load_delimited(name: &str, contents: &str) -> Book<'static> {
+    let path = std::env::temp_dir().join(format!("sheetui-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).expect("Failed to write test file");
+    let book =
+        Book::new_from_delimited_with_locale(&path.to_string_lossy(), "en", "America/New_York")
+            .expect("Failed to load delimited file");
+    let _ = std::fs::remove_file(&path);
+    book
+}
+
+fn contents(book: &Book, row: usize, col: usize) -> String {
+    book.get_cell_addr_contents(&Address::new(row, col))
+        .expect("Failed to get cell contents")
+}
+
+fn rendered(book: &Book, row: usize, col: usize) -> String {
+    book.get_cell_addr_rendered(&Address::new(row, col))
+        .expect("Failed to get rendered cell")
+}
+
+#[test]
+fn test_detect_delimiter() {
+    assert_eq!(detect_delimiter("a,b,c\n1,2,3\n"), b',');
+    assert_eq!(detect_delimiter("a\tb\tc\n1\t2\t3\n"), b'\t');
+    assert_eq!(detect_delimiter("a;b;c\n1;2;3\n"), b';');
+    // Delimiters inside of quotes don't count.
+    assert_eq!(detect_delimiter("\"a,b\";c\n\"1,2\";3\n"), b';');
+    assert_eq!(detect_delimiter("just one field\n"), b',');
+}
+
+#[test]
+fn test_load_tsv() {
+    let book = load_delimited(
+        "load.tsv",
+        "name\tcity, state\tage\nalice\tAustin, TX\t30\n",
+    );
+    assert_eq!(book.delimiter, Some(b'\t'));
+    assert_eq!(contents(&book, 1, 1), "name");
+    assert_eq!(contents(&book, 1, 2), "city, state");
+    assert_eq!(contents(&book, 1, 3), "age");
+    assert_eq!(contents(&book, 2, 1), "alice");
+    assert_eq!(contents(&book, 2, 2), "Austin, TX");
+    assert_eq!(rendered(&book, 2, 3), "30");
+    assert_eq!(contents(&book, 1, 4), "");
+}
+
+#[test]
+fn test_load_semicolon_delimited() {
+    let book = load_delimited("load.csv", "item;price\n\"nuts; bolts\";4\nwasher;1\n");
+    assert_eq!(book.delimiter, Some(b';'));
+    assert_eq!(contents(&book, 1, 1), "item");
+    assert_eq!(contents(&book, 1, 2), "price");
+    assert_eq!(contents(&book, 2, 1), "nuts; bolts");
+    assert_eq!(rendered(&book, 2, 2), "4");
+    assert_eq!(contents(&book, 3, 1), "washer");
+    assert_eq!(rendered(&book, 3, 2), "1");
+}
+
+fn set(book: &mut Book, row: usize, col: usize, value: &str) {
+    book.update_cell(&Address::new(row, col), value)
+        .expect("Failed to update cell");
+}
+
+#[test]
+fn test_insert_row_above_formula() {
+    let mut book = Book::default();
+    set(&mut book, 2, 1, "5");
+    set(&mut book, 3, 1, "=A2*2");
+    book.set_note(&Address::new(2, 1), "five");
+    book.evaluate();
+    book.insert_rows(1, 1).expect("Failed to insert row");
+    book.evaluate();
+    assert_eq!(contents(&book, 3, 1), "5");
+    assert_eq!(contents(&book, 4, 1), "=A3*2");
+    assert_eq!(rendered(&book, 4, 1), "10");
+    assert_eq!(book.get_note(&Address::new(2, 1)), None);
+    assert_eq!(book.get_note(&Address::new(3, 1)), Some("five"));
+}
+
+#[test]
+fn test_insert_column_before_formula() {
+    let mut book = Book::default();
+    set(&mut book, 1, 2, "5");
+    set(&mut book, 1, 3, "=B1*2");
+    book.evaluate();
+    book.insert_columns(1, 1).expect("Failed to insert column");
+    book.evaluate();
+    assert_eq!(contents(&book, 1, 3), "5");
+    assert_eq!(contents(&book, 1, 4), "=C1*2");
+    assert_eq!(rendered(&book, 1, 4), "10");
+}
+
+#[test]
+fn test_delete_referenced_row() {
+    let mut book = Book::default();
+    set(&mut book, 2, 1, "5");
+    set(&mut book, 3, 1, "=A2*2");
+    book.set_note(&Address::new(2, 1), "five");
+    book.evaluate();
+    book.delete_rows(2, 1).expect("Failed to delete row");
+    book.evaluate();
+    assert_eq!(rendered(&book, 2, 1), "#REF!");
+    assert_eq!(book.get_note(&Address::new(2, 1)), None);
+}
+
+#[test]
+fn test_delete_referenced_column() {
+    let mut book = Book::default();
+    set(&mut book, 1, 2, "5");
+    set(&mut book, 1, 3, "=B1*2");
+    book.evaluate();
+    book.delete_columns(2, 1).expect("Failed to delete column");
+    book.evaluate();
+    assert_eq!(rendered(&book, 1, 2), "#REF!");
+}
+
+#[test]
+fn test_locked_cell_edit_is_blocked() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    book.lock_range(&Address::new(1, 1), &Address::new(2, 1));
+    assert!(book.is_locked(&Address::new(2, 1)));
+    assert!(book.update_cell(&Address::new(1, 1), "2").is_err());
+    assert_eq!(contents(&book, 1, 1), "1");
+    // Cells outside of the locked range can still be edited.
+    set(&mut book, 1, 2, "3");
+    assert_eq!(contents(&book, 1, 2), "3");
+
+    book.unlock_range(&Address::new(1, 1), &Address::new(2, 1));
+    set(&mut book, 1, 1, "2");
+    assert_eq!(contents(&book, 1, 1), "2");
+}
+
+#[test]
+fn test_auto_sum_column() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 2, 1, "2");
+    set(&mut book, 3, 1, "3");
+    let inserted = book
+        .auto_sum(&Address::new(1, 1), &Address::new(3, 1))
+        .expect("Failed to auto sum");
+    assert_eq!(inserted, vec![Address::new(4, 1)]);
+    assert_eq!(contents(&book, 4, 1), "=SUM(A1:A3)");
+    assert_eq!(rendered(&book, 4, 1), "6");
+}
+
+#[test]
+fn test_auto_sum_row() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 2, "2");
+    set(&mut book, 1, 3, "3");
+    // The sum goes in the first empty cell to the right.
+    set(&mut book, 1, 4, "total");
+    let inserted = book
+        .auto_sum(&Address::new(1, 1), &Address::new(1, 3))
+        .expect("Failed to auto sum");
+    assert_eq!(inserted, vec![Address::new(1, 5)]);
+    assert_eq!(contents(&book, 1, 5), "=SUM(A1:C1)");
+    assert_eq!(rendered(&book, 1, 5), "6");
+}
+
+#[test]
+fn test_transpose_range() {
+    let mut book = Book::default();
+    // A 2x3 range
+    for (row, values) in [["1", "2", "3"], ["4", "5", "6"]].iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            set(&mut book, row + 1, col + 1, value);
+        }
+    }
+    book.transpose_range(&Address::new(1, 1), &Address::new(2, 3))
+        .expect("Failed to transpose range");
+    // becomes a 3x2 range.
+    for (row, values) in [["1", "4"], ["2", "5"], ["3", "6"]].iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            assert_eq!(&contents(&book, row + 1, col + 1), value);
+        }
+    }
+    assert_eq!(contents(&book, 1, 3), "");
+    assert_eq!(contents(&book, 2, 3), "");
+}
+
+#[test]
+fn test_clipboard_tsv_round_trip() {
+    let rows = vec![
+        vec!["name".to_owned(), "notes".to_owned()],
+        vec![
+            "alice".to_owned(),
+            "likes \"tabs\"\tand\nnewlines".to_owned(),
+        ],
+        vec!["bob".to_owned(), "".to_owned()],
+    ];
+    let (html, tsv) = rows_to_clipboard_content(&rows).expect("Failed to serialize rows");
+    assert!(tsv.starts_with("name\tnotes\n"));
+    assert!(html.contains("alice"));
+    assert_eq!(
+        clipboard_text_to_rows(&tsv).expect("Failed to parse tsv"),
+        rows
+    );
+}
+
+#[test]
+fn test_clipboard_csv_to_rows() {
+    assert_eq!(
+        clipboard_text_to_rows("a,b\n\"c, d\",e\n").expect("Failed to parse csv"),
+        vec![vec!["a", "b"], vec!["c, d", "e"]]
+    );
+}
+
+#[test]
+fn test_clipboard_empty_text_to_rows() {
+    assert!(
+        clipboard_text_to_rows("")
+            .expect("Failed to parse empty text")
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_undo_and_redo_edit() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 1, "2");
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "1");
+    assert!(book.redo().expect("Failed to redo"));
+    assert_eq!(contents(&book, 1, 1), "2");
+    assert!(book.undo().expect("Failed to undo"));
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "");
+    assert!(!book.undo().expect("Failed to undo"));
+}
+
+#[test]
+fn test_new_edit_clears_redo() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 1, "2");
+    assert!(book.undo().expect("Failed to undo"));
+    set(&mut book, 1, 2, "3");
+    assert!(!book.redo().expect("Failed to redo"));
+    assert_eq!(contents(&book, 1, 1), "1");
+}
+
+#[test]
+fn test_history_limit() {
+    let mut book = Book::default();
+    let edits = HISTORY_LIMIT + 5;
+    for i in 0..edits {
+        set(&mut book, 1, 1, &i.to_string());
+    }
+    for _ in 0..HISTORY_LIMIT {
+        assert!(book.undo().expect("Failed to undo"));
+    }
+    // The oldest edits were dropped.
+    assert!(!book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "4");
+}
+
+#[test]
+fn test_undo_range_clear_in_one_step() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 2, 1, "2");
+    book.clear_cell_range(Address::new(1, 1), Address::new(2, 1))
+        .expect("Failed to clear range");
+    assert_eq!(contents(&book, 1, 1), "");
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "1");
+    assert_eq!(contents(&book, 2, 1), "2");
+}
+
+#[test]
+fn test_undo_grouped_edits_in_one_step() {
+    let mut book = Book::default();
+    book.begin_edit();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 2, "2");
+    book.end_edit();
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "");
+    assert_eq!(contents(&book, 1, 2), "");
+    assert!(book.redo().expect("Failed to redo"));
+    assert_eq!(contents(&book, 1, 1), "1");
+    assert_eq!(contents(&book, 1, 2), "2");
+}
+
+#[test]
+fn test_undo_locked_cell_is_refused() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    book.lock_range(&Address::new(1, 1), &Address::new(1, 1));
+    assert!(book.undo().is_err());
+    assert_eq!(contents(&book, 1, 1), "1");
+    book.unlock_range(&Address::new(1, 1), &Address::new(1, 1));
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 1), "");
+}
+
+#[test]
+fn test_read_quoted_fields() {
+    let rows = read_delimited_rows("name,notes\n\"Smith, Ann\",\"two\nlines\"\n", b',')
+        .expect("Failed to read rows");
+    assert_eq!(
+        rows,
+        vec![vec!["name", "notes"], vec!["Smith, Ann", "two\nlines"]]
+    );
+    let rows = read_delimited_rows("a;\"b;c\"\n", b';').expect("Failed to read rows");
+    assert_eq!(rows, vec![vec!["a", "b;c"]]);
+}
+
+#[test]
+fn test_read_ragged_row_is_an_error() {
+    let err = read_delimited_rows("a,b\n1,2\n3\n", b',').expect_err("Ragged row was accepted");
+    assert_eq!(
+        err.to_string(),
+        "Malformed row on line 3: expected 2 fields but found 1"
+    );
+}
+
+#[test]
+fn test_import_delimited() {
+    let mut book = Book::default();
+    book.import_delimited(0, "item,count\n\"nuts, bolts\",4\n", b',')
+        .expect("Failed to import");
+    assert_eq!(contents(&book, 1, 1), "item");
+    assert_eq!(contents(&book, 2, 1), "nuts, bolts");
+    assert_eq!(rendered(&book, 2, 2), "4");
+    // A ragged row leaves the sheet alone.
+    assert!(book.import_delimited(0, "x,y\nz\n", b',').is_err());
+    assert_eq!(contents(&book, 1, 1), "item");
+}
+
+#[test]
+fn test_looks_like_header() {
+    let rows = |text: &str| read_delimited_rows(text, b',').expect("Failed to read rows");
+    assert!(looks_like_header(&rows("name,age\nann,30\n")));
+    assert!(!looks_like_header(&rows("1,2\n3,4\n")));
+    assert!(!looks_like_header(&rows("name,age\nann,bob\n")));
+    assert!(!looks_like_header(&rows("name,\nann,30\n")));
+}
+
+/// A book with a header row and fields that need quoting.
+fn export_book() -> Book<'static> {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "name");
+    set(&mut book, 1, 2, "note");
+    set(&mut book, 2, 1, "Smith, Ann");
+    set(&mut book, 2, 2, "say \"hi\"");
+    set(&mut book, 3, 1, "bob");
+    set(&mut book, 3, 2, "plain");
+    book.evaluate();
+    book
+}
+
+#[test]
+fn test_export_escapes_fields() {
+    let book = export_book();
+    let mut out = Vec::new();
+    book.delimited_for_sheet(0, b',', &mut out)
+        .expect("Failed to export");
+    assert_eq!(
+        String::from_utf8(out).expect("Export isn't utf8"),
+        "name,note\n\"Smith, Ann\",\"say \"\"hi\"\"\"\nbob,plain\n"
+    );
+    let mut out = Vec::new();
+    book.delimited_for_sheet(0, b'\t', &mut out)
+        .expect("Failed to export");
+    assert_eq!(
+        String::from_utf8(out).expect("Export isn't utf8"),
+        "name\tnote\nSmith, Ann\t\"say \"\"hi\"\"\"\nbob\tplain\n"
+    );
+}
+
+#[test]
+fn test_export_range_header() {
+    let book = export_book();
+    let start = Address::new(3, 1);
+    let end = Address::new(3, 2);
+    let range = || AddressRange {
+        start: &start,
+        end: &end,
+    };
+    assert_eq!(
+        book.get_delimited_rows_for_range(range(), true)
+            .expect("Failed to get rows"),
+        vec![vec!["name", "note"], vec!["bob", "plain"]]
+    );
+    assert_eq!(
+        book.get_delimited_rows_for_range(range(), false)
+            .expect("Failed to get rows"),
+        vec![vec!["bob", "plain"]]
+    );
+}
+
+#[test]
+fn test_trim_blank_edges() {
+    let row = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        trim_blank_edges(vec![
+            row(&["a", "", ""]),
+            row(&["", "b", ""]),
+            row(&["", "", ""])
+        ]),
+        vec![row(&["a", ""]), row(&["", "b"])]
+    );
+    assert!(trim_blank_edges(vec![row(&["", ""])]).is_empty());
+}
+
+#[test]
+fn test_find_cells() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "Apple");
+    set(&mut book, 1, 2, "banana");
+    set(&mut book, 2, 1, "=1+1");
+    set(&mut book, 2, 2, "pineapple");
+    set(&mut book, 3, 3, "APPLE pie");
+    book.evaluate();
+    assert_eq!(
+        book.find_cells(0, "apple", false)
+            .expect("Failed to find cells"),
+        vec![Address::new(1, 1), Address::new(2, 2), Address::new(3, 3)]
+    );
+    assert_eq!(
+        book.find_cells(0, "apple", true)
+            .expect("Failed to find cells"),
+        vec![Address::new(2, 2)]
+    );
+    // Formulas match on their value rather than their source.
+    assert_eq!(
+        book.find_cells(0, "2", false)
+            .expect("Failed to find cells"),
+        vec![Address::new(2, 1)]
+    );
+    assert!(
+        book.find_cells(0, "=1", false)
+            .expect("Failed to find cells")
+            .is_empty()
+    );
+    assert!(
+        book.find_cells(0, "", false)
+            .expect("Failed to find cells")
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_auto_fit_column() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "ab");
+    set(&mut book, 2, 1, "日本語");
+    set(&mut book, 1, 2, &"x".repeat(100));
+    book.evaluate();
+    // Wide characters take up two columns each.
+    assert_eq!(
+        book.auto_fit_column(1).expect("Failed to auto fit"),
+        Some(6)
+    );
+    assert_eq!(book.get_col_size(1).expect("Failed to get size"), 6);
+    assert_eq!(
+        book.auto_fit_column(2).expect("Failed to auto fit"),
+        Some(AUTO_FIT_MAX_WIDTH)
+    );
+    let empty_size = book.get_col_size(3).expect("Failed to get size");
+    assert_eq!(book.auto_fit_column(3).expect("Failed to auto fit"), None);
+    assert_eq!(
+        book.get_col_size(3).expect("Failed to get size"),
+        empty_size
+    );
+}
+
+#[test]
+fn test_display_width() {
+    assert_eq!(display_width(""), 0);
+    assert_eq!(display_width("abc"), 3);
+    assert_eq!(display_width("日本"), 4);
+    assert_eq!(display_width("short\na longer line"), 13);
+}
+
+/// The area of a single cell in the first sheet.
+fn cell_area(row: usize, col: usize) -> Area {
+    Area {
+        sheet: 0,
+        row: row as i32,
+        column: col as i32,
+        width: 1,
+        height: 1,
+    }
+}
+
+#[test]
+fn test_number_formats() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1234.5");
+    set(&mut book, 2, 1, "0.25");
+    set(&mut book, 3, 1, "3.14159");
+    book.set_cell_format(CellFormat::Currency, &cell_area(1, 1))
+        .expect("Failed to format cell");
+    book.set_cell_format(CellFormat::Percent, &cell_area(2, 1))
+        .expect("Failed to format cell");
+    book.set_cell_format(CellFormat::Decimals(2), &cell_area(3, 1))
+        .expect("Failed to format cell");
+    book.evaluate();
+    assert_eq!(rendered(&book, 1, 1), "$1,234.50");
+    assert_eq!(rendered(&book, 2, 1), "25%");
+    assert_eq!(rendered(&book, 3, 1), "3.14");
+    // The values themselves don't change.
+    assert_eq!(contents(&book, 1, 1), "1234.5");
+    assert_eq!(contents(&book, 2, 1), "0.25");
+}
+
+#[test]
+fn test_parse_cell_format() {
+    assert_eq!(CellFormat::parse("currency"), Some(CellFormat::Currency));
+    assert_eq!(CellFormat::parse("Percent"), Some(CellFormat::Percent));
+    assert_eq!(
+        CellFormat::parse("decimals 3"),
+        Some(CellFormat::Decimals(3))
+    );
+    assert_eq!(CellFormat::parse("decimals"), None);
+    assert_eq!(CellFormat::parse("currency 2"), None);
+    assert_eq!(CellFormat::parse("bogus"), None);
+    assert_eq!(CellFormat::Decimals(0).num_fmt(), "0");
+    assert_eq!(CellFormat::Decimals(2).num_fmt(), "0.00");
+}
+
+/// A sidecar with some of every kind of metadata in the first two sheets.
+fn full_sidecar() -> Sidecar {
+    let on_sheet = |sheet, row, col| Address { sheet, row, col };
+    let mut sidecar = Sidecar::default();
+    sidecar.notes.insert(on_sheet(0, 2, 3), "first".to_owned());
+    sidecar.notes.insert(on_sheet(1, 4, 1), "second".to_owned());
+    sidecar.locked.push((on_sheet(0, 1, 1), on_sheet(0, 2, 2)));
+    sidecar.locked.push((on_sheet(1, 5, 1), on_sheet(1, 6, 1)));
+    sidecar.hidden_rows.insert((0, 7));
+    sidecar.hidden_cols.insert((1, 3));
+    sidecar.frozen.insert(1, (1, 2));
+    sidecar.location = Some(on_sheet(1, 9, 2));
+    sidecar.viewport_corner = Some(on_sheet(1, 5, 1));
+    sidecar
+}
+
+#[test]
+fn test_sidecar_json_round_trip() {
+    let sidecar = full_sidecar();
+    assert_eq!(Sidecar::from_json(&sidecar.to_json()), sidecar);
+    assert_eq!(
+        Sidecar::from_json(&Sidecar::default().to_json()),
+        Sidecar::default()
+    );
+}
+
+#[test]
+fn test_sidecar_missing_fields_use_defaults() {
+    let value = serde_json::json!({
+        "notes": [{ "sheet": 0, "row": 1, "col": 1, "text": "kept" }],
+    });
+    let sidecar = Sidecar::from_json(&value);
+    assert_eq!(
+        sidecar.notes.get(&Address::new(1, 1)).map(String::as_str),
+        Some("kept")
+    );
+    assert!(sidecar.locked.is_empty());
+    assert!(sidecar.frozen.is_empty());
+    assert_eq!(sidecar.location, None);
+    // Entries without the expected shape are skipped.
+    let value = serde_json::json!({ "notes": [{ "row": 1, "text": "lost" }], "locked": 7 });
+    assert_eq!(Sidecar::from_json(&value), Sidecar::default());
+}
+
+#[test]
+fn test_sheets() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Data")).expect("Failed to add sheet");
+    assert_eq!(book.get_sheet_names(), vec!["Sheet1", "Data"]);
+    // Adding a sheet doesn't switch to it.
+    assert_eq!(book.location.sheet, 0);
+    book.select_next_sheet();
+    assert_eq!(book.get_sheet_name().expect("Failed to get name"), "Data");
+    book.select_next_sheet();
+    assert_eq!(book.location.sheet, 0);
+    book.select_prev_sheet();
+    assert_eq!(book.location.sheet, 1);
+
+    book.set_sheet_name(1, "Numbers")
+        .expect("Failed to rename sheet");
+    assert_eq!(book.get_sheet_names(), vec!["Sheet1", "Numbers"]);
+    assert!(book.select_sheet_by_name("Sheet1"));
+    assert_eq!(book.location.sheet, 0);
+    assert!(!book.select_sheet_by_name("Missing"));
+}
+
+#[test]
+fn test_cross_sheet_reference() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Numbers"))
+        .expect("Failed to add sheet");
+    book.update_cell(
+        &Address {
+            sheet: 1,
+            row: 2,
+            col: 2,
+        },
+        "21",
+    )
+    .expect("Failed to update cell");
+    set(&mut book, 1, 1, "=Numbers!B2*2");
+    book.evaluate();
+    assert_eq!(rendered(&book, 1, 1), "42");
+}
+
+#[test]
+fn test_delete_last_sheet_is_refused() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Data")).expect("Failed to add sheet");
+    book.select_next_sheet();
+    book.delete_sheet().expect("Failed to delete sheet");
+    assert_eq!(book.location.sheet, 0);
+    assert_eq!(book.get_sheet_names(), vec!["Sheet1"]);
+    assert!(book.delete_sheet().is_err());
+    assert_eq!(book.get_sheet_names(), vec!["Sheet1"]);
+}
+
+#[test]
+fn test_sidecar_remove_sheet() {
+    let mut sidecar = full_sidecar();
+    sidecar.remove_sheet(0);
+    // The first sheet's metadata is gone and the second sheet's moved down.
+    let notes: Vec<(&Address, &str)> = sidecar
+        .notes
+        .iter()
+        .map(|(addr, text)| (addr, text.as_str()))
+        .collect();
+    assert_eq!(notes, vec![(&Address::new(4, 1), "second")]);
+    assert_eq!(
+        sidecar.locked,
+        vec![(Address::new(5, 1), Address::new(6, 1))]
+    );
+    assert!(sidecar.hidden_rows.is_empty());
+    assert_eq!(
+        sidecar.hidden_cols.iter().collect::<Vec<_>>(),
+        vec![&(0, 3)]
+    );
+    assert_eq!(sidecar.frozen.get(&0), Some(&(1, 2)));
+    assert_eq!(sidecar.location, Some(Address::new(9, 2)));
+    assert_eq!(sidecar.viewport_corner, Some(Address::new(5, 1)));
+}
+
+#[test]
+fn test_fill_down_adjusts_relative_references() {
+    let mut book = Book::default();
+    set(&mut book, 1, 2, "=A1+1");
+    set(&mut book, 1, 3, "=$A$1*2");
+    book.fill_down(&Address::new(1, 2), &Address::new(3, 3), false)
+        .expect("Failed to fill down");
+    assert_eq!(contents(&book, 2, 2), "=A2+1");
+    assert_eq!(contents(&book, 3, 2), "=A3+1");
+    // Absolute references don't move.
+    assert_eq!(contents(&book, 2, 3), "=$A$1*2");
+    assert_eq!(contents(&book, 3, 3), "=$A$1*2");
+    // The fill undoes in one step.
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 2, 2), "");
+    assert_eq!(contents(&book, 3, 3), "");
+    assert_eq!(contents(&book, 1, 2), "=A1+1");
+}
+
+#[test]
+fn test_fill_right_adjusts_relative_references() {
+    let mut book = Book::default();
+    set(&mut book, 2, 1, "=A1");
+    book.fill_right(&Address::new(2, 1), &Address::new(2, 3), false)
+        .expect("Failed to fill right");
+    assert_eq!(contents(&book, 2, 2), "=B1");
+    assert_eq!(contents(&book, 2, 3), "=C1");
+}
+
+#[test]
+fn test_fill_down_series() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 2, 1, "3");
+    book.fill_down(&Address::new(1, 1), &Address::new(4, 1), true)
+        .expect("Failed to fill down");
+    assert_eq!(rendered(&book, 3, 1), "5");
+    assert_eq!(rendered(&book, 4, 1), "7");
+}
+
+#[test]
+fn test_sidecar_follows_inserted_and_deleted_rows() {
+    let mut sidecar = Sidecar::default();
+    sidecar.notes.insert(Address::new(2, 1), "two".to_owned());
+    sidecar.notes.insert(Address::new(5, 1), "five".to_owned());
+    sidecar
+        .locked
+        .push((Address::new(2, 1), Address::new(6, 1)));
+    sidecar.hidden_rows.insert((0, 5));
+    sidecar.frozen.insert(0, (3, 0));
+
+    sidecar.insert_rows(0, 3, 2);
+    assert_eq!(
+        sidecar.notes.keys().collect::<Vec<_>>(),
+        vec![&Address::new(2, 1), &Address::new(7, 1)]
+    );
+    assert_eq!(
+        sidecar.locked,
+        vec![(Address::new(2, 1), Address::new(8, 1))]
+    );
+    assert!(sidecar.hidden_rows.contains(&(0, 7)));
+    assert_eq!(sidecar.frozen.get(&0), Some(&(5, 0)));
+
+    // Deleting rows drops what was in them and trims ranges running into them.
+    sidecar.delete_rows(0, 1, 2);
+    assert_eq!(
+        sidecar.notes.keys().collect::<Vec<_>>(),
+        vec![&Address::new(5, 1)]
+    );
+    assert_eq!(
+        sidecar.locked,
+        vec![(Address::new(1, 1), Address::new(6, 1))]
+    );
+    assert!(sidecar.hidden_rows.contains(&(0, 5)));
+    assert_eq!(sidecar.frozen.get(&0), Some(&(3, 0)));
+}
+
+#[test]
+fn test_delete_locked_row_is_refused() {
+    let mut book = Book::default();
+    set(&mut book, 2, 1, "locked");
+    book.lock_range(&Address::new(2, 1), &Address::new(2, 1));
+    assert!(book.delete_rows(1, 2).is_err());
+    assert!(book.delete_columns(1, 1).is_err());
+    assert_eq!(contents(&book, 2, 1), "locked");
+    // Rows without locked cells can still go.
+    book.delete_rows(3, 1).expect("Failed to delete row");
+    assert_eq!(contents(&book, 2, 1), "locked");
+}
+
+/// Fill the first sheet from rows of values starting at A1.
+fn set_rows(book: &mut Book, rows: &[&[&str]]) {
+    for (row, values) in rows.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            set(book, row + 1, col + 1, value);
+        }
+    }
+    book.evaluate();
+}
+
+/// The contents of a column from the first row down through `rows`.
+fn column(book: &Book, col: usize, rows: usize) -> Vec<String> {
+    (1..=rows).map(|row| contents(book, row, col)).collect()
+}
+
+#[test]
+fn test_sort_numeric_column() {
+    let mut book = Book::default();
+    set_rows(
+        &mut book,
+        &[
+            &["10", "ten", "=A1*2"],
+            &["9", "nine", "=A2*2"],
+            &["100", "hundred", "=A3*2"],
+        ],
+    );
+    book.sort_rows(0, None, 1, false).expect("Failed to sort");
+    // Numbers sort by value rather than as text.
+    assert_eq!(column(&book, 1, 3), vec!["9", "10", "100"]);
+    assert_eq!(column(&book, 2, 3), vec!["nine", "ten", "hundred"]);
+    // Formulas move with their row and keep pointing at it.
+    assert_eq!(column(&book, 3, 3), vec!["=A1*2", "=A2*2", "=A3*2"]);
+    assert_eq!(rendered(&book, 1, 3), "18");
+
+    book.sort_rows(0, None, 1, true).expect("Failed to sort");
+    assert_eq!(column(&book, 1, 3), vec!["100", "10", "9"]);
+}
+
+#[test]
+fn test_sort_lexical_column() {
+    let mut book = Book::default();
+    set_rows(&mut book, &[&["banana"], &["Apple"], &["cherry"], &["b2"]]);
+    book.sort_rows(0, None, 1, false).expect("Failed to sort");
+    assert_eq!(column(&book, 1, 4), vec!["Apple", "b2", "banana", "cherry"]);
+}
+
+#[test]
+fn test_sort_keeps_ties_in_order() {
+    let rows: &[&[&str]] = &[&["2", "a"], &["1", "b"], &["2", "c"], &["1", "d"]];
+    let mut book = Book::default();
+    set_rows(&mut book, rows);
+    book.sort_rows(0, None, 1, false).expect("Failed to sort");
+    assert_eq!(column(&book, 2, 4), vec!["b", "d", "a", "c"]);
+
+    let mut book = Book::default();
+    set_rows(&mut book, rows);
+    book.sort_rows(0, None, 1, true).expect("Failed to sort");
+    assert_eq!(column(&book, 2, 4), vec!["a", "c", "b", "d"]);
+}
+
+#[test]
+fn test_sort_keeps_header() {
+    let mut book = Book::default();
+    set_rows(
+        &mut book,
+        &[&["name", "age"], &["bob", "40"], &["ann", "30"]],
+    );
+    book.sort_rows(0, None, 2, false).expect("Failed to sort");
+    assert_eq!(column(&book, 1, 3), vec!["name", "ann", "bob"]);
+    // The sort undoes in one step.
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(column(&book, 1, 3), vec!["name", "bob", "ann"]);
+}
+
+#[test]
+fn test_transpose_range_undo() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 2, "2");
+    set(&mut book, 1, 3, "3");
+    set(&mut book, 3, 1, "keep");
+    // The corners can be given in either order.
+    book.transpose_range(&Address::new(1, 3), &Address::new(1, 1))
+        .expect("Failed to transpose range");
+    assert_eq!(contents(&book, 1, 1), "1");
+    assert_eq!(contents(&book, 2, 1), "2");
+    assert_eq!(contents(&book, 3, 1), "3");
+    assert_eq!(contents(&book, 1, 2), "");
+    assert_eq!(contents(&book, 1, 3), "");
+    // The whole transpose is one undo step.
+    assert!(book.undo().expect("Failed to undo"));
+    assert_eq!(contents(&book, 1, 2), "2");
+    assert_eq!(contents(&book, 1, 3), "3");
+    assert_eq!(contents(&book, 2, 1), "");
+    assert_eq!(contents(&book, 3, 1), "keep");
+    assert!(book.redo().expect("Failed to redo"));
+    assert_eq!(contents(&book, 3, 1), "3");
+    assert_eq!(contents(&book, 1, 3), "");
+}
+
+#[test]
+fn test_delete_sheet_forgets_history() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Data")).expect("Failed to add sheet");
+    set(&mut book, 1, 1, "first");
+    book.select_next_sheet();
+    book.update_cell(
+        &Address {
+            sheet: 1,
+            row: 1,
+            col: 1,
+        },
+        "second",
+    )
+    .expect("Failed to update cell");
+    book.select_prev_sheet();
+    book.delete_sheet().expect("Failed to delete sheet");
+    assert_eq!(book.get_sheet_names(), vec!["Data"]);
+    // The history referred to sheets that moved so there is nothing to undo.
+    assert!(!book.undo().expect("Failed to undo"));
+    assert_eq!(
+        book.get_cell_addr_contents(&Address {
+            sheet: 0,
+            row: 1,
+            col: 1,
+        })
+        .expect("Failed to get contents"),
+        "second"
+    );
+}
+
+#[test]
+fn test_range_stats() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 2, 1, "2.5");
+    set(&mut book, 3, 1, "text");
+    set(&mut book, 4, 1, "=A1+A2");
+    set(&mut book, 1, 2, "100");
+    book.evaluate();
+    let stats = |start: Address, end: Address| {
+        book.get_range_stats(&AddressRange {
+            start: &start,
+            end: &end,
+        })
+        .expect("Failed to get range stats")
+    };
+    // Text and empty cells aren't counted.
+    let column = stats(Address::new(1, 1), Address::new(10, 1));
+    assert_eq!(column.sum, 7.0);
+    assert_eq!(column.count, 3);
+    assert_eq!(column.average(), Some(7.0 / 3.0));
+    // The corners can be given in either order.
+    assert_eq!(stats(Address::new(2, 2), Address::new(1, 1)).sum, 103.5);
+    // A whole column selection only visits the cells with data.
+    let whole = stats(
+        Address::new(1, 1),
+        Address::new(super::LAST_ROW as usize, 1),
+    );
+    assert_eq!(whole, column);
+    let empty = stats(Address::new(5, 5), Address::new(6, 6));
+    assert_eq!(empty.count, 0);
+    assert_eq!(empty.average(), None);
+}
+
+#[test]
+fn test_export_markdown_round_trip() {
+    let rows: Vec<Vec<String>> = [["name", "note"], ["a|b", "line\nbreak"], ["c", ""]]
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+        .collect();
+    let table = rows_to_markdown_table(&rows);
+    assert_eq!(
+        table,
+        "| name | note |\n| --- | --- |\n| a\\|b | line break |\n| c |  |\n"
+    );
+    // Every cell's text comes back out of the markdown in order.
+    let md = Markdown::from_str(&table);
+    let text = md.get_text();
+    let plain = text
+        .lines
+        .iter()
+        .map(|line| {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let mut rest = plain.as_str();
+    for cell in ["name", "note", "a|b", "line break", "c"] {
+        let idx = rest
+            .find(cell)
+            .unwrap_or_else(|| panic!("{:?} is missing from {:?}", cell, plain));
+        rest = &rest[idx + cell.len()..];
+    }
+}
@@ -6,7 +6,7 @@ use ratatui;
 use serde_json::to_writer;
 use std::io::Write;
 
-use ui::Workspace;
+use ui::{Workspace, keymap::KeyMap, render::viewport};
 
 mod book;
 mod ui;
@@ -22,6 +22,12 @@ pub struct Args {
     timezone_name: String,
     #[arg(long)]
     log_input: Option<PathBuf>,
+    #[arg(long)]
+    keymap: Option<PathBuf>,
+    #[arg(long)]
+    error_color: Option<String>,
+    #[arg(long)]
+    watch: bool,
 }
 
 type ReadFn = Box<dyn FnMut() -> anyhow::Result<event::Event>>;
@@ -32,6 +38,19 @@ fn run(terminal: &mut ratatui::DefaultTerminal, args: Args) -> anyhow::Result<Ex
     } else {
         Workspace::new_empty(&args.locale_name, &args.timezone_name)?
     };
+    if let Some(keymap_path) = &args.keymap {
+        ws.state.keymap = KeyMap::load(keymap_path)?;
+    }
+    if let Some(color) = &args.error_color {
+        ws.state.error_style = ws
+            .state
+            .error_style
+            .fg(viewport::map_color(Some(color), viewport::DEFAULT_ERROR_COLOR));
+    }
+    let watch = args.watch;
+    if watch {
+        ws.enable_watch();
+    }
     let mut read_func: ReadFn = if let Some(log_path) = args.log_input {
         {
             let log_file = std::fs::File::create(log_path)?;
@@ -50,6 +69,10 @@ fn run(terminal: &mut ratatui::DefaultTerminal, args: Args) -> anyhow::Result<Ex
     };
     loop {
         terminal.draw(|frame| ui::render::draw(frame, &mut ws))?;
+        if watch && !event::poll(ui::watch::POLL_INTERVAL)? {
+            ws.check_for_external_change();
+            continue;
+        }
         if let Some(code) = ws.handle_input(read_func()?)? {
             return Ok(code);
         }
@@ -60,8 +83,10 @@ fn main() -> anyhow::Result<ExitCode> {
     let args = Args::parse();
 
     let mut terminal = ratatui::init();
+    crossterm::execute!(std::io::stdout(), crossterm::event::EnableMouseCapture)?;
     terminal.clear()?;
     let app_result = run(&mut terminal, args);
+    crossterm::execute!(std::io::stdout(), crossterm::event::DisableMouseCapture)?;
     ratatui::restore();
     app_result
 }
@@ -0,0 +1,39 @@
+//! System clipboard integration.
+//!
+//! The system clipboard is only available with the `clipboard` feature. Without
+//! it, or when there is no clipboard to talk to like in a headless session,
+//! these functions quietly do nothing and we fall back to our internal
+//! clipboard.
+
+/// Put html and its plain text alternative on the system clipboard.
+#[cfg(feature = "clipboard")]
+pub fn set_html(html: String, text: String) {
+    if let Ok(mut cb) = arboard::Clipboard::new() {
+        let _ = cb.set_html(html, Some(text));
+    }
+}
+
+/// Put plain text on the system clipboard.
+#[cfg(feature = "clipboard")]
+pub fn set_text(text: String) {
+    if let Ok(mut cb) = arboard::Clipboard::new() {
+        let _ = cb.set_text(text);
+    }
+}
+
+/// Get the plain text on the system clipboard if there is any.
+#[cfg(feature = "clipboard")]
+pub fn get_text() -> Option<String> {
+    arboard::Clipboard::new().ok()?.get_text().ok()
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn set_html(_html: String, _text: String) {}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn set_text(_text: String) {}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn get_text() -> Option<String> {
+    None
+}
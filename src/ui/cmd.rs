@@ -1,26 +1,75 @@
 use std::ops::Range;
 
+use crate::book::{
+    conditional::Condition,
+    format::{CellAlignment, CellFormat},
+};
+
 /// A parsed command entered in during command mode.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum Cmd<'a> {
     Write(Option<&'a str>),
     InsertRows(usize),
     InsertColumns(usize),
+    DeleteRows(usize),
+    DeleteColumns(usize),
+    HideRows(usize),
+    HideColumns(usize),
+    UnhideRows,
+    UnhideColumns,
+    /// Freeze (rows, columns). `None` freezes the rows above and the columns
+    /// left of the cursor.
+    Freeze(Option<(usize, usize)>),
+    Unfreeze,
     ColorRows(Option<usize>, String),
     ColorColumns(Option<usize>, String),
     ColorCell(String),
+    FormatCell(CellFormat),
+    FormatColumns(Option<usize>, CellFormat),
+    AlignCell(CellAlignment),
+    AlignColumns(Option<usize>, CellAlignment),
+    ConditionalFormat(Condition, String),
+    ClearConditionalFormat,
+    WrapText,
+    AutoFit,
+    Lock,
+    Unlock,
+    Transpose,
+    /// Sort rows by the current column. True sorts descending.
+    Sort(bool),
     RenameSheet(Option<usize>, &'a str),
     NewSheet(Option<&'a str>),
+    DeleteSheet,
     SelectSheet(&'a str),
     Edit(&'a str),
+    /// Move the cursor to an A1 style cell address.
+    Goto(&'a str),
     Help(Option<&'a str>),
-    ExportCsv(&'a str),
+    /// Export the selection or the current sheet to a delimited file. Takes
+    /// the path, the delimiter if one was given, and whether to include the
+    /// header row.
+    ExportCsv(&'a str, Option<u8>, bool),
+    /// Import a delimited file into the current sheet. The delimiter is
+    /// detected when it isn't given.
+    ImportCsv(&'a str, Option<u8>),
+    ExportMarkdown(&'a str),
+    Note(&'a str),
+    ClearNote,
     Quit,
 }
 
 /// Parse command text into a `Cmd`.
 pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'static str> {
     let cursor = StrCursor::new(input);
+    // A bare cell address is a goto.
+    if let Some(cmd) = try_consume_goto(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    // `wrap-text` starts with the `w` short form of write so it has to come
+    // first.
+    if let Some(cmd) = try_consume_wrap_text(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume write command.
     if let Some(cmd) = try_consume_write(cursor.clone())? {
         return Ok(Some(cmd));
@@ -31,6 +80,9 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_select_sheet(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_delete_sheet(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume insert-row command.
     if let Some(cmd) = try_consume_insert_row(cursor.clone())? {
         return Ok(Some(cmd));
@@ -39,14 +91,53 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_insert_column(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_delete_row(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_delete_column(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_hide_rows(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_hide_columns(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_unhide_rows(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_unhide_columns(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_freeze(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_unfreeze(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_sort(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // Try consume export
     if let Some(cmd) = try_consume_export_csv(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_export_markdown(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_import_csv(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume edit command.
     if let Some(cmd) = try_consume_edit(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_note(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_clear_note(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume help command.
     if let Some(cmd) = try_consume_help(cursor.clone())? {
         return Ok(Some(cmd));
@@ -67,6 +158,36 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_color_cell(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_format_cell(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_format_columns(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_align_cell(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_align_columns(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_auto_fit(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_transpose(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_lock(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_unlock(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_clear_conditional_format(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_conditional_format(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     Ok(None)
 }
 
@@ -124,10 +245,100 @@ fn try_consume_export_csv<'cmd, 'i: 'cmd>(
         return Ok(None);
     }
     if input.remaining() == 0 || !is_ws(&mut input) {
-        return Err("Invalid command: Did you mean to type `export <path>`?");
+        return Err(
+            "Invalid command: Did you mean to type `export-csv <path> [delimiter] [no-header]`?",
+        );
+    }
+    let mut arg = input.take_rest().trim();
+    let mut delimiter = None;
+    let mut header = true;
+    // Options are trailing words after the path.
+    while let Some((rest, word)) = arg.rsplit_once(char::is_whitespace) {
+        if word == "no-header" && header {
+            header = false;
+        } else if let Some(d) = delimiter_from_name(word).filter(|_| delimiter.is_none()) {
+            delimiter = Some(d);
+        } else {
+            break;
+        }
+        arg = rest.trim_end();
+    }
+    return Ok(Some(Cmd::ExportCsv(arg, delimiter, header)));
+}
+
+/// The delimiter named by a word in an import or export command.
+fn delimiter_from_name(name: &str) -> Option<u8> {
+    match name {
+        "comma" => Some(b','),
+        "tab" => Some(b'\t'),
+        "semicolon" => Some(b';'),
+        _ => None,
+    }
+}
+
+fn try_consume_import_csv<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "import-csv";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() == 0 || !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `import-csv <path> [delimiter]`?");
+    }
+    let arg = input.take_rest().trim();
+    // An optional trailing word names the delimiter.
+    if let Some((path, name)) = arg.rsplit_once(char::is_whitespace) {
+        if let Some(delimiter) = delimiter_from_name(name) {
+            return Ok(Some(Cmd::ImportCsv(path.trim(), Some(delimiter))));
+        }
+    }
+    return Ok(Some(Cmd::ImportCsv(arg, None)));
+}
+
+fn try_consume_export_markdown<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "export-md";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() == 0 || !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `export-md <path>`?");
+    }
+    let arg = input.take_rest().trim();
+    return Ok(Some(Cmd::ExportMarkdown(arg)));
+}
+
+fn try_consume_goto<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "goto";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+        if input.remaining() == 0 || !is_ws(&mut input) {
+            return Err("Invalid command: Did you mean to type `goto <cell>`?");
+        }
+        let arg = input.take_rest().trim();
+        if arg.is_empty() {
+            return Err("Invalid command: Did you forget the cell? `goto <cell>`?");
+        }
+        return Ok(Some(Cmd::Goto(arg)));
     }
     let arg = input.take_rest().trim();
-    return Ok(Some(Cmd::ExportCsv(arg)));
+    let letters = arg.chars().take_while(char::is_ascii_alphabetic).count();
+    let digits = arg[letters..].chars().all(|c| c.is_ascii_digit());
+    if (1..=3).contains(&letters) && letters < arg.len() && digits {
+        return Ok(Some(Cmd::Goto(arg)));
+    }
+    Ok(None)
 }
 
 fn try_consume_new_sheet<'cmd, 'i: 'cmd>(
@@ -171,6 +382,22 @@ fn try_consume_select_sheet<'cmd, 'i: 'cmd>(
     return Ok(Some(Cmd::SelectSheet(arg)));
 }
 
+fn try_consume_delete_sheet<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "delete-sheet";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: `delete-sheet` does not take an argument");
+    }
+    return Ok(Some(Cmd::DeleteSheet));
+}
+
 fn try_consume_color_cell<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -190,6 +417,208 @@ fn try_consume_color_cell<'cmd, 'i: 'cmd>(
     return Ok(Some(Cmd::ColorCell(arg)));
 }
 
+fn try_consume_format_cell<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "format-cell";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `format-cell <format>`?");
+    }
+    let arg = parse_format(input.take_rest().trim())?;
+    return Ok(Some(Cmd::FormatCell(arg)));
+}
+
+fn try_consume_format_columns<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "format-columns";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `format-columns [count] <format>`?");
+    }
+    let (idx, rest) = try_consume_usize(input.clone());
+    let arg = parse_format(rest.take_rest().trim())?;
+    return Ok(Some(Cmd::FormatColumns(idx, arg)));
+}
+
+fn parse_format(format: &str) -> Result<CellFormat, &'static str> {
+    CellFormat::parse(format).ok_or(
+        "Invalid command: The format must be one of `currency`, `percent`, `decimals <n>`, or `general`",
+    )
+}
+
+fn try_consume_align_cell<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "align-cell";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `align-cell <alignment>`?");
+    }
+    let arg = parse_alignment(input.take_rest().trim())?;
+    return Ok(Some(Cmd::AlignCell(arg)));
+}
+
+fn try_consume_align_columns<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "align-columns";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `align-columns [count] <alignment>`?");
+    }
+    let (idx, rest) = try_consume_usize(input.clone());
+    let arg = parse_alignment(rest.take_rest().trim())?;
+    return Ok(Some(Cmd::AlignColumns(idx, arg)));
+}
+
+fn parse_alignment(alignment: &str) -> Result<CellAlignment, &'static str> {
+    CellAlignment::parse(alignment)
+        .ok_or("Invalid command: The alignment must be one of `left`, `center`, or `right`")
+}
+
+fn try_consume_conditional_format<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "cf";
+    const LONG: &'static str = "cond-format";
+    const USAGE: &'static str =
+        "Invalid command: Did you mean to type `cond-format <gt|lt|between> <n> [m] <color>`?";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() == 0 || !is_ws(&mut input) {
+        return Err(USAGE);
+    }
+    let args: Vec<&str> = input.take_rest().split_whitespace().collect();
+    let parse_num = |s: &str| {
+        s.parse::<f64>()
+            .map_err(|_| "Invalid number in `cond-format`")
+    };
+    let (condition, color) = match args.as_slice() {
+        ["gt", n, color] => (Condition::GreaterThan(parse_num(*n)?), *color),
+        ["lt", n, color] => (Condition::LessThan(parse_num(*n)?), *color),
+        ["between", low, high, color] => (
+            Condition::Between(parse_num(*low)?, parse_num(*high)?),
+            *color,
+        ),
+        _ => return Err(USAGE),
+    };
+    return Ok(Some(Cmd::ConditionalFormat(condition, parse_color(color)?)));
+}
+
+fn try_consume_wrap_text<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "wrap-text";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `wrap-text` does not take an argument");
+    }
+    return Ok(Some(Cmd::WrapText));
+}
+
+fn try_consume_auto_fit<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "autofit";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: `autofit` does not take an argument");
+    }
+    return Ok(Some(Cmd::AutoFit));
+}
+
+fn try_consume_transpose<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "transpose";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `transpose` does not take an argument");
+    }
+    return Ok(Some(Cmd::Transpose));
+}
+
+fn try_consume_lock<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "lock";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `lock` does not take an argument");
+    }
+    return Ok(Some(Cmd::Lock));
+}
+
+fn try_consume_unlock<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "unlock";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `unlock` does not take an argument");
+    }
+    return Ok(Some(Cmd::Unlock));
+}
+
+fn try_consume_clear_conditional_format<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "clear-cond-format";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `clear-cond-format` does not take an argument");
+    }
+    return Ok(Some(Cmd::ClearConditionalFormat));
+}
+
 fn try_consume_insert_row<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -246,6 +675,203 @@ fn try_consume_insert_column<'cmd, 'i: 'cmd>(
     })));
 }
 
+fn try_consume_delete_row<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "dr";
+    const LONG: &'static str = "delete-rows";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `delete-rows <arg>`?");
+    }
+    let arg = input.take_rest().trim();
+    return Ok(Some(Cmd::DeleteRows(if arg.is_empty() {
+        1
+    } else {
+        if let Ok(count) = arg.parse() {
+            count
+        } else {
+            return Err("You must pass in a non negative number for the row count");
+        }
+    })));
+}
+
+fn try_consume_delete_column<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "dc";
+    const LONG: &'static str = "delete-cols";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `delete-cols <arg>`?");
+    }
+    let arg = input.take_rest().trim();
+    return Ok(Some(Cmd::DeleteColumns(if arg.is_empty() {
+        1
+    } else {
+        if let Ok(count) = arg.parse() {
+            count
+        } else {
+            return Err("You must pass in a non negative number for the column count");
+        }
+    })));
+}
+
+fn try_consume_hide_rows<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "hide-rows";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `hide-rows <arg>`?");
+    }
+    let arg = input.take_rest().trim();
+    return Ok(Some(Cmd::HideRows(if arg.is_empty() {
+        1
+    } else {
+        if let Ok(count) = arg.parse() {
+            count
+        } else {
+            return Err("You must pass in a non negative number for the row count");
+        }
+    })));
+}
+
+fn try_consume_hide_columns<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "hide-cols";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `hide-cols <arg>`?");
+    }
+    let arg = input.take_rest().trim();
+    return Ok(Some(Cmd::HideColumns(if arg.is_empty() {
+        1
+    } else {
+        if let Ok(count) = arg.parse() {
+            count
+        } else {
+            return Err("You must pass in a non negative number for the column count");
+        }
+    })));
+}
+
+fn try_consume_unhide_rows<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "unhide-rows";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `unhide-rows` does not take an argument");
+    }
+    return Ok(Some(Cmd::UnhideRows));
+}
+
+fn try_consume_unhide_columns<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "unhide-cols";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 {
+        return Err("Invalid command: `unhide-cols` does not take an argument");
+    }
+    return Ok(Some(Cmd::UnhideColumns));
+}
+
+fn try_consume_freeze<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "freeze";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `freeze [rows] [columns]`?");
+    }
+    let args: Vec<&str> = input.take_rest().split_whitespace().collect();
+    let counts: Vec<usize> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+    if counts.len() != args.len() || counts.len() > 2 {
+        return Err("You must pass in up to two non negative numbers for the row and column count");
+    }
+    return Ok(Some(Cmd::Freeze(match counts.as_slice() {
+        [] => None,
+        [rows] => Some((*rows, 0)),
+        [rows, cols, ..] => Some((*rows, *cols)),
+    })));
+}
+
+fn try_consume_unfreeze<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "unfreeze";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: `unfreeze` does not take an argument");
+    }
+    return Ok(Some(Cmd::Unfreeze));
+}
+
+fn try_consume_sort<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "sort";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `sort [asc|desc]`?");
+    }
+    match input.take_rest().trim() {
+        "" | "asc" => Ok(Some(Cmd::Sort(false))),
+        "desc" => Ok(Some(Cmd::Sort(true))),
+        _ => Err("Invalid command: The sort order must be `asc` or `desc`"),
+    }
+}
+
 fn try_consume_edit<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -295,6 +921,42 @@ fn try_consume_help<'cmd, 'i: 'cmd>(
     })));
 }
 
+fn try_consume_note<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "note";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `note <text>`?");
+    }
+    let arg = input.take_rest().trim();
+    if arg.is_empty() {
+        return Err("Invalid command: `note` requires the text of the note");
+    }
+    return Ok(Some(Cmd::Note(arg)));
+}
+
+fn try_consume_clear_note<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "clear-note";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: `clear-note` does not take an argument");
+    }
+    return Ok(Some(Cmd::ClearNote));
+}
+
 fn try_consume_quit<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
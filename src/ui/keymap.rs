@@ -0,0 +1,273 @@
+//! Key bindings for navigation mode.
+//!
+//! A `KeyMap` maps key events to named `Action`s. The defaults are vim like and
+//! can be overridden from a config file with one binding per line:
+//!
+//! ```text
+//! # comments start with a hash
+//! ctrl-j = next-sheet
+//! w = move-up
+//! ```
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A named action that a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    NextSheet,
+    PrevSheet,
+    EditCell,
+    SubstituteCell,
+    CommandMode,
+    Search,
+    SearchNext,
+    SearchPrev,
+    RangeSelect,
+    VisualSelect,
+    Save,
+    Help,
+    Copy,
+    CopyRendered,
+    Paste,
+    PasteTranspose,
+    ClearCell,
+    ClearCellAll,
+    Undo,
+    Redo,
+    ToggleBold,
+    ToggleItalic,
+    WidenColumn,
+    NarrowColumn,
+    InsertRowBelow,
+    InsertRowAbove,
+    Quit,
+}
+
+impl Action {
+    const ALL: [(&'static str, Action); 35] = [
+        ("move-up", Action::MoveUp),
+        ("move-down", Action::MoveDown),
+        ("move-left", Action::MoveLeft),
+        ("move-right", Action::MoveRight),
+        ("select-up", Action::SelectUp),
+        ("select-down", Action::SelectDown),
+        ("select-left", Action::SelectLeft),
+        ("select-right", Action::SelectRight),
+        ("next-sheet", Action::NextSheet),
+        ("prev-sheet", Action::PrevSheet),
+        ("edit-cell", Action::EditCell),
+        ("substitute-cell", Action::SubstituteCell),
+        ("command-mode", Action::CommandMode),
+        ("search", Action::Search),
+        ("search-next", Action::SearchNext),
+        ("search-prev", Action::SearchPrev),
+        ("range-select", Action::RangeSelect),
+        ("visual-select", Action::VisualSelect),
+        ("save", Action::Save),
+        ("help", Action::Help),
+        ("copy", Action::Copy),
+        ("copy-rendered", Action::CopyRendered),
+        ("paste", Action::Paste),
+        ("paste-transpose", Action::PasteTranspose),
+        ("clear-cell", Action::ClearCell),
+        ("clear-cell-all", Action::ClearCellAll),
+        ("undo", Action::Undo),
+        ("redo", Action::Redo),
+        ("toggle-bold", Action::ToggleBold),
+        ("toggle-italic", Action::ToggleItalic),
+        ("widen-column", Action::WidenColumn),
+        ("narrow-column", Action::NarrowColumn),
+        ("insert-row-below", Action::InsertRowBelow),
+        ("insert-row-above", Action::InsertRowAbove),
+        ("quit", Action::Quit),
+    ];
+
+    /// Look up an action by its config name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, action)| *action)
+    }
+}
+
+/// Maps key events to actions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        use Action::*;
+        let mut map = Self {
+            bindings: HashMap::new(),
+        };
+        let none = KeyModifiers::NONE;
+        let ctrl = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+        let shift = KeyModifiers::SHIFT;
+        for (code, modifiers, action) in [
+            (KeyCode::Char('k'), none, MoveUp),
+            (KeyCode::Up, none, MoveUp),
+            (KeyCode::Enter, shift, MoveUp),
+            (KeyCode::Char('j'), none, MoveDown),
+            (KeyCode::Down, none, MoveDown),
+            (KeyCode::Enter, none, MoveDown),
+            (KeyCode::Char('h'), none, MoveLeft),
+            (KeyCode::Left, none, MoveLeft),
+            (KeyCode::Tab, shift, MoveLeft),
+            (KeyCode::BackTab, shift, MoveLeft),
+            (KeyCode::Char('l'), none, MoveRight),
+            (KeyCode::Right, none, MoveRight),
+            (KeyCode::Tab, none, MoveRight),
+            (KeyCode::Up, shift, SelectUp),
+            (KeyCode::Down, shift, SelectDown),
+            (KeyCode::Left, shift, SelectLeft),
+            (KeyCode::Right, shift, SelectRight),
+            (KeyCode::Char('n'), ctrl, NextSheet),
+            (KeyCode::PageDown, ctrl, NextSheet),
+            (KeyCode::Char('p'), ctrl, PrevSheet),
+            (KeyCode::PageUp, ctrl, PrevSheet),
+            (KeyCode::Char('e'), none, EditCell),
+            (KeyCode::Char('i'), none, EditCell),
+            (KeyCode::Char('s'), none, SubstituteCell),
+            (KeyCode::Char(':'), none, CommandMode),
+            (KeyCode::Char('/'), none, Search),
+            (KeyCode::Char('n'), none, SearchNext),
+            (KeyCode::Char('N'), none, SearchPrev),
+            (KeyCode::Char('r'), alt, RangeSelect),
+            (KeyCode::Char('v'), none, VisualSelect),
+            (KeyCode::Char('s'), ctrl, Save),
+            (KeyCode::Char('h'), alt, Help),
+            (KeyCode::Char('y'), none, Copy),
+            (KeyCode::Char('c'), ctrl, Copy),
+            (KeyCode::Char('Y'), none, CopyRendered),
+            (KeyCode::Char('C'), ctrl, CopyRendered),
+            (KeyCode::Char('p'), none, Paste),
+            (KeyCode::Char('v'), ctrl, Paste),
+            (KeyCode::Char('P'), none, PasteTranspose),
+            (KeyCode::Char('d'), none, ClearCell),
+            (KeyCode::Char('D'), none, ClearCellAll),
+            (KeyCode::Char('u'), none, Undo),
+            (KeyCode::Char('r'), ctrl, Redo),
+            (KeyCode::Char('B'), none, ToggleBold),
+            (KeyCode::Char('I'), none, ToggleItalic),
+            (KeyCode::Char('l'), ctrl, WidenColumn),
+            (KeyCode::Char('w'), none, WidenColumn),
+            (KeyCode::Char('h'), ctrl, NarrowColumn),
+            (KeyCode::Char('W'), none, NarrowColumn),
+            (KeyCode::Char('o'), none, InsertRowBelow),
+            (KeyCode::Char('O'), none, InsertRowAbove),
+        ] {
+            map.bind(code, modifiers, action);
+        }
+        map
+    }
+}
+
+impl KeyMap {
+    /// Bind a key to an action replacing any existing binding for that key.
+    pub fn bind(&mut self, code: KeyCode, modifiers: KeyModifiers, action: Action) {
+        self.bindings.insert(normalize(code, modifiers), action);
+    }
+
+    /// Get the action bound to a key event if there is one.
+    pub fn get(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&normalize(key.code, key.modifiers))
+            .copied()
+    }
+
+    /// Construct a keymap from config contents. Bindings in the config are
+    /// layered on top of the defaults.
+    pub fn from_config_str(config: &str) -> Result<Self> {
+        let mut map = Self::default();
+        for (idx, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, action) = line.split_once('=').ok_or_else(|| {
+                anyhow!("Invalid keymap line {}: expected `key = action`", idx + 1)
+            })?;
+            let (code, modifiers) = parse_key(key.trim())
+                .ok_or_else(|| anyhow!("Invalid key on keymap line {}: {}", idx + 1, key.trim()))?;
+            let action = Action::from_name(action.trim()).ok_or_else(|| {
+                anyhow!(
+                    "Unknown action on keymap line {}: {}",
+                    idx + 1,
+                    action.trim()
+                )
+            })?;
+            map.bind(code, modifiers, action);
+        }
+        Ok(map)
+    }
+
+    /// Load a keymap from a config file.
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        Self::from_config_str(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Terminals report uppercase characters with a shift modifier inconsistently
+/// so we ignore shift for character keys.
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(_) => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+
+/// Parse a key description like `ctrl-n`, `alt-h`, `shift-tab`, `up`, or `j`.
+pub fn parse_key(key: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = key;
+    loop {
+        let lower = rest.to_lowercase();
+        if lower.starts_with("ctrl-") {
+            modifiers |= KeyModifiers::CONTROL;
+        } else if lower.starts_with("alt-") {
+            modifiers |= KeyModifiers::ALT;
+        } else if lower.starts_with("shift-") {
+            modifiers |= KeyModifiers::SHIFT;
+        } else {
+            break;
+        }
+        rest = &rest[lower.find('-')? + 1..];
+    }
+    let code = match rest.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "esc" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return None,
+            }
+        }
+    };
+    Some((code, modifiers))
+}
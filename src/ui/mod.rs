@@ -1,10 +1,16 @@
 //! Ui rendering logic
-use std::{path::PathBuf, process::ExitCode, str::FromStr};
+use std::{
+    collections::HashMap, ops::RangeInclusive, path::PathBuf, process::ExitCode, str::FromStr,
+};
 
-use crate::book::{self, AddressRange, Book};
+use crate::book::{
+    self, AddressRange, Book, conditional::ConditionalFormat, sidecar::Sidecar,
+};
 
 use anyhow::{Result, anyhow};
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
 use ironcalc::base::{Model, expressions::types::Area};
 use ratatui::{
     buffer::Buffer,
@@ -15,22 +21,33 @@ use ratatui::{
 use tui_prompts::{State, Status, TextPrompt, TextState};
 use tui_textarea::{CursorMove, TextArea};
 
+mod clipboard;
 mod cmd;
 mod help;
+pub mod keymap;
 pub mod render;
+pub mod watch;
+#[cfg(test)]
+mod test;
 
 use cmd::Cmd;
+use keymap::{Action, KeyMap};
 use render::{markdown::Markdown, viewport::ViewportState};
 
+/// How many rows one notch of the mouse wheel moves.
+const MOUSE_SCROLL_LINES: u16 = 3;
+
 #[derive(Default, Debug, PartialEq, Clone)]
 pub enum Modality {
     #[default]
     Navigate,
     CellEdit,
     Command,
+    Search,
     Dialog,
     RangeSelect,
     Quit,
+    Reload,
 }
 
 #[derive(Debug, Default)]
@@ -40,7 +57,73 @@ pub struct RangeSelection {
     pub end: Option<Address>,
 }
 
+/// The state of a `/` search over the current sheet.
+#[derive(Debug, Default)]
+pub struct SearchState {
+    pub query: String,
+    pub case_sensitive: bool,
+    /// The matching cells in row then column order.
+    pub matches: Vec<Address>,
+}
+
+impl SearchState {
+    /// The match to move to from `from`. Searching forward past the last
+    /// match wraps to the first and searching backward wraps to the last.
+    pub fn next_match(&self, from: &Address, forward: bool) -> Option<&Address> {
+        if forward {
+            self.matches
+                .iter()
+                .find(|addr| *addr > from)
+                .or_else(|| self.matches.first())
+        } else {
+            self.matches
+                .iter()
+                .rev()
+                .find(|addr| *addr < from)
+                .or_else(|| self.matches.last())
+        }
+    }
+}
+
+/// A rectangular block of cells between an anchor cell and a cursor cell.
+/// Either corner can be anywhere relative to the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: Address,
+    pub cursor: Address,
+}
+
+impl Selection {
+    pub fn new(anchor: Address, cursor: Address) -> Self {
+        Self { anchor, cursor }
+    }
+
+    /// The rows the selection covers.
+    pub fn rows(&self) -> RangeInclusive<usize> {
+        std::cmp::min(self.anchor.row, self.cursor.row)
+            ..=std::cmp::max(self.anchor.row, self.cursor.row)
+    }
+
+    /// The columns the selection covers.
+    pub fn cols(&self) -> RangeInclusive<usize> {
+        std::cmp::min(self.anchor.col, self.cursor.col)
+            ..=std::cmp::max(self.anchor.col, self.cursor.col)
+    }
+
+    /// Returns true if the address is inside of the selection.
+    pub fn contains(&self, addr: &Address) -> bool {
+        addr.sheet == self.anchor.sheet
+            && self.rows().contains(&addr.row)
+            && self.cols().contains(&addr.col)
+    }
+}
+
 impl RangeSelection {
+    /// The current selection if both ends of the range have been chosen.
+    pub fn selection(&self) -> Option<Selection> {
+        Some(Selection::new(self.start.clone()?, self.end.clone()?))
+    }
+
     pub fn get_range(&self) -> Option<(Address, Address)> {
         if let (Some(start), Some(end)) = (&self.start, &self.end) {
             return Some((
@@ -76,13 +159,27 @@ pub struct AppState<'ws> {
     pub modality_stack: Vec<Modality>,
     pub viewport_state: ViewportState,
     pub command_state: TextState<'ws>,
+    pub search_prompt: TextState<'ws>,
+    pub search: SearchState,
     pub numeric_prefix: Vec<char>,
     pub char_queue: Vec<char>,
     pub range_select: RangeSelection,
     pub dialog_scroll: u16,
+    pub keymap: KeyMap,
+    /// A message for the status line. Cleared on the next key press.
+    pub status_message: Option<String>,
+    /// The style for cells whose formula failed to evaluate.
+    pub error_style: Style,
+    /// Watches our file for external changes when live reload is enabled.
+    pub watch: Option<watch::FileWatch>,
     dirty: bool,
     popup: Option<Markdown>,
     clipboard: Option<ClipboardContents>,
+    /// Recorded key macros by register.
+    macros: HashMap<char, Vec<event::KeyEvent>>,
+    /// The register and keys of the macro we are currently recording.
+    recording: Option<(char, Vec<event::KeyEvent>)>,
+    replaying: bool,
 }
 
 impl<'ws> Default for AppState<'ws> {
@@ -91,13 +188,22 @@ impl<'ws> Default for AppState<'ws> {
             modality_stack: vec![Modality::default()],
             viewport_state: Default::default(),
             command_state: Default::default(),
+            search_prompt: Default::default(),
+            search: Default::default(),
             numeric_prefix: Default::default(),
             char_queue: Default::default(),
             range_select: Default::default(),
             dialog_scroll: 0,
+            keymap: Default::default(),
+            status_message: None,
+            error_style: Style::default().fg(render::viewport::DEFAULT_ERROR_COLOR),
+            watch: None,
             dirty: false,
             popup: Default::default(),
             clipboard: Default::default(),
+            macros: Default::default(),
+            recording: None,
+            replaying: false,
         }
     }
 }
@@ -147,16 +253,31 @@ impl Address {
     }
 
     pub fn to_range_part(&self) -> String {
-        let count = if self.col == 26 {
-            1
-        } else {
-            (self.col / 26) + 1
-        };
-        format!(
-            "{}{}",
-            render::viewport::COLNAMES[(self.col - 1) % 26].repeat(count),
-            self.row
-        )
+        format!("{}{}", render::viewport::column_name(self.col), self.row)
+    }
+
+    /// Parse an A1 style address like `B7` or `AA100` in a sheet. Column
+    /// letters may be lowercase.
+    pub fn from_a1(sheet: u32, text: &str) -> Result<Self> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let (letters, digits) = text.split_at(split);
+        if letters.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow!("Invalid cell address: {}", text));
+        }
+        let mut col: usize = 0;
+        for c in letters.chars() {
+            col = col
+                .saturating_mul(26)
+                .saturating_add((c.to_ascii_uppercase() as u8 - b'A') as usize + 1);
+        }
+        let row = digits.parse::<usize>().unwrap_or(usize::MAX);
+        if row == 0 || row > book::LAST_ROW as usize || col > book::LAST_COLUMN as usize {
+            return Err(anyhow!("Cell address is outside of the sheet: {}", text));
+        }
+        Ok(Self { sheet, row, col })
     }
 }
 
@@ -196,20 +317,46 @@ impl<'ws> Workspace<'ws> {
 
     /// Loads a workspace from a path.
     pub fn load(path: &PathBuf, locale: &str, tz: &str) -> Result<Self> {
-        let book = load_book(path, locale, tz)?;
-        Ok(Workspace::new(book, path.clone()))
+        let mut book = load_book(path, locale, tz)?;
+        book.meta = Sidecar::load(path);
+        let mut ws = Workspace::new(book, path.clone());
+        ws.restore_position();
+        Ok(ws)
     }
 
     /// Loads a new `Book` into a `Workspace` from a path.
     pub fn load_into<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
         let path: PathBuf = path.into();
         // FIXME(zaphar): This should be managed better.
-        let book = load_book(&path, "en", "America/New_York")?;
+        let mut book = load_book(&path, "en", "America/New_York")?;
+        book.meta = Sidecar::load(&path);
         self.book = book;
         self.name = path;
+        self.state.viewport_state = Default::default();
+        self.restore_position();
         Ok(())
     }
 
+    /// Restore the cursor and viewport position stored in our sidecar metadata.
+    /// Positions that don't exist in the book are ignored.
+    fn restore_position(&mut self) {
+        if let Some(location) = self.book.meta.location.clone() {
+            if location.row >= 1
+                && location.col >= 1
+                && location.row <= book::LAST_ROW as usize
+                && location.col <= book::LAST_COLUMN as usize
+                && self.book.select_sheet_by_idx(location.sheet as usize)
+            {
+                self.book.location = location;
+                if let Some(corner) = self.book.meta.viewport_corner.clone() {
+                    self.state.viewport_state.set_corner(corner);
+                }
+            }
+        }
+        self.book.dirty = false;
+        self.handle_movement_change();
+    }
+
     pub fn selected_range_to_string(&self) -> String {
         let state = &self.state;
         if let Some((start, end)) = state.range_select.get_range() {
@@ -237,8 +384,8 @@ impl<'ws> Workspace<'ws> {
     /// Move a row down in the current sheet.
     pub fn move_down(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
-        if loc.row < (book::LAST_ROW as usize) {
-            loc.row += 1;
+        if let Some(row) = self.book.next_visible_row(loc.sheet, loc.row) {
+            loc.row = row;
             self.book.move_to(&loc)?;
         }
         Ok(())
@@ -257,8 +404,8 @@ impl<'ws> Workspace<'ws> {
     /// Move a row up in the current sheet.
     pub fn move_up(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
-        if loc.row > 1 {
-            loc.row -= 1;
+        if let Some(row) = self.book.prev_visible_row(loc.sheet, loc.row) {
+            loc.row = row;
             self.book.move_to(&loc)?;
         }
         Ok(())
@@ -267,8 +414,8 @@ impl<'ws> Workspace<'ws> {
     /// Move a column to the left in the current sheet.
     pub fn move_left(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
-        if loc.col > 1 {
-            loc.col -= 1;
+        if let Some(col) = self.book.prev_visible_col(loc.sheet, loc.col) {
+            loc.col = col;
             self.book.move_to(&loc)?;
         }
         Ok(())
@@ -277,8 +424,8 @@ impl<'ws> Workspace<'ws> {
     /// Move a column to the left in the current sheet.
     pub fn move_right(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
-        if loc.col < (book::LAST_COLUMN as usize) {
-            loc.col += 1;
+        if let Some(col) = self.book.next_visible_col(loc.sheet, loc.col) {
+            loc.col = col;
             self.book.move_to(&loc)?;
         }
         Ok(())
@@ -287,19 +434,103 @@ impl<'ws> Workspace<'ws> {
     /// Handle input in our ui loop.
     pub fn handle_input(&mut self, evt: Event) -> Result<Option<ExitCode>> {
         if let Event::Key(key) = evt {
+            if !self.state.replaying && key.kind == KeyEventKind::Press {
+                if let Some((_, keys)) = self.state.recording.as_mut() {
+                    keys.push(key);
+                }
+            }
+            if key.kind == KeyEventKind::Press {
+                self.state.status_message = None;
+            }
             let result = match self.state.modality() {
                 Modality::Navigate => self.handle_navigation_input(key)?,
                 Modality::CellEdit => self.handle_edit_input(key)?,
                 Modality::Command => self.handle_command_input(key)?,
+                Modality::Search => self.handle_search_input(key)?,
                 Modality::Dialog => self.handle_dialog_input(key)?,
                 Modality::RangeSelect => self.handle_range_select_input(key)?,
                 Modality::Quit => self.handle_quit_dialog(key)?,
+                Modality::Reload => self.handle_reload_dialog(key)?,
             };
+            // NOTE(zaphar): Keep the formula bar in sync with the cursor unless
+            // we are in the middle of editing a cell.
+            if !self.state.modality_stack.contains(&Modality::CellEdit) {
+                self.handle_movement_change();
+            }
             return Ok(result);
         }
+        if let Event::Mouse(mouse) = evt {
+            self.handle_mouse_input(mouse)?;
+            if !self.state.modality_stack.contains(&Modality::CellEdit) {
+                self.handle_movement_change();
+            }
+        }
         Ok(None)
     }
 
+    /// Clicking a cell moves the cursor to it and dragging selects a range.
+    /// The scroll wheel moves the cursor or scrolls an open dialog.
+    fn handle_mouse_input(&mut self, mouse: event::MouseEvent) -> Result<()> {
+        let modality = self.state.modality().clone();
+        let sheet = self.book.location.sheet;
+        let address = self
+            .state
+            .viewport_state
+            .address_at(sheet, mouse.column, mouse.row);
+        match (mouse.kind, modality) {
+            (MouseEventKind::ScrollDown, Modality::Dialog) => {
+                self.state.dialog_scroll =
+                    self.state.dialog_scroll.saturating_add(MOUSE_SCROLL_LINES);
+            }
+            (MouseEventKind::ScrollUp, Modality::Dialog) => {
+                self.state.dialog_scroll =
+                    self.state.dialog_scroll.saturating_sub(MOUSE_SCROLL_LINES);
+            }
+            (MouseEventKind::ScrollDown, Modality::Navigate | Modality::RangeSelect) => {
+                for _ in 0..MOUSE_SCROLL_LINES {
+                    self.move_down()?;
+                }
+                self.maybe_update_range_end();
+            }
+            (MouseEventKind::ScrollUp, Modality::Navigate | Modality::RangeSelect) => {
+                for _ in 0..MOUSE_SCROLL_LINES {
+                    self.move_up()?;
+                }
+                self.maybe_update_range_end();
+            }
+            (MouseEventKind::Down(MouseButton::Left), Modality::Navigate) => {
+                if let Some(address) = address {
+                    self.book.move_to(&address)?;
+                }
+            }
+            (MouseEventKind::Down(MouseButton::Left), Modality::RangeSelect) => {
+                if let Some(address) = address {
+                    self.book.move_to(&address)?;
+                    self.state.range_select.start = Some(address);
+                    self.state.range_select.end = None;
+                }
+            }
+            (MouseEventKind::Drag(MouseButton::Left), Modality::Navigate) => {
+                if let Some(address) = address {
+                    // The drag started on the cell under the cursor.
+                    self.enter_range_select_mode(true);
+                    self.book.move_to(&address)?;
+                    self.maybe_update_range_end();
+                }
+            }
+            (MouseEventKind::Drag(MouseButton::Left), Modality::RangeSelect) => {
+                if let Some(address) = address {
+                    self.book.move_to(&address)?;
+                    self.maybe_update_range_end();
+                }
+            }
+            _ => {
+                // NOOP
+            }
+        }
+        Ok(())
+    }
+
     fn render_help_text(&self) -> Markdown {
         // TODO(zaphar): We should be sourcing these from our actual help documentation.
         // Ideally we would also render the markdown content properly.
@@ -330,6 +561,67 @@ impl<'ws> Workspace<'ws> {
         Ok(None)
     }
 
+    fn handle_search_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press {
+            match key.code {
+                KeyCode::Esc => {
+                    self.exit_search_mode();
+                    self.state.search = SearchState {
+                        case_sensitive: self.state.search.case_sensitive,
+                        ..Default::default()
+                    };
+                    return Ok(None);
+                }
+                KeyCode::Enter => {
+                    self.exit_search_mode();
+                    self.move_to_search_match(true)?;
+                    return Ok(None);
+                }
+                KeyCode::Char('c') if key.modifiers == KeyModifiers::ALT => {
+                    self.state.search.case_sensitive = !self.state.search.case_sensitive;
+                    self.refresh_search()?;
+                    return Ok(None);
+                }
+                _ => {
+                    // NOOP
+                }
+            }
+        }
+        self.state.search_prompt.handle_key_event(key);
+        self.state.search.query = self.state.search_prompt.value().to_owned();
+        self.refresh_search()?;
+        Ok(None)
+    }
+
+    /// Find the cells in the current sheet matching the search query.
+    fn refresh_search(&mut self) -> Result<()> {
+        self.state.search.matches = self.book.find_cells(
+            self.book.location.sheet,
+            &self.state.search.query,
+            self.state.search.case_sensitive,
+        )?;
+        Ok(())
+    }
+
+    /// Move the cursor to the next or previous search match.
+    fn move_to_search_match(&mut self, forward: bool) -> Result<()> {
+        if self.state.search.query.is_empty() {
+            return Ok(());
+        }
+        self.refresh_search()?;
+        match self.state.search.next_match(&self.book.location, forward) {
+            Some(address) => {
+                let address = address.clone();
+                self.book.move_to(&address)?;
+            }
+            None => {
+                self.state.status_message =
+                    Some(format!("Pattern not found: {}", self.state.search.query));
+            }
+        }
+        Ok(())
+    }
+
     fn handle_quit_dialog(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
         if key.kind == KeyEventKind::Press {
             match key.code {
@@ -349,9 +641,105 @@ impl<'ws> Workspace<'ws> {
         Ok(None)
     }
 
+    fn handle_reload_dialog(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
+                    // Keep our edits. Saving them will overwrite the external change.
+                    self.state.pop_modality();
+                }
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    self.state.pop_modality();
+                    self.reload();
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Start watching our file for changes made outside of sheetui.
+    pub fn enable_watch(&mut self) {
+        self.state.watch = Some(watch::FileWatch::new(watch::modified_time(&self.name)));
+    }
+
+    /// Check our file for changes made outside of sheetui and reload it if
+    /// it changed. Called periodically by the ui loop while idle.
+    pub fn check_for_external_change(&mut self) {
+        let modified = watch::modified_time(&self.name);
+        self.handle_file_observed(modified, std::time::Instant::now())
+    }
+
+    /// Handle an observed modification time for our file. Unsaved edits are
+    /// never discarded without asking first.
+    fn handle_file_observed(
+        &mut self,
+        modified: Option<std::time::SystemTime>,
+        now: std::time::Instant,
+    ) {
+        // NOTE(zaphar): We only reload from navigation mode so we don't yank the
+        // book out from under an edit in progress. The change stays pending
+        // until we are back in navigation mode.
+        if self.state.modality() != &Modality::Navigate {
+            return;
+        }
+        // NOTE(zaphar): A file that is gone was deleted or is in the middle of
+        // being renamed. Loading it would swap our book for an empty one so we
+        // wait for it to come back.
+        if modified.is_none() {
+            return;
+        }
+        let changed = match self.state.watch.as_mut() {
+            Some(watch) => watch.observe(modified, now),
+            None => false,
+        };
+        if changed {
+            if self.book.dirty {
+                self.state.modality_stack.push(Modality::Reload);
+            } else {
+                self.reload();
+            }
+        }
+    }
+
+    /// Reload our book from disk keeping the cursor where it is. A file that
+    /// fails to load, like one caught partway through a write, leaves our book
+    /// alone and the error is shown in the status bar.
+    fn reload(&mut self) {
+        let location = self.book.location.clone();
+        let corner = self.state.viewport_state.corner().clone();
+        if let Err(e) = self.load_into(self.name.clone()) {
+            self.state.status_message = Some(format!("Unable to reload: {}", e));
+            return;
+        }
+        if self.book.select_sheet_by_idx(location.sheet as usize) {
+            self.book.location = location;
+            self.state.viewport_state.set_corner(corner);
+        }
+        self.handle_movement_change();
+    }
+
     fn handle_dialog_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
         if key.kind == KeyEventKind::Press {
+            let pending = self
+                .state
+                .popup
+                .as_ref()
+                .map_or(false, |widget| widget.has_pending_selection());
             match key.code {
+                KeyCode::Enter if pending => {
+                    if let Some(link) = self
+                        .state
+                        .popup
+                        .as_mut()
+                        .and_then(|widget| widget.handle_input(KeyCode::Enter))
+                    {
+                        if let Err(e) = open_link(&link) {
+                            self.state.status_message = Some(e.to_string());
+                        }
+                        self.exit_dialog_mode()?;
+                    }
+                }
                 KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.exit_dialog_mode()?,
                 KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
                     self.exit_dialog_mode()?
@@ -363,8 +751,16 @@ impl<'ws> Workspace<'ws> {
                     self.state.dialog_scroll = self.state.dialog_scroll.saturating_sub(1);
                 }
                 code => {
-                    if let Some(widget) = &self.state.popup {
-                        widget.handle_input(code);
+                    if let Some(link) = self
+                        .state
+                        .popup
+                        .as_mut()
+                        .and_then(|widget| widget.handle_input(code))
+                    {
+                        if let Err(e) = open_link(&link) {
+                            self.state.status_message = Some(e.to_string());
+                        }
+                        self.exit_dialog_mode()?;
                     }
                 }
             }
@@ -379,7 +775,12 @@ impl<'ws> Workspace<'ws> {
                     self.enter_dialog_mode(self.render_help_text());
                     return Ok(None);
                 }
-                KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                // NOTE(zaphar): `Alt-r` matches navigation mode where `Ctrl-r` is
+                // redo. `Ctrl-r` still works here since there is nothing to redo.
+                KeyCode::Char('r')
+                    if key.modifiers == KeyModifiers::ALT
+                        || key.modifiers == KeyModifiers::CONTROL =>
+                {
                     self.enter_range_select_mode(false);
                     return Ok(None);
                 }
@@ -428,9 +829,46 @@ impl<'ws> Workspace<'ws> {
                 }
                 Ok(None)
             }
-            Ok(Some(Cmd::ExportCsv(path))) => {
+            Ok(Some(Cmd::ExportCsv(path, delimiter, header))) => {
+                let delimiter = delimiter
+                    .or_else(|| delimiter_for_path(&PathBuf::from(path)))
+                    .unwrap_or(b',');
+                self.book.save_range_to_delimited(
+                    self.book.location.sheet,
+                    self.state.range_select.get_range(),
+                    delimiter,
+                    header,
+                    path,
+                )?;
+                Ok(None)
+            }
+            Ok(Some(Cmd::Goto(address))) => {
+                match Address::from_a1(self.book.location.sheet, address) {
+                    Ok(address) => {
+                        self.book.move_to(&address)?;
+                        self.handle_movement_change();
+                    }
+                    Err(e) => self.state.status_message = Some(e.to_string()),
+                }
+                Ok(None)
+            }
+            Ok(Some(Cmd::ImportCsv(path, delimiter))) => {
+                let contents = std::fs::read_to_string(path)?;
+                let delimiter = delimiter.unwrap_or_else(|| book::detect_delimiter(&contents));
                 self.book
-                    .save_sheet_to_csv(self.book.location.sheet, path)?;
+                    .import_delimited(self.book.location.sheet, &contents, delimiter)?;
+                Ok(None)
+            }
+            Ok(Some(Cmd::ExportMarkdown(path))) => {
+                let table = if let Some((start, end)) = self.state.range_select.get_range() {
+                    self.book.range_to_markdown(AddressRange {
+                        start: &start,
+                        end: &end,
+                    })?
+                } else {
+                    self.book.sheet_to_markdown(self.book.location.sheet)?
+                };
+                std::fs::write(path, table)?;
                 Ok(None)
             }
             Ok(Some(Cmd::InsertColumns(count))) => {
@@ -443,6 +881,70 @@ impl<'ws> Workspace<'ws> {
                 self.book.evaluate();
                 Ok(None)
             }
+            Ok(Some(Cmd::DeleteColumns(count))) => {
+                // NOTE(zaphar): Locked cells make this fail so we report it
+                // instead of bailing.
+                match self.book.delete_columns(self.book.location.col, count) {
+                    Ok(()) => self.book.evaluate(),
+                    Err(e) => self.state.status_message = Some(e.to_string()),
+                }
+                self.handle_movement_change();
+                Ok(None)
+            }
+            Ok(Some(Cmd::HideRows(count))) => {
+                self.book.hide_rows(self.book.location.row, count);
+                // Move off of the rows we just hid.
+                let Address { sheet, row, .. } = self.book.location.clone();
+                if self.book.is_row_hidden(sheet, row) {
+                    self.move_down()?;
+                }
+                if self.book.is_row_hidden(sheet, self.book.location.row) {
+                    self.move_up()?;
+                }
+                self.handle_movement_change();
+                Ok(None)
+            }
+            Ok(Some(Cmd::HideColumns(count))) => {
+                self.book.hide_columns(self.book.location.col, count);
+                // Move off of the columns we just hid.
+                let Address { sheet, col, .. } = self.book.location.clone();
+                if self.book.is_col_hidden(sheet, col) {
+                    self.move_right()?;
+                }
+                if self.book.is_col_hidden(sheet, self.book.location.col) {
+                    self.move_left()?;
+                }
+                self.handle_movement_change();
+                Ok(None)
+            }
+            Ok(Some(Cmd::UnhideRows)) => {
+                self.book.unhide_rows();
+                Ok(None)
+            }
+            Ok(Some(Cmd::UnhideColumns)) => {
+                self.book.unhide_columns();
+                Ok(None)
+            }
+            Ok(Some(Cmd::Freeze(counts))) => {
+                let (rows, cols) =
+                    counts.unwrap_or((self.book.location.row - 1, self.book.location.col - 1));
+                self.book.freeze(rows, cols);
+                Ok(None)
+            }
+            Ok(Some(Cmd::Unfreeze)) => {
+                self.book.freeze(0, 0);
+                Ok(None)
+            }
+            Ok(Some(Cmd::DeleteRows(count))) => {
+                // NOTE(zaphar): Locked cells make this fail so we report it
+                // instead of bailing.
+                match self.book.delete_rows(self.book.location.row, count) {
+                    Ok(()) => self.book.evaluate(),
+                    Err(e) => self.state.status_message = Some(e.to_string()),
+                }
+                self.handle_movement_change();
+                Ok(None)
+            }
             Ok(Some(Cmd::RenameSheet(idx, name))) => {
                 match idx {
                     Some(idx) => {
@@ -458,10 +960,26 @@ impl<'ws> Workspace<'ws> {
                 self.book.new_sheet(name)?;
                 Ok(None)
             }
+            Ok(Some(Cmd::DeleteSheet)) => {
+                if let Err(e) = self.book.delete_sheet() {
+                    self.state.status_message = Some(e.to_string());
+                }
+                Ok(None)
+            }
             Ok(Some(Cmd::SelectSheet(name))) => {
                 self.book.select_sheet_by_name(name);
                 Ok(None)
             }
+            Ok(Some(Cmd::Note(text))) => {
+                let address = self.book.location.clone();
+                self.book.set_note(&address, text);
+                Ok(None)
+            }
+            Ok(Some(Cmd::ClearNote)) => {
+                let address = self.book.location.clone();
+                self.book.remove_note(&address);
+                Ok(None)
+            }
             Ok(Some(Cmd::Quit)) => self.quit_app(),
             Ok(Some(Cmd::ColorRows(count, color))) => {
                 let row_count = count.unwrap_or(1);
@@ -487,30 +1005,110 @@ impl<'ws> Workspace<'ws> {
                 }
                 Ok(None)
             }
+            Ok(Some(Cmd::FormatCell(format))) => {
+                let area = self.selected_area();
+                self.book.set_cell_format(format, &area)?;
+                Ok(None)
+            }
+            Ok(Some(Cmd::FormatColumns(count, format))) => {
+                let col = self.book.location.col;
+                for c in col..(col + count.unwrap_or(1)) {
+                    self.book
+                        .set_col_format(format, self.book.location.sheet, c)?;
+                }
+                Ok(None)
+            }
+            Ok(Some(Cmd::AlignCell(alignment))) => {
+                let area = self.selected_area();
+                self.book.set_cell_alignment(alignment, &area)?;
+                Ok(None)
+            }
+            Ok(Some(Cmd::AlignColumns(count, alignment))) => {
+                let col = self.book.location.col;
+                for c in col..(col + count.unwrap_or(1)) {
+                    self.book
+                        .set_col_alignment(alignment, self.book.location.sheet, c)?;
+                }
+                Ok(None)
+            }
             Ok(Some(Cmd::ColorCell(color))) => {
-                let sheet = self.book.location.sheet;
-                let area = if let Some((start, end)) = self.state.range_select.get_range() {
-                    Area {
-                        sheet,
-                        row: start.row as i32,
-                        column: start.col as i32,
-                        width: (end.col - start.col + 1) as i32,
-                        height: (end.row - start.row + 1) as i32,
-                    }
-                } else {
-                    let address = self.book.location.clone();
-                    Area {
-                        sheet,
-                        row: address.row as i32,
-                        column: address.col as i32,
-                        width: 1,
-                        height: 1,
-                    }
-                };
+                let area = self.selected_area();
                 self.book
                     .set_cell_style(&[("fill.bg_color", &color)], &area)?;
                 Ok(None)
             }
+            Ok(Some(Cmd::ConditionalFormat(condition, color))) => {
+                let (start, end) = self.state.range_select.get_range().unwrap_or_else(|| {
+                    (self.book.location.clone(), self.book.location.clone())
+                });
+                self.book.add_conditional_format(ConditionalFormat {
+                    start,
+                    end,
+                    condition,
+                    bg_color: Some(color),
+                    fg_color: None,
+                });
+                Ok(None)
+            }
+            Ok(Some(Cmd::WrapText)) => {
+                let address = self.book.location.clone();
+                let wrapped = self.book.is_cell_wrapped(&address);
+                let area = self.selected_area();
+                self.book.set_cell_style(
+                    &[("alignment.wrap_text", if wrapped { "false" } else { "true" })],
+                    &area,
+                )?;
+                Ok(None)
+            }
+            Ok(Some(Cmd::AutoFit)) => {
+                let area = self.selected_area();
+                for col in area.column..area.column + area.width {
+                    self.book.auto_fit_column(col as usize)?;
+                }
+                Ok(None)
+            }
+            Ok(Some(Cmd::Transpose)) => {
+                if let Some((start, end)) = self.state.range_select.get_range() {
+                    // NOTE(zaphar): The only way this fails is a locked cell so
+                    // we report it instead of bailing.
+                    if let Err(e) = self.book.transpose_range(&start, &end) {
+                        self.state.status_message = Some(e.to_string());
+                    }
+                }
+                Ok(None)
+            }
+            Ok(Some(Cmd::Sort(descending))) => {
+                let location = self.book.location.clone();
+                let range = self.state.range_select.get_range();
+                // NOTE(zaphar): This fails for locked cells or a cursor outside
+                // the selection so we report it instead of bailing.
+                if let Err(e) = self
+                    .book
+                    .sort_rows(location.sheet, range, location.col, descending)
+                {
+                    self.state.status_message = Some(e.to_string());
+                }
+                Ok(None)
+            }
+            Ok(Some(Cmd::Lock)) => {
+                let (start, end) = self.state.range_select.get_range().unwrap_or_else(|| {
+                    (self.book.location.clone(), self.book.location.clone())
+                });
+                self.book.lock_range(&start, &end);
+                Ok(None)
+            }
+            Ok(Some(Cmd::Unlock)) => {
+                let (start, end) = self.state.range_select.get_range().unwrap_or_else(|| {
+                    (self.book.location.clone(), self.book.location.clone())
+                });
+                self.book.unlock_range(&start, &end);
+                Ok(None)
+            }
+            Ok(Some(Cmd::ClearConditionalFormat)) => {
+                let address = self.book.location.clone();
+                self.book.clear_conditional_formats(&address);
+                Ok(None)
+            }
             Ok(None) => {
                 self.enter_dialog_mode(Markdown::from_str(&format!(
                     "Unrecognized commmand {}",
@@ -525,6 +1123,29 @@ impl<'ws> Workspace<'ws> {
         }
     }
 
+    /// The `Area` of the selected range or the current cell if there is no range selected.
+    fn selected_area(&self) -> Area {
+        let sheet = self.book.location.sheet;
+        if let Some((start, end)) = self.state.range_select.get_range() {
+            Area {
+                sheet,
+                row: start.row as i32,
+                column: start.col as i32,
+                width: (end.col - start.col + 1) as i32,
+                height: (end.row - start.row + 1) as i32,
+            }
+        } else {
+            let address = self.book.location.clone();
+            Area {
+                sheet,
+                row: address.row as i32,
+                column: address.col as i32,
+                width: 1,
+                height: 1,
+            }
+        }
+    }
+
     fn handle_numeric_prefix(&mut self, digit: char) {
         self.state.numeric_prefix.push(digit);
     }
@@ -550,36 +1171,40 @@ impl<'ws> Workspace<'ws> {
                 }
                 KeyCode::Char('D') => {
                     if let Some((start, end)) = self.state.range_select.get_range() {
-                        self.book.clear_cell_range_all(start, end)?;
+                        if !self.reject_locked_range(&start, &end) {
+                            self.book.clear_cell_range_all(start, end)?;
+                        }
                     }
                 }
-                KeyCode::Char('d') => {
+                KeyCode::Char('d') if key.modifiers == KeyModifiers::NONE => {
                     if let Some((start, end)) = self.state.range_select.get_range() {
-                        self.book.clear_cell_range(start, end)?;
+                        if !self.reject_locked_range(&start, &end) {
+                            self.book.clear_cell_range(start, end)?;
+                        }
                     }
                 }
-                KeyCode::Char('h') => {
+                KeyCode::Char('h') | KeyCode::Left => {
                     self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
                         ws.move_left()?;
                         Ok(())
                     })?;
                     self.maybe_update_range_end();
                 }
-                KeyCode::Char('j') => {
+                KeyCode::Char('j') | KeyCode::Down => {
                     self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
                         ws.move_down()?;
                         Ok(())
                     })?;
                     self.maybe_update_range_end();
                 }
-                KeyCode::Char('k') => {
+                KeyCode::Char('k') | KeyCode::Up => {
                     self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
                         ws.move_up()?;
                         Ok(())
                     })?;
                     self.maybe_update_range_end();
                 }
-                KeyCode::Char('l') => {
+                KeyCode::Char('l') | KeyCode::Right => {
                     self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
                         ws.move_right()?;
                         Ok(())
@@ -621,12 +1246,38 @@ impl<'ws> Workspace<'ws> {
                     self.copy_range(false)?;
                     self.exit_range_select_mode()?;
                 }
+                KeyCode::Char('d') | KeyCode::Char('r')
+                    if key.modifiers == KeyModifiers::CONTROL
+                        || key.modifiers == KeyModifiers::ALT =>
+                {
+                    let series = key.modifiers == KeyModifiers::ALT;
+                    if let Some((start, end)) = self.state.range_select.get_range() {
+                        if key.code == KeyCode::Char('d') {
+                            self.book.fill_down(&start, &end, series)?;
+                        } else {
+                            self.book.fill_right(&start, &end, series)?;
+                        }
+                    }
+                    self.exit_range_select_mode()?;
+                }
+                KeyCode::Char('=') => {
+                    if let Some((start, end)) = self.state.range_select.get_range() {
+                        self.book.begin_edit();
+                        let result = self.book.auto_sum(&start, &end);
+                        self.book.end_edit();
+                        result?;
+                    }
+                    self.exit_range_select_mode()?;
+                }
                 KeyCode::Char('x') => {
                     if let (Some(from), Some(to)) = (
                         self.state.range_select.start.as_ref(),
                         self.state.range_select.end.as_ref(),
                     ) {
-                        self.book.extend_to(from, to)?;
+                        self.book.begin_edit();
+                        let result = self.book.extend_to(from, to);
+                        self.book.end_edit();
+                        result?;
                     }
                     self.exit_range_select_mode()?;
                 }
@@ -642,7 +1293,6 @@ impl<'ws> Workspace<'ws> {
     }
 
     fn copy_range(&mut self, formatted: bool) -> Result<(), anyhow::Error> {
-        use arboard::Clipboard;
         self.update_range_selection()?;
         match &self.state.range_select.get_range() {
             Some((start, end)) => {
@@ -659,19 +1309,20 @@ impl<'ws> Workspace<'ws> {
                     rows.push(cols);
                 }
                 // TODO(zaphar): Rethink this a bit perhaps?
-                let mut cb = Clipboard::new()?;
-                let (html, csv) = self
+                let (html, tsv) = self
                     .book
                     .range_to_clipboard_content(AddressRange { start, end })?;
-                cb.set_html(html, Some(csv))?;
+                clipboard::set_html(html, tsv);
                 self.state.clipboard = Some(ClipboardContents::Range(rows));
             }
             None => {
-                self.state.clipboard = Some(ClipboardContents::Cell(if formatted {
+                let contents = if formatted {
                     self.book.get_current_cell_rendered()?
                 } else {
                     self.book.get_current_cell_contents()?
-                }));
+                };
+                clipboard::set_text(contents.clone());
+                self.state.clipboard = Some(ClipboardContents::Cell(contents));
             }
         }
         Ok(())
@@ -696,175 +1347,66 @@ impl<'ws> Workspace<'ws> {
 
     fn handle_navigation_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
         if key.kind == KeyEventKind::Press {
+            // NOTE(zaphar): A `d` already cleared its cell so anything but a
+            // second `d` is handled as if the `d` was never queued.
+            if self.state.char_queue.first() == Some(&'d') && key.code != KeyCode::Char('d') {
+                self.state.char_queue.clear();
+            }
+            // NOTE(zaphar): Multi key sequences like `gg` bypass the keymap until
+            // they are complete.
+            if self.state.char_queue.is_empty() {
+                if let Some(action) = self.state.keymap.get(&key) {
+                    // `d` clears the cell right away and also starts `dd`.
+                    if action == Action::ClearCell && key.code == KeyCode::Char('d') {
+                        self.state.char_queue.push('d');
+                    }
+                    return self.handle_navigation_action(action);
+                }
+            }
             match key.code {
                 KeyCode::Esc => {
                     self.state.reset_n_prefix();
                     self.state.char_queue.clear();
                 }
-                KeyCode::Char('B') => {
-                    let address = self.book.location.clone();
-                    let style = self.book.get_cell_style(&address).map(|s| s.font.b);
-                    self.toggle_bool_style(style, "font.b", &address)?;
-                }
-                KeyCode::Char('I') => {
-                    let address = self.book.location.clone();
-                    let style = self.book.get_cell_style(&address).map(|s| s.font.i);
-                    self.toggle_bool_style(style, "font.i", &address)?;
-                }
-                KeyCode::Char(d) if d.is_ascii_digit() => {
-                    self.handle_numeric_prefix(d);
-                }
-                KeyCode::Char('e') | KeyCode::Char('i') => {
-                    self.enter_edit_mode();
-                }
-                KeyCode::Char(':') => {
-                    self.enter_command_mode();
-                }
-                KeyCode::Char('s') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.save_file()?;
-                }
-                KeyCode::Char('s') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.book.clear_current_cell()?;
-                    self.text_area = reset_text_area(String::new());
-                    self.enter_edit_mode();
-                }
-                KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.enter_range_select_mode(false);
-                }
-                KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.state.clipboard = Some(ClipboardContents::Cell(
-                        self.book.get_current_cell_contents()?,
-                    ));
-                }
-                KeyCode::Char('y') => {
-                    self.state.clipboard = Some(ClipboardContents::Cell(
-                        self.book.get_current_cell_contents()?,
-                    ));
-                }
-                KeyCode::Char('Y') => {
-                    self.state.clipboard = Some(ClipboardContents::Cell(
-                        self.book.get_current_cell_rendered()?,
-                    ));
-                }
-                KeyCode::Char('C') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.state.clipboard = Some(ClipboardContents::Cell(
-                        self.book.get_current_cell_rendered()?,
-                    ));
-                }
-                KeyCode::Char('v') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.enter_range_select_mode(true)
-                }
-                KeyCode::Char('p') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.paste_range()?;
-                }
-                KeyCode::Char('v') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.paste_range()?;
-                }
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.enter_dialog_mode(self.render_help_text());
-                }
-                KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_next_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('d') => {
-                    self.book.clear_current_cell()?;
-                }
-                KeyCode::Char('D') => {
-                    self.book.clear_current_cell_all()?;
-                }
-                KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_prev_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('l') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        let Address {
-                            sheet: _,
-                            row: _,
-                            col,
-                        } = &ws.book.location;
-                        ws.book
-                            .set_col_size(*col, ws.book.get_col_size(*col)? + 1)?;
-                        Ok(())
-                    })?;
+                KeyCode::Char(reg) if self.state.char_queue.first() == Some(&'q') => {
+                    self.state.char_queue.pop();
+                    if reg.is_ascii_alphanumeric() {
+                        self.state.recording = Some((reg, Vec::new()));
+                    }
                 }
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        let Address {
-                            sheet: _,
-                            row: _,
-                            col,
-                        } = &ws.book.location;
-                        let curr_size = ws.book.get_col_size(*col)?;
-                        if curr_size > 1 {
-                            ws.book.set_col_size(*col, curr_size - 1)?;
+                KeyCode::Char(reg) if self.state.char_queue.first() == Some(&'@') => {
+                    self.state.char_queue.pop();
+                    let count = self.state.get_n_prefix();
+                    self.state.reset_n_prefix();
+                    for _ in 0..count {
+                        if let Some(code) = self.replay_macro(reg)? {
+                            return Ok(Some(code));
                         }
-                        Ok(())
-                    })?;
+                    }
                 }
                 KeyCode::Char('q') => {
-                    return self.quit_app();
-                }
-                KeyCode::Char('j') | KeyCode::Down if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_down()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Enter if key.modifiers != KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_down()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Enter if key.modifiers == KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_up()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('k') | KeyCode::Up if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_up()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                    if let Some((reg, mut keys)) = self.state.recording.take() {
+                        // Drop the `q` that stopped the recording.
+                        keys.pop();
+                        self.state.macros.insert(reg, keys);
+                    } else {
+                        self.state.char_queue.push('q');
+                    }
                 }
-                KeyCode::Char('h') | KeyCode::Left if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_left()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char('@') => {
+                    self.state.char_queue.push('@');
                 }
-                KeyCode::Char('l') | KeyCode::Right if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_right()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Tab if key.modifiers != KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_right()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char('Z') => {
+                    // NOTE(zaphar): `q` records macros so quitting moved to `ZZ`.
+                    if self.state.char_queue.first() == Some(&'Z') {
+                        self.state.char_queue.pop();
+                        return self.quit_app();
+                    }
+                    self.state.char_queue.clear();
+                    self.state.char_queue.push('Z');
                 }
-                KeyCode::Tab if key.modifiers == KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_left()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char(d) if d.is_ascii_digit() => {
+                    self.handle_numeric_prefix(d);
                 }
                 KeyCode::Char('g') => {
                     // TODO(zaphar): This really needs a better state machine.
@@ -881,17 +1423,46 @@ impl<'ws> Workspace<'ws> {
                         self.state.char_queue.push('g');
                     }
                 }
-                KeyCode::Char('o') => {
-                    self.book.insert_rows(self.book.location.row + 1, 1)?;
-                    self.move_down()?;
+                KeyCode::Char('d') if self.state.char_queue.first() == Some(&'d') => {
+                    self.state.char_queue.pop();
+                    let count = self.state.get_n_prefix();
+                    self.state.reset_n_prefix();
+                    // NOTE(zaphar): This fails for locked cells or a count that runs
+                    // off the end of the sheet so we report it instead of bailing.
+                    match self.book.delete_rows(self.book.location.row, count) {
+                        Ok(()) => self.book.evaluate(),
+                        Err(e) => self.state.status_message = Some(e.to_string()),
+                    }
                     self.handle_movement_change();
-                    self.enter_edit_mode();
                 }
-                KeyCode::Char('O') => {
-                    self.book.insert_rows(self.book.location.row, 1)?;
-                    self.move_up()?;
+                KeyCode::Char('t') if self.state.char_queue.first() == Some(&'g') => {
+                    self.state.char_queue.pop();
+                    if self.state.numeric_prefix.is_empty() {
+                        self.book.select_next_sheet();
+                    } else {
+                        // NOTE(zaphar): Like vim `{n}gt` jumps to the nth tab rather than
+                        // moving n tabs forward.
+                        let idx = self.state.get_n_prefix();
+                        self.state.reset_n_prefix();
+                        self.book.select_sheet_by_idx(idx.saturating_sub(1));
+                    }
+                    self.handle_movement_change();
+                }
+                KeyCode::Char('x') if self.state.char_queue.first() == Some(&'g') => {
+                    self.state.char_queue.pop();
+                    let contents = self.book.get_current_cell_rendered()?;
+                    if let Some(md) = contents.strip_prefix(render::viewport::MARKDOWN_CELL_PREFIX)
+                    {
+                        self.enter_dialog_mode(Markdown::from_str(md));
+                    }
+                }
+                KeyCode::Char('T') if self.state.char_queue.first() == Some(&'g') => {
+                    self.state.char_queue.pop();
+                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                        ws.book.select_prev_sheet();
+                        Ok(())
+                    })?;
                     self.handle_movement_change();
-                    self.enter_edit_mode();
                 }
                 _ => {
                     // noop
@@ -902,6 +1473,197 @@ impl<'ws> Workspace<'ws> {
         return Ok(None);
     }
 
+    fn handle_navigation_action(&mut self, action: Action) -> Result<Option<ExitCode>> {
+        match action {
+            Action::MoveUp => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_up()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveDown => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_down()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveLeft => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_left()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveRight => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_right()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::NextSheet => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_next_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::PrevSheet => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_prev_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::EditCell => {
+                self.enter_edit_mode();
+            }
+            Action::SubstituteCell => {
+                if self.reject_locked_current() {
+                    return Ok(None);
+                }
+                self.book.clear_current_cell()?;
+                self.text_area = reset_text_area(String::new());
+                self.enter_edit_mode();
+            }
+            Action::CommandMode => {
+                self.enter_command_mode();
+            }
+            Action::SelectUp | Action::SelectDown | Action::SelectLeft | Action::SelectRight => {
+                // Start a selection at the current cell and extend it with the move.
+                self.enter_range_select_mode(true);
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    match action {
+                        Action::SelectUp => ws.move_up()?,
+                        Action::SelectDown => ws.move_down()?,
+                        Action::SelectLeft => ws.move_left()?,
+                        _ => ws.move_right()?,
+                    }
+                    Ok(())
+                })?;
+                self.maybe_update_range_end();
+            }
+            Action::RangeSelect => {
+                self.enter_range_select_mode(false);
+            }
+            Action::VisualSelect => {
+                self.enter_range_select_mode(true);
+            }
+            Action::Save => {
+                self.save_file()?;
+            }
+            Action::Help => {
+                self.enter_dialog_mode(self.render_help_text());
+            }
+            Action::Copy => {
+                let contents = self.book.get_current_cell_contents()?;
+                clipboard::set_text(contents.clone());
+                self.state.clipboard = Some(ClipboardContents::Cell(contents));
+            }
+            Action::CopyRendered => {
+                let contents = self.book.get_current_cell_rendered()?;
+                clipboard::set_text(contents.clone());
+                self.state.clipboard = Some(ClipboardContents::Cell(contents));
+            }
+            Action::Paste => {
+                self.paste_range(false)?;
+            }
+            Action::PasteTranspose => {
+                self.paste_range(true)?;
+            }
+            Action::ClearCell => {
+                if !self.reject_locked_current() {
+                    self.book.clear_current_cell()?;
+                }
+            }
+            Action::Search => {
+                self.enter_search_mode();
+            }
+            Action::SearchNext => {
+                self.move_to_search_match(true)?;
+            }
+            Action::SearchPrev => {
+                self.move_to_search_match(false)?;
+            }
+            Action::Undo => match self.book.undo() {
+                Ok(true) => {}
+                Ok(false) => self.state.status_message = Some("Nothing to undo".to_owned()),
+                Err(e) => self.state.status_message = Some(e.to_string()),
+            },
+            Action::Redo => match self.book.redo() {
+                Ok(true) => {}
+                Ok(false) => self.state.status_message = Some("Nothing to redo".to_owned()),
+                Err(e) => self.state.status_message = Some(e.to_string()),
+            },
+            Action::ClearCellAll => {
+                if !self.reject_locked_current() {
+                    self.book.clear_current_cell_all()?;
+                }
+            }
+            Action::ToggleBold => {
+                let address = self.book.location.clone();
+                let style = self.book.get_cell_style(&address).map(|s| s.font.b);
+                self.toggle_bool_style(style, "font.b", &address)?;
+            }
+            Action::ToggleItalic => {
+                let address = self.book.location.clone();
+                let style = self.book.get_cell_style(&address).map(|s| s.font.i);
+                self.toggle_bool_style(style, "font.i", &address)?;
+            }
+            Action::WidenColumn => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    let col = ws.book.location.col;
+                    ws.book.set_col_size(col, ws.book.get_col_size(col)? + 1)?;
+                    Ok(())
+                })?;
+            }
+            Action::NarrowColumn => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    let col = ws.book.location.col;
+                    let curr_size = ws.book.get_col_size(col)?;
+                    if curr_size > 1 {
+                        ws.book.set_col_size(col, curr_size - 1)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Action::InsertRowBelow => {
+                self.book.insert_rows(self.book.location.row + 1, 1)?;
+                self.move_down()?;
+                self.handle_movement_change();
+                self.enter_edit_mode();
+            }
+            Action::InsertRowAbove => {
+                self.book.insert_rows(self.book.location.row, 1)?;
+                self.move_up()?;
+                self.handle_movement_change();
+                self.enter_edit_mode();
+            }
+            Action::Quit => return self.quit_app(),
+        }
+        Ok(None)
+    }
+
+    /// Replay the keys recorded in a macro register through our input handler.
+    fn replay_macro(&mut self, reg: char) -> Result<Option<ExitCode>> {
+        // NOTE(zaphar): We don't support a macro replaying another macro. This
+        // keeps a macro that replays itself from looping forever.
+        if self.state.replaying {
+            return Ok(None);
+        }
+        let keys = self.state.macros.get(&reg).cloned().unwrap_or_default();
+        self.state.replaying = true;
+        let mut result = Ok(None);
+        for key in keys {
+            result = self.handle_input(Event::Key(key));
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+        self.state.replaying = false;
+        result
+    }
+
     fn toggle_bool_style(
         &mut self,
         current_val: Option<bool>,
@@ -926,29 +1688,64 @@ impl<'ws> Workspace<'ws> {
         Ok(())
     }
 
-    fn paste_range(&mut self) -> Result<(), anyhow::Error> {
+    /// Paste the clipboard at the current cell. If `transpose` is true then
+    /// the rows of a copied range are pasted as columns.
+    fn paste_range(&mut self, transpose: bool) -> Result<(), anyhow::Error> {
+        // Fall back to whatever another application put on the system clipboard.
+        if self.state.clipboard.is_none() {
+            // An empty system clipboard pastes nothing.
+            if let Some(text) = clipboard::get_text().filter(|t| !t.trim().is_empty()) {
+                let mut rows = book::clipboard_text_to_rows(&text)?;
+                self.state.clipboard = match (rows.len(), rows.first().map(|r| r.len())) {
+                    (0, _) => None,
+                    (1, Some(1)) => Some(ClipboardContents::Cell(rows.remove(0).remove(0))),
+                    _ => Some(ClipboardContents::Range(rows)),
+                };
+            }
+        }
         match &self.state.clipboard {
             Some(ClipboardContents::Cell(contents)) => {
+                let address = self.book.location.clone();
+                if self.book.is_locked(&address) {
+                    self.state.status_message = Some(locked_message(&address));
+                    return Ok(());
+                }
                 self.book.edit_current_cell(contents)?;
                 self.book.evaluate();
             }
             Some(ClipboardContents::Range(rows)) => {
                 let Address { sheet, row, col } = self.book.location.clone();
+                // The whole paste is undone in one step.
+                self.book.begin_edit();
                 let row_len = rows.len();
                 for ri in 0..row_len {
                     let columns = &rows[ri];
                     let col_len = columns.len();
                     for ci in 0..col_len {
-                        self.book.update_cell(
-                            &Address {
-                                sheet,
-                                row: ri + row,
-                                col: ci + col,
-                            },
-                            columns[ci].clone(),
-                        )?;
+                        let (ro, co) = if transpose { (ci, ri) } else { (ri, ci) };
+                        let address = Address {
+                            sheet,
+                            row: ro + row,
+                            col: co + col,
+                        };
+                        // Anything that would land past the edge of the sheet is
+                        // clipped.
+                        if address.row > book::LAST_ROW as usize
+                            || address.col > book::LAST_COLUMN as usize
+                        {
+                            continue;
+                        }
+                        // Locked cells keep their contents.
+                        if self.book.is_locked(&address) {
+                            continue;
+                        }
+                        if let Err(e) = self.book.update_cell(&address, columns[ci].clone()) {
+                            self.book.end_edit();
+                            return Err(e);
+                        }
                     }
                 }
+                self.book.end_edit();
                 self.book.evaluate();
             }
             None => {
@@ -1001,7 +1798,33 @@ impl<'ws> Workspace<'ws> {
         self.state.modality_stack.push(Modality::RangeSelect);
     }
 
+    /// Returns true and sets a status message if the current cell is locked.
+    fn reject_locked_current(&mut self) -> bool {
+        let address = self.book.location.clone();
+        if self.book.is_locked(&address) {
+            self.state.status_message = Some(locked_message(&address));
+            return true;
+        }
+        false
+    }
+
+    /// Returns true and sets a status message if any cell in the range is locked.
+    fn reject_locked_range(&mut self, start: &Address, end: &Address) -> bool {
+        if self.book.is_range_locked(start, end) {
+            self.state.status_message = Some(format!(
+                "Range {}:{} contains locked cells",
+                start.to_range_part(),
+                end.to_range_part()
+            ));
+            return true;
+        }
+        false
+    }
+
     fn enter_edit_mode(&mut self) {
+        if self.reject_locked_current() {
+            return;
+        }
         self.state.modality_stack.push(Modality::CellEdit);
         self.text_area
             .set_cursor_line_style(Style::default().add_modifier(Modifier::UNDERLINED));
@@ -1016,6 +1839,19 @@ impl<'ws> Workspace<'ws> {
         Ok(None)
     }
 
+    fn enter_search_mode(&mut self) {
+        self.state.modality_stack.push(Modality::Search);
+        self.state.search_prompt.truncate();
+        *self.state.search_prompt.status_mut() = Status::Pending;
+        self.state.search_prompt.focus();
+    }
+
+    fn exit_search_mode(&mut self) {
+        self.state.search_prompt.blur();
+        *self.state.search_prompt.status_mut() = Status::Done;
+        self.state.pop_modality();
+    }
+
     fn exit_command_mode(&mut self) -> Result<Option<ExitCode>> {
         let cmd = self.state.command_state.value().to_owned();
         self.state.command_state.blur();
@@ -1070,13 +1906,45 @@ impl<'ws> Workspace<'ws> {
     }
 
     fn save_file(&mut self) -> Result<()> {
-        self.book
-            .save_to_xlsx(&self.name.to_string_lossy().to_string())?;
+        let path = self.name.to_string_lossy().to_string();
+        // A delimited file is saved with the delimiter it was loaded with.
+        if let Some(delimiter) = self
+            .book
+            .delimiter
+            .or_else(|| delimiter_for_path(&self.name))
+        {
+            // NOTE(zaphar): Delimited files can only hold a single sheet so we save
+            // the one that is currently selected.
+            self.book
+                .save_sheet_to_delimited(self.book.location.sheet, delimiter, &path)?;
+            self.book.dirty = false;
+        } else {
+            self.book.save_to_xlsx(&path)?;
+        }
+        let name = self.name.clone();
+        self.save_sidecar(&name)?;
+        if let Some(watch) = self.state.watch.as_mut() {
+            watch.acknowledge(watch::modified_time(&name));
+        }
         Ok(())
     }
 
     fn save_to<S: Into<String>>(&mut self, path: S) -> Result<()> {
-        self.book.save_to_xlsx(path.into().as_str())?;
+        let path = path.into();
+        self.book.save_to_xlsx(path.as_str())?;
+        self.save_sidecar(&PathBuf::from(path))?;
+        Ok(())
+    }
+
+    fn save_sidecar(&mut self, path: &PathBuf) -> Result<()> {
+        // NOTE(zaphar): The cursor position alone isn't worth littering the
+        // directory with a sidecar file.
+        if !self.book.meta.has_metadata() && !Sidecar::path_for(path).exists() {
+            return Ok(());
+        }
+        self.book.meta.location = Some(self.book.location.clone());
+        self.book.meta.viewport_corner = Some(self.state.viewport_state.corner().clone());
+        self.book.meta.save(path)?;
         Ok(())
     }
 
@@ -1088,9 +1956,65 @@ impl<'ws> Workspace<'ws> {
     }
 }
 
+fn locked_message(address: &Address) -> String {
+    format!("Cell {} is locked", address.to_range_part())
+}
+
+/// The link schemes we are willing to hand to the platform's handler.
+const OPENABLE_SCHEMES: [&'static str; 3] = ["http://", "https://", "mailto:"];
+
+/// Open a link with the platform's default handler. Only web and mail links
+/// are opened since the link comes from the workbook and may not be trusted.
+fn open_link(link: &str) -> Result<()> {
+    let lower = link.trim().to_lowercase();
+    let openable = OPENABLE_SCHEMES
+        .iter()
+        .any(|scheme| lower.starts_with(scheme));
+    if !openable {
+        return Err(anyhow!(
+            "Not opening {}: only http, https, and mailto links can be opened",
+            link
+        ));
+    }
+    let mut cmd = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        // NOTE(zaphar): We avoid `cmd /C start` because cmd interprets
+        // characters like `&` and `|` in the link.
+        let mut cmd = std::process::Command::new("rundll32");
+        cmd.arg("url.dll,FileProtocolHandler");
+        cmd
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    cmd.arg(link)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Returns the delimiter to use if the path points to a delimited text file.
+fn delimiter_for_path(path: &PathBuf) -> Option<u8> {
+    match path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("csv") => Some(b','),
+        Some("tsv") | Some("tab") => Some(b'\t'),
+        _ => None,
+    }
+}
+
 fn load_book<'a>(path: &PathBuf, locale: &str, tz: &str) -> Result<Book<'a>, anyhow::Error> {
     let book = if path.exists() {
-        Book::new_from_xlsx_with_locale(&path.to_string_lossy().to_string(), locale, tz)?
+        let path_str = path.to_string_lossy().to_string();
+        if delimiter_for_path(path).is_some() {
+            Book::new_from_delimited_with_locale(&path_str, locale, tz)?
+        } else {
+            Book::new_from_xlsx_with_locale(&path_str, locale, tz)?
+        }
     } else {
         Book::default()
     };
@@ -1,30 +1,408 @@
-use std::collections::BTreeSet;
+use std::{cell::RefCell, collections::BTreeMap};
 
 use crossterm::event::KeyCode;
 use ratatui::{
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::Widget,
+    widgets::{Block, StatefulWidget, Widget},
 };
+use unicode_width::UnicodeWidthStr;
 
-use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use super::wrap::wrap_line;
+
+use pulldown_cmark::{
+    Alignment, CodeBlockKind, Event, HeadingLevel, LinkType, Options, Parser, Tag, TagEnd,
+};
+
+#[cfg(test)]
+mod test;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Markdown {
     input: String,
-    links: BTreeSet<String>,
+    /// Link destinations in the order they first appear in the document.
+    links: Vec<String>,
+    /// Image urls in the order they first appear in the document.
+    images: Vec<String>,
+    /// Markdown features in the document that we don't know how to render.
+    warnings: Vec<String>,
+    /// The level, text, and starting line of each heading.
+    headings: Vec<(HeadingLevel, String, usize)>,
+    /// The key value pairs from a front matter block at the start of the
+    /// document.
+    front_matter: BTreeMap<String, String>,
     parsed_text: Option<Text<'static>>,
+    options: MarkdownOptions,
+    /// Digits typed so far when selecting a link.
+    link_digits: String,
+    theme: MarkdownTheme,
+    /// The number of lines scrolled past the top of the render area.
+    scroll_offset: u16,
+    /// The lines from the last render wrapped to the width they were rendered
+    /// at. Wrapping the whole document every frame is wasteful for large
+    /// documents.
+    wrap_cache: RefCell<Option<WrapCache>>,
+    /// Bumped every time we parse so caches can tell the content changed.
+    generation: u64,
+    /// Blank columns to the left of every rendered line.
+    left_padding: u16,
+    /// A block drawn around the document. Text is wrapped and scrolled
+    /// within the block's inner area.
+    block: Option<Block<'static>>,
+}
+
+/// The markdown extensions we parse and the optional ways we render them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownOptions {
+    /// `~~struck~~` text.
+    pub strikethrough: bool,
+    /// `^super^` scripts.
+    pub superscript: bool,
+    /// `~sub~` scripts.
+    pub subscript: bool,
+    pub tables: bool,
+    /// `- [x]` task list items.
+    pub task_lists: bool,
+    pub footnotes: bool,
+    /// `[[Target]]` and `[[Target|Label]]` links.
+    pub wikilinks: bool,
+    pub definition_lists: bool,
+    /// Make bare `http://` and `https://` urls in text into links.
+    pub autolinks: bool,
+    /// Render super and subscripts with unicode characters when they are all
+    /// digits instead of as `^{...}` and `_{...}`.
+    pub unicode_scripts: bool,
+    /// Wrap link text in OSC 8 escapes so terminals that support them make
+    /// the text clickable. Off by default since some terminals print the
+    /// escapes literally.
+    pub hyperlinks: bool,
+    /// Start a new line at soft breaks instead of joining the lines with a
+    /// space. This keeps the line structure of hand wrapped markdown.
+    pub soft_break_as_newline: bool,
+    /// Collapse runs of spaces and tabs outside of code to a single space.
+    pub collapse_whitespace: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            strikethrough: true,
+            superscript: true,
+            subscript: true,
+            tables: true,
+            task_lists: true,
+            footnotes: true,
+            wikilinks: true,
+            definition_lists: true,
+            autolinks: true,
+            unicode_scripts: false,
+            hyperlinks: false,
+            soft_break_as_newline: false,
+            collapse_whitespace: false,
+        }
+    }
+}
+
+impl MarkdownOptions {
+    /// The pulldown parser options for the enabled extensions.
+    fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+        for (enabled, option) in [
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.superscript, Options::ENABLE_SUPERSCRIPT),
+            (self.subscript, Options::ENABLE_SUBSCRIPT),
+            (self.tables, Options::ENABLE_TABLES),
+            (self.task_lists, Options::ENABLE_TASKLISTS),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.wikilinks, Options::ENABLE_WIKILINKS),
+            (self.definition_lists, Options::ENABLE_DEFINITION_LIST),
+        ] {
+            if enabled {
+                options |= option;
+            }
+        }
+        options
+    }
+}
+
+/// Builds a `Markdown` with non default options.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MarkdownBuilder {
+    options: MarkdownOptions,
+    theme: MarkdownTheme,
+    left_padding: u16,
+    block: Option<Block<'static>>,
+}
+
+impl MarkdownBuilder {
+    pub fn options(mut self, options: MarkdownOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn theme(mut self, theme: MarkdownTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn left_padding(mut self, left_padding: u16) -> Self {
+        self.left_padding = left_padding;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'static>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Parse the input with the configured options.
+    pub fn build(self, input: &str) -> Markdown {
+        let mut markdown = Markdown {
+            input: input.to_owned(),
+            links: Default::default(),
+            images: Default::default(),
+            warnings: Vec::new(),
+            headings: Vec::new(),
+            front_matter: BTreeMap::new(),
+            parsed_text: None,
+            options: self.options,
+            link_digits: String::new(),
+            theme: self.theme,
+            scroll_offset: 0,
+            wrap_cache: RefCell::new(None),
+            generation: 0,
+            left_padding: self.left_padding,
+            block: self.block,
+        };
+        markdown.parse();
+        markdown
+    }
+}
+
+/// The result of a key press while selecting a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkSelection {
+    /// The destination of the selected link.
+    Chosen(String),
+    /// The digits typed so far could still name a link.
+    Pending,
+    /// There is no link with the typed index.
+    OutOfRange,
+    /// The key wasn't part of a link index.
+    NotALink,
+}
+
+/// Lines wrapped to the width they were rendered at.
+#[derive(Debug, Clone, PartialEq)]
+struct WrapCache {
+    width: u16,
+    generation: u64,
+    lines: Vec<Line<'static>>,
+}
+
+/// Render state for a `Markdown` document drawn by reference.
+///
+/// Keeping the scroll offset and wrapped lines here lets the document be
+/// rendered repeatedly without cloning it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MarkdownViewState {
+    /// The number of lines scrolled past the top of the render area.
+    pub scroll_offset: u16,
+    wrap_cache: Option<WrapCache>,
+}
+
+impl MarkdownViewState {
+    /// Scroll by `delta` lines. Negative values scroll up.
+    pub fn scroll(&mut self, delta: i16) {
+        self.scroll_offset = self.scroll_offset.saturating_add_signed(delta);
+        if let Some(cache) = &self.wrap_cache {
+            let max_offset = u16::try_from(cache.lines.len().saturating_sub(1)).unwrap_or(u16::MAX);
+            self.scroll_offset = std::cmp::min(self.scroll_offset, max_offset);
+        }
+    }
+}
+
+/// The style for inline code and code blocks.
+const CODE_STYLE: Style = Style::new().fg(Color::Yellow).bg(Color::DarkGray);
+
+/// The style for the text of a link.
+const LINK_STYLE: Style = Style::new()
+    .fg(Color::Blue)
+    .add_modifier(Modifier::UNDERLINED);
+
+/// The styles used when rendering markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownTheme {
+    /// Heading styles indexed by level starting with H1.
+    pub headings: [Style; 6],
+    pub strong: Style,
+    pub emphasis: Style,
+    pub code: Style,
+    pub link: Style,
+    pub blockquote: Style,
+    /// The style for text matching a search.
+    pub highlight: Style,
+    /// Unordered list markers indexed by nesting level. Lists nested deeper
+    /// than there are bullets start over from the first bullet.
+    pub bullets: Vec<String>,
+    /// The colors the terminal can show. Colors in the styles above are
+    /// mapped to the nearest color the terminal has when rendering.
+    pub color_depth: ColorDepth,
+}
+
+/// How many colors a terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorDepth {
+    /// Any rgb color.
+    #[default]
+    TrueColor,
+    /// Only the 16 standard ansi colors.
+    Ansi16,
+}
+
+/// The 16 ansi colors with the rgb values xterm uses for them.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+impl ColorDepth {
+    /// Map the colors of a style to ones this depth can show.
+    pub fn resolve_style(self, style: Style) -> Style {
+        Style {
+            fg: style.fg.map(|c| self.resolve(c)),
+            bg: style.bg.map(|c| self.resolve(c)),
+            ..style
+        }
+    }
+
+    /// Map a color to the nearest one this depth can show.
+    pub fn resolve(self, color: Color) -> Color {
+        let rgb = match (self, color) {
+            (ColorDepth::TrueColor, _) => return color,
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => (r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(idx)) => match indexed_to_rgb(idx) {
+                Some(rgb) => rgb,
+                None => return ANSI16[idx as usize].0,
+            },
+            (ColorDepth::Ansi16, color) => return color,
+        };
+        let distance = |(r, g, b): (u8, u8, u8)| {
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+        };
+        ANSI16
+            .iter()
+            .min_by_key(|(_, candidate)| distance(*candidate))
+            .map(|(color, _)| *color)
+            .unwrap_or(color)
+    }
+}
+
+/// The rgb value of a 256 color palette index outside of the first 16 colors.
+fn indexed_to_rgb(idx: u8) -> Option<(u8, u8, u8)> {
+    match idx {
+        0..16 => None,
+        16..232 => {
+            let idx = idx - 16;
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            Some((level(idx / 36), level((idx / 6) % 6), level(idx % 6)))
+        }
+        _ => {
+            let gray = 8 + (idx - 232) * 10;
+            Some((gray, gray, gray))
+        }
+    }
+}
+
+impl MarkdownTheme {
+    fn heading(&self, level: HeadingLevel) -> Style {
+        self.headings[level as usize - 1]
+    }
+
+    fn bullet(&self, nesting_level: usize) -> &str {
+        if self.bullets.is_empty() {
+            return "* ";
+        }
+        &self.bullets[nesting_level % self.bullets.len()]
+    }
 }
 
+impl Default for MarkdownTheme {
+    fn default() -> Self {
+        let minor_heading = Style::new().fg(Color::Blue);
+        Self {
+            headings: [
+                Style::new().add_modifier(Modifier::BOLD),
+                Style::new().add_modifier(Modifier::ITALIC),
+                minor_heading,
+                minor_heading,
+                minor_heading,
+                minor_heading,
+            ],
+            strong: Style::new().add_modifier(Modifier::BOLD),
+            emphasis: Style::new().add_modifier(Modifier::ITALIC),
+            code: CODE_STYLE,
+            link: LINK_STYLE,
+            blockquote: Style::new().add_modifier(Modifier::DIM),
+            highlight: Style::new().add_modifier(Modifier::REVERSED),
+            bullets: vec!["* ".to_owned(), "◦ ".to_owned(), "▪ ".to_owned()],
+            color_depth: ColorDepth::default(),
+        }
+    }
+}
+
+/// The gutter drawn to the left of block quotes once per level of nesting.
+const QUOTE_GUTTER: &str = "│ ";
+
+/// The style for an image placeholder.
+const IMAGE_STYLE: Style = Style::new().add_modifier(Modifier::DIM);
+
 /// Define the different states a markdown parser can be in
 #[derive(Debug, Clone, PartialEq)]
 enum MarkdownState {
     Normal,
-    Heading(pulldown_cmark::HeadingLevel),
+    Heading(HeadingLevel),
     Strong,
     Emphasis,
-    Code,
+    Strikethrough,
+    Superscript,
+    Subscript,
+    /// A code block with the language of a fenced block if it has one.
+    Code(Option<String>),
     List(ListState),
+    /// The rest of a checked task list item.
+    TaskDone,
+    /// The text of a link with the link's destination.
+    Link(String),
+    /// The alt text of an image with the image's url.
+    Image(String),
+    /// The term of a definition list.
+    DefinitionTitle,
+    BlockQuote,
+}
+
+/// Remove the innermost state matching `is_match`. Popping the top of the
+/// stack blindly would drop the wrong state if the stack is out of step with
+/// the events.
+fn pop_state(stack: &mut Vec<MarkdownState>, is_match: impl Fn(&MarkdownState) -> bool) {
+    if let Some(idx) = stack.iter().rposition(is_match) {
+        stack.remove(idx);
+    }
 }
 
 /// Track list state including nesting level and type
@@ -32,7 +410,11 @@ enum MarkdownState {
 struct ListState {
     list_type: ListType,
     nesting_level: usize,
+    /// The number of the current item. Only incremented when an item starts.
     item_number: usize,
+    /// The indentation for lines of the current item after its first line so
+    /// they line up with the item's text.
+    continuation_indent: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -41,27 +423,559 @@ enum ListType {
     Unordered,
 }
 
+/// Collects the cells of a table so we can lay it out once we have seen all of it.
+#[derive(Debug, Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<Span<'static>>>>,
+    current_row: Vec<Vec<Span<'static>>>,
+    /// The number of rows that make up the table head.
+    head_rows: usize,
+}
+
+impl TableState {
+    fn new(alignments: Vec<Alignment>) -> Self {
+        Self {
+            alignments,
+            ..Default::default()
+        }
+    }
+
+    fn finish_row(&mut self) {
+        self.rows.push(std::mem::take(&mut self.current_row));
+    }
+
+    /// Lay the table out as lines with the columns padded to a common width.
+    fn into_lines(self) -> Vec<Line<'static>> {
+        let column_count = self.rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut widths = vec![0; column_count];
+        for row in self.rows.iter() {
+            for (idx, cell) in row.iter().enumerate() {
+                widths[idx] = std::cmp::max(widths[idx], spans_width(cell));
+            }
+        }
+        let mut lines = Vec::with_capacity(self.rows.len() + 1);
+        for (row_idx, row) in self.rows.into_iter().enumerate() {
+            let mut line = Line::default();
+            let mut cells = row.into_iter();
+            for (idx, width) in widths.iter().enumerate() {
+                if idx > 0 {
+                    line.spans.push(Span::raw(" │ "));
+                }
+                let cell = cells.next().unwrap_or_default();
+                let pad = width - spans_width(&cell);
+                let (left, right) = match self.alignments.get(idx) {
+                    Some(Alignment::Right) => (pad, 0),
+                    Some(Alignment::Center) => (pad / 2, pad - pad / 2),
+                    _ => (0, pad),
+                };
+                if left > 0 {
+                    line.spans.push(Span::raw(" ".repeat(left)));
+                }
+                line.spans.extend(cell);
+                if right > 0 {
+                    line.spans.push(Span::raw(" ".repeat(right)));
+                }
+            }
+            lines.push(line);
+            if row_idx + 1 == self.head_rows {
+                let rule: Vec<String> = widths
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, w)| alignment_rule(self.alignments.get(idx), *w))
+                    .collect();
+                lines.push(Line::raw(rule.join("─┼─")));
+            }
+        }
+        lines
+    }
+}
+
+/// The rule under a table head column. Like the `:---`, `:--:`, and `---:`
+/// markers in the source a `├` marks a left edge the column is aligned to and
+/// a `┤` a right edge.
+fn alignment_rule(alignment: Option<&Alignment>, width: usize) -> String {
+    let (left, right) = match alignment {
+        Some(Alignment::Left) => ("├", ""),
+        Some(Alignment::Right) => ("", "┤"),
+        Some(Alignment::Center) if width >= 2 => ("├", "┤"),
+        Some(Alignment::Center) => ("┼", ""),
+        _ => ("", ""),
+    };
+    let markers = left.chars().count() + right.chars().count();
+    if width < markers {
+        return "─".repeat(width);
+    }
+    format!("{}{}{}", left, "─".repeat(width - markers), right)
+}
+
+/// The destination we record for a link tag. Links we can't resolve have no
+/// destination so they aren't selectable.
+fn link_destination(tag: &Tag<'_>) -> Option<String> {
+    match tag {
+        Tag::Link {
+            link_type,
+            dest_url,
+            ..
+        } => match link_type {
+            // [foo](bar), <https://foo>, and <foo@bar.com>
+            LinkType::Inline | LinkType::Autolink | LinkType::Email => Some(dest_url.to_string()),
+            // [foo][bar], [foo][], and [foo] have their reference resolved for us.
+            LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut => {
+                Some(dest_url.to_string())
+            }
+            LinkType::ReferenceUnknown | LinkType::CollapsedUnknown | LinkType::ShortcutUnknown => {
+                None
+            }
+            // [[Target]] and [[Target|Label]] where only the label is shown.
+            LinkType::WikiLink { has_pothole } => {
+                let target = match dest_url.split_once('|') {
+                    Some((target, _label)) if *has_pothole => target,
+                    _ => dest_url.as_ref(),
+                };
+                Some(target.trim().to_owned())
+            }
+        },
+        _ => None,
+    }
+}
+
+/// The byte ranges of the bare `http://` and `https://` urls in some text.
+/// Trailing punctuation is left out of the url so a url can end a sentence.
+fn bare_urls(text: &str) -> Vec<(usize, usize)> {
+    let mut urls = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = text[offset..]
+        .find("http://")
+        .into_iter()
+        .chain(text[offset..].find("https://"))
+        .min()
+    {
+        let start = offset + found;
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || c == '<' || c == '>')
+            .unwrap_or(text.len() - start);
+        let mut url = &text[start..start + len];
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+            // Keep a closing paren that closes one in the url.
+            let trimmed = match trimmed.strip_suffix(')') {
+                Some(inner) if inner.matches('(').count() < trimmed.matches(')').count() => inner,
+                _ => trimmed,
+            };
+            if trimmed.len() == url.len() {
+                break;
+            }
+            url = trimmed;
+        }
+        if url.len() > url.find("://").unwrap_or(0) + 3 {
+            urls.push((start, start + url.len()));
+        }
+        offset = start + len.max(1);
+    }
+    urls
+}
+
+/// Split a front matter block delimited by `---` lines off of the start of a
+/// document. Only simple `key: value` lines are understood. Anything else in
+/// the block is ignored.
+fn split_front_matter(input: &str) -> (BTreeMap<String, String>, &str) {
+    let mut front_matter = BTreeMap::new();
+    let Some(rest) = input
+        .strip_prefix("---\n")
+        .or_else(|| input.strip_prefix("---\r\n"))
+    else {
+        return (front_matter, input);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            return (front_matter, &rest[offset..]);
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            front_matter.insert(key.trim().to_owned(), value.to_owned());
+        }
+    }
+    // Without a closing delimiter this wasn't front matter after all.
+    (BTreeMap::new(), input)
+}
+
+/// Replace each run of spaces and tabs with a single space.
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_run = false;
+    for c in text.chars() {
+        let blank = c == ' ' || c == '\t';
+        if !(blank && in_run) {
+            collapsed.push(if blank { ' ' } else { c });
+        }
+        in_run = blank;
+    }
+    collapsed
+}
+
+/// Wrap text in the OSC 8 escape sequence that links it to `url`.
+fn osc8_hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Split a span into pieces with the matches of `query` highlighted.
+fn highlight_span(span: Span<'static>, query: &[char], highlight: Style) -> Vec<Span<'static>> {
+    let chars: Vec<char> = span.content.chars().collect();
+    let matches_at = |idx: usize| {
+        idx + query.len() <= chars.len()
+            && chars[idx..idx + query.len()]
+                .iter()
+                .zip(query)
+                .all(|(c, q)| c.to_lowercase().eq(q.to_lowercase()))
+    };
+    let mut pieces = Vec::new();
+    let mut plain = String::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        if matches_at(idx) {
+            if !plain.is_empty() {
+                pieces.push(Span::styled(std::mem::take(&mut plain), span.style));
+            }
+            let found: String = chars[idx..idx + query.len()].iter().collect();
+            pieces.push(Span::styled(found, span.style.patch(highlight)));
+            idx += query.len();
+        } else {
+            plain.push(chars[idx]);
+            idx += 1;
+        }
+    }
+    if !plain.is_empty() || pieces.is_empty() {
+        pieces.push(Span::styled(plain, span.style));
+    }
+    pieces
+}
+
+/// A piece of raw html that we know how to show.
+enum HtmlPiece {
+    Text(String),
+    Break,
+}
+
+/// Split raw html into its text and line breaks. Tags other than `<br>` are
+/// dropped and entities in the text are decoded.
+fn html_pieces(html: &str) -> Vec<HtmlPiece> {
+    let mut pieces = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        // An unclosed `<` is just text.
+        let tag_bounds = rest
+            .find('<')
+            .and_then(|start| Some((start, start + rest[start..].find('>')? + 1)));
+        let (text, tag, consumed) = match tag_bounds {
+            Some((start, end)) => (&rest[..start], Some(&rest[start..end]), end),
+            None => (rest, None, rest.len()),
+        };
+        let text = decode_entities(&text.replace('\n', " "));
+        if !text.trim().is_empty() {
+            pieces.push(HtmlPiece::Text(text));
+        }
+        if let Some(tag) = tag {
+            let name = tag
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .trim_end_matches('/')
+                .trim();
+            if name.eq_ignore_ascii_case("br") {
+                pieces.push(HtmlPiece::Break);
+            }
+        }
+        rest = &rest[consumed..];
+    }
+    pieces
+}
+
+/// Decode the html entities we are likely to run into.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .map(|end| &rest[1..end])
+            .and_then(|name| Some((name, decode_entity(name)?)));
+        match entity {
+            Some((name, c)) => {
+                decoded.push(c);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        _ => {
+            let code = match name.strip_prefix('#')? {
+                hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok()?,
+                dec => dec.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    })
+}
+
+/// The width of the rule separating footnote definitions from the document.
+const FOOTNOTE_RULE_WIDTH: usize = 20;
+
+/// The 1 based number for a footnote label assigning the next number to labels
+/// we haven't seen before.
+fn footnote_number(order: &mut Vec<String>, label: &str) -> usize {
+    if let Some(idx) = order.iter().position(|l| l == label) {
+        return idx + 1;
+    }
+    order.push(label.to_owned());
+    order.len()
+}
+
+/// Collapse runs of blank lines into a single blank line and drop any blank
+/// lines at the start or end of the document. Also returns the new index of
+/// each line.
+fn normalize_blank_lines(lines: Vec<Line<'static>>) -> (Vec<Line<'static>>, Vec<usize>) {
+    let mut normalized: Vec<Line<'static>> = Vec::with_capacity(lines.len());
+    let mut new_index = Vec::with_capacity(lines.len());
+    for line in lines {
+        new_index.push(normalized.len());
+        if line.spans.is_empty() && normalized.last().is_none_or(|l| l.spans.is_empty()) {
+            continue;
+        }
+        normalized.push(line);
+    }
+    while normalized.last().map_or(false, |l| l.spans.is_empty()) {
+        normalized.pop();
+    }
+    (normalized, new_index)
+}
+
+/// Drop whitespace from the end of a line including any spans that are only
+/// whitespace.
+fn trim_trailing_whitespace(line: &mut Line<'static>) {
+    while line
+        .spans
+        .last()
+        .is_some_and(|span| span.content.trim_end().is_empty())
+    {
+        line.spans.pop();
+    }
+    if let Some(last) = line.spans.last_mut() {
+        let trimmed = last.content.trim_end();
+        if trimmed.len() < last.content.len() {
+            last.content = trimmed.to_owned().into();
+        }
+    }
+}
+
+/// The number of columns the spans take up.
+fn spans_width(spans: &[Span<'_>]) -> usize {
+    spans.iter().map(|s| s.content.width()).sum()
+}
+
 impl Markdown {
     pub fn from_str(input: &str) -> Self {
-        let mut me = Self {
-            input: input.to_owned(),
-            links: Default::default(),
-            parsed_text: None,
+        Self::with_theme(input, MarkdownTheme::default())
+    }
+
+    /// Construct markdown rendered with the styles from a theme.
+    pub fn with_theme(input: &str, theme: MarkdownTheme) -> Self {
+        Self::builder().theme(theme).build(input)
+    }
+
+    /// Start building markdown with non default options.
+    pub fn builder() -> MarkdownBuilder {
+        MarkdownBuilder::default()
+    }
+
+    /// Replace the markdown source and re-parse it.
+    pub fn set_input(&mut self, input: &str) {
+        self.input = input.to_owned();
+        self.links.clear();
+        self.images.clear();
+        self.link_digits.clear();
+        self.scroll_offset = 0;
+        self.parse();
+    }
+
+    /// Choose whether super and subscripts of digits use unicode characters.
+    pub fn with_unicode_scripts(mut self, unicode_scripts: bool) -> Self {
+        self.options.unicode_scripts = unicode_scripts;
+        self.parse();
+        self
+    }
+
+    /// Choose whether link text is emitted as an OSC 8 hyperlink.
+    pub fn with_hyperlinks(mut self, hyperlinks: bool) -> Self {
+        self.options.hyperlinks = hyperlinks;
+        self.parse();
+        self
+    }
+
+    /// Choose whether soft breaks start a new line.
+    pub fn with_soft_break_as_newline(mut self, soft_break_as_newline: bool) -> Self {
+        self.options.soft_break_as_newline = soft_break_as_newline;
+        self.parse();
+        self
+    }
+
+    /// Choose whether runs of whitespace outside of code are collapsed.
+    pub fn with_collapse_whitespace(mut self, collapse_whitespace: bool) -> Self {
+        self.options.collapse_whitespace = collapse_whitespace;
+        self.parse();
+        self
+    }
+
+    /// Offset every rendered line by `left_padding` columns.
+    pub fn with_left_padding(mut self, left_padding: u16) -> Self {
+        self.left_padding = left_padding;
+        self
+    }
+
+    /// Draw the document inside of a block such as a titled border.
+    pub fn with_block(mut self, block: Block<'static>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Draw our block if we have one and return the part of the render area
+    /// that text is drawn in.
+    fn render_frame(
+        &self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+    ) -> ratatui::prelude::Rect {
+        let area = match &self.block {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.clone().render(area, buf);
+                inner
+            }
+            None => area,
         };
-        me.parse();
-        me
+        let padding = std::cmp::min(self.left_padding, area.width);
+        ratatui::prelude::Rect {
+            x: area.x + padding,
+            width: area.width - padding,
+            ..area
+        }
     }
 
     fn parse(&mut self) {
-        let input = self.input.clone();
+        let (front_matter, body) = split_front_matter(&self.input);
+        self.front_matter = front_matter;
+        let input = body.to_owned();
+        self.links.clear();
+        self.images.clear();
+        self.warnings.clear();
+        self.headings.clear();
+        self.wrap_cache.get_mut().take();
+        self.generation += 1;
 
-        let parser = pulldown_cmark::TextMergeStream::new(Parser::new(&input));
+        let options = self.options.parser_options();
+        // Collect the links up front so we know each link's index as we render it.
+        let mut in_link = false;
+        let mut in_code = false;
+        for event in pulldown_cmark::TextMergeStream::new(Parser::new_ext(&input, options)) {
+            match &event {
+                Event::Start(tag @ Tag::Link { .. }) => {
+                    self.handle_link_tag(tag);
+                    in_link = true;
+                }
+                Event::End(TagEnd::Link) => in_link = false,
+                Event::Start(Tag::CodeBlock(_)) => in_code = true,
+                Event::End(TagEnd::CodeBlock) => in_code = false,
+                Event::Text(text) if self.options.autolinks && !in_link && !in_code => {
+                    for (start, end) in bare_urls(text) {
+                        self.add_link(text[start..end].to_owned());
+                    }
+                }
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    if !self.images.iter().any(|i| i == dest_url.as_ref()) {
+                        self.images.push(dest_url.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let parser = pulldown_cmark::TextMergeStream::new(Parser::new_ext(&input, options));
 
         let mut current_line = Line::default();
         let mut lines: Vec<Line> = Vec::new();
         let mut state_stack: Vec<MarkdownState> = vec![MarkdownState::Normal];
+        let mut table: Option<TableState> = None;
+        // Footnote labels in the order they were first seen. A footnote's number
+        // is its position in this list plus one.
+        let mut footnote_order: Vec<String> = Vec::new();
+        let mut footnotes: Vec<(String, Vec<Line>)> = Vec::new();
+        // The label of the footnote definition we are collecting and the lines
+        // we set aside while collecting it.
+        let mut outside_footnote: Option<(String, Vec<Line>, Line)> = None;
 
+        // True right after an item or definition starts while its marker is the
+        // only thing on the line.
+        let mut item_start = false;
         for event in parser {
+            let starts_item = matches!(
+                event,
+                Event::Start(Tag::Item | Tag::DefinitionListDefinition)
+            );
+            let starts_content = matches!(
+                event,
+                Event::Text(_)
+                    | Event::Code(_)
+                    | Event::InlineHtml(_)
+                    | Event::InlineMath(_)
+                    | Event::FootnoteReference(_)
+                    | Event::Start(Tag::Image { .. })
+            );
+            if starts_content && current_line.spans.is_empty() && table.is_none() {
+                // Each level of block quote gets its own gutter segment.
+                let quote_depth = state_stack
+                    .iter()
+                    .filter(|state| **state == MarkdownState::BlockQuote)
+                    .count();
+                if quote_depth > 0 {
+                    current_line.spans.push(Span::styled(
+                        QUOTE_GUTTER.repeat(quote_depth),
+                        self.theme.blockquote,
+                    ));
+                }
+                // Lines after the first line of a list item are indented to
+                // line up with the item's text.
+                let indent = state_stack.iter().rev().find_map(|state| match state {
+                    MarkdownState::List(list_state) => Some(list_state.continuation_indent),
+                    _ => None,
+                });
+                if let Some(indent) = indent.filter(|i| *i > 0) {
+                    current_line.spans.push(Span::raw(" ".repeat(indent)));
+                }
+            }
             match event {
                 Event::Start(tag) => {
                     match &tag {
@@ -70,21 +984,12 @@ impl Markdown {
                                 lines.push(current_line);
                             }
 
-                            // Add heading style based on level
-                            let heading_style = match level {
-                                pulldown_cmark::HeadingLevel::H1 => {
-                                    Style::default().add_modifier(Modifier::BOLD)
-                                }
-                                pulldown_cmark::HeadingLevel::H2 => {
-                                    Style::default().add_modifier(Modifier::ITALIC)
-                                }
-                                _ => Style::default().fg(Color::Blue),
-                            };
-                            current_line = Line::styled("", heading_style);
+                            current_line = Line::styled("", self.theme.heading(*level));
                             state_stack.push(MarkdownState::Heading(*level));
                         }
                         Tag::Paragraph => {
-                            if !current_line.spans.is_empty() {
+                            // The first paragraph of an item goes on the marker's line.
+                            if !current_line.spans.is_empty() && !item_start {
                                 lines.push(current_line);
                                 current_line = Line::default();
                             }
@@ -95,8 +1000,24 @@ impl Markdown {
                         Tag::Emphasis => {
                             state_stack.push(MarkdownState::Emphasis);
                         }
-                        Tag::CodeBlock(_) => {
-                            state_stack.push(MarkdownState::Code);
+                        Tag::CodeBlock(kind) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            let lang = match kind {
+                                CodeBlockKind::Fenced(lang) if !lang.is_empty() => {
+                                    Some(lang.to_string())
+                                }
+                                _ => None,
+                            };
+                            if let Some(lang) = &lang {
+                                lines.push(Line::styled(
+                                    format!("┌─ {}", lang),
+                                    Style::default().add_modifier(Modifier::DIM),
+                                ));
+                            }
+                            state_stack.push(MarkdownState::Code(lang));
                         }
                         Tag::List(list_type) => {
                             if !current_line.spans.is_empty() {
@@ -104,10 +1025,14 @@ impl Markdown {
                                 current_line = Line::default();
                             }
 
-                            // Determine list type and nesting level
-                            let list_type = match list_type {
-                                Some(_) => ListType::Ordered,
-                                None => ListType::Unordered,
+                            // Determine list type and nesting level. An ordered list
+                            // interrupted by other blocks resumes at its own start
+                            // number so we number from there.
+                            let (list_type, item_number) = match list_type {
+                                Some(start) => {
+                                    (ListType::Ordered, (*start as usize).saturating_sub(1))
+                                }
+                                None => (ListType::Unordered, 0),
                             };
 
                             // Calculate nesting level based on existing lists in the stack
@@ -119,7 +1044,8 @@ impl Markdown {
                             state_stack.push(MarkdownState::List(ListState {
                                 list_type,
                                 nesting_level,
-                                item_number: 0,
+                                item_number,
+                                continuation_indent: 0,
                             }));
                         }
                         Tag::Item => {
@@ -138,12 +1064,15 @@ impl Markdown {
 
                                     // Add appropriate marker based on list type
                                     let marker = match list_state.list_type {
-                                        ListType::Unordered => "* ".to_string(),
+                                        ListType::Unordered => {
+                                            self.theme.bullet(list_state.nesting_level).to_owned()
+                                        }
                                         ListType::Ordered => {
                                             format!("{}. ", list_state.item_number)
                                         }
                                     };
 
+                                    list_state.continuation_indent = indent.len() + marker.width();
                                     current_line
                                         .spans
                                         .push(Span::raw(format!("{}{}", indent, marker)));
@@ -151,18 +1080,63 @@ impl Markdown {
                                 }
                             }
                         }
-                        Tag::Link {
-                            link_type: _,
-                            dest_url: _,
-                            title: _,
-                            id: _,
-                        } => {
-                            self.handle_link_tag(&tag);
+                        Tag::Link { .. } => {
+                            if let Some(dest) = link_destination(&tag) {
+                                state_stack.push(MarkdownState::Link(dest));
+                            }
+                        }
+                        Tag::Image { dest_url, .. } => {
+                            // We can't draw images so we show a placeholder with the alt text.
+                            current_line.spans.push(Span::styled("🖼 ", IMAGE_STYLE));
+                            state_stack.push(MarkdownState::Image(dest_url.to_string()));
+                        }
+                        Tag::DefinitionListTitle => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            state_stack.push(MarkdownState::DefinitionTitle);
+                        }
+                        Tag::DefinitionListDefinition => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            current_line.spans.push(Span::raw("  "));
+                        }
+                        Tag::Table(alignments) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            table = Some(TableState::new(alignments.clone()));
+                        }
+                        Tag::TableCell => {
+                            current_line = Line::default();
+                        }
+                        Tag::FootnoteDefinition(label) => {
+                            outside_footnote = Some((
+                                label.to_string(),
+                                std::mem::take(&mut lines),
+                                std::mem::take(&mut current_line),
+                            ));
+                        }
+                        Tag::BlockQuote(_) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            state_stack.push(MarkdownState::BlockQuote);
+                        }
+                        Tag::MetadataBlock(_) => {
+                            self.warn("metadata blocks");
+                        }
+                        Tag::Strikethrough => {
+                            state_stack.push(MarkdownState::Strikethrough);
+                        }
+                        Tag::Superscript => {
+                            state_stack.push(MarkdownState::Superscript);
+                        }
+                        Tag::Subscript => {
+                            state_stack.push(MarkdownState::Subscript);
                         }
-                        Tag::BlockQuote(_) => todo!(),
-                        Tag::Strikethrough => todo!(),
-                        Tag::Superscript => todo!(),
-                        Tag::Subscript => todo!(),
                         _ => {
                             // noop
                         }
@@ -170,11 +1144,21 @@ impl Markdown {
                 }
                 Event::End(tag) => {
                     match tag {
-                        TagEnd::Heading { .. } => {
+                        TagEnd::Heading(level) => {
+                            // Headings in footnotes move to the bottom so we don't
+                            // know where they start.
+                            if outside_footnote.is_none() {
+                                let text: String = current_line
+                                    .spans
+                                    .iter()
+                                    .map(|s| s.content.as_ref())
+                                    .collect();
+                                self.headings.push((level, text, lines.len()));
+                            }
                             lines.push(current_line);
                             lines.push(Line::default()); // Add empty line after heading
                             current_line = Line::default();
-                            state_stack.pop();
+                            pop_state(&mut state_stack, |s| matches!(s, MarkdownState::Heading(_)));
                         }
                         TagEnd::Paragraph => {
                             lines.push(current_line);
@@ -182,13 +1166,101 @@ impl Markdown {
                             current_line = Line::default();
                         }
                         TagEnd::Strong => {
-                            state_stack.pop();
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::Strong);
                         }
                         TagEnd::Emphasis => {
-                            state_stack.pop();
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::Emphasis);
+                        }
+                        TagEnd::FootnoteDefinition => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            if let Some((label, outside_lines, outside_line)) =
+                                outside_footnote.take()
+                            {
+                                let definition = std::mem::replace(&mut lines, outside_lines);
+                                current_line = outside_line;
+                                footnotes.push((label, definition));
+                            }
+                        }
+                        TagEnd::Link => {
+                            if let Some(MarkdownState::Link(dest)) = state_stack.last() {
+                                if let Some(idx) = self.links.iter().position(|l| l == dest) {
+                                    current_line
+                                        .spans
+                                        .push(Span::styled(format!("[{}]", idx), self.theme.link));
+                                }
+                                state_stack.pop();
+                            }
+                        }
+                        TagEnd::Image => {
+                            if let Some(MarkdownState::Image(url)) = state_stack.last() {
+                                current_line
+                                    .spans
+                                    .push(Span::styled(format!(" ({})", url), IMAGE_STYLE));
+                                state_stack.pop();
+                            }
+                        }
+                        TagEnd::BlockQuote(_) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::BlockQuote);
+                            lines.push(Line::default()); // Add empty line after block quote
+                        }
+                        TagEnd::DefinitionListTitle => {
+                            lines.push(std::mem::take(&mut current_line));
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::DefinitionTitle);
+                        }
+                        TagEnd::DefinitionListDefinition => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                        }
+                        TagEnd::DefinitionList => {
+                            lines.push(Line::default()); // Add empty line after definition list
+                        }
+                        TagEnd::TableCell => {
+                            if let Some(table) = table.as_mut() {
+                                table
+                                    .current_row
+                                    .push(std::mem::take(&mut current_line.spans));
+                            }
+                        }
+                        TagEnd::TableHead => {
+                            if let Some(table) = table.as_mut() {
+                                table.finish_row();
+                                table.head_rows = table.rows.len();
+                            }
+                        }
+                        TagEnd::TableRow => {
+                            if let Some(table) = table.as_mut() {
+                                table.finish_row();
+                            }
+                        }
+                        TagEnd::Table => {
+                            if let Some(table) = table.take() {
+                                lines.extend(table.into_lines());
+                                lines.push(Line::default()); // Add empty line after table
+                            }
+                            current_line = Line::default();
+                        }
+                        TagEnd::Strikethrough => {
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::Strikethrough);
+                        }
+                        TagEnd::Superscript => {
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::Superscript);
+                        }
+                        TagEnd::Subscript => {
+                            pop_state(&mut state_stack, |s| *s == MarkdownState::Subscript);
                         }
                         TagEnd::CodeBlock => {
-                            state_stack.pop();
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            lines.push(Line::default()); // Add empty line after code block
+                            pop_state(&mut state_stack, |s| matches!(s, MarkdownState::Code(_)));
                         }
                         TagEnd::Item => {
                             // Push the current line to preserve the list item
@@ -196,9 +1268,12 @@ impl Markdown {
                                 lines.push(current_line);
                                 current_line = Line::default();
                             }
+                            if state_stack.last() == Some(&MarkdownState::TaskDone) {
+                                state_stack.pop();
+                            }
                         }
                         TagEnd::List(_) => {
-                            state_stack.pop();
+                            pop_state(&mut state_stack, |s| matches!(s, MarkdownState::List(_)));
 
                             // Only add an empty line if we're back to the root level
                             if state_stack
@@ -213,38 +1288,126 @@ impl Markdown {
                         _ => {}
                     }
                 }
-                Event::InlineMath(text)
-                | Event::Code(text)
-                | Event::InlineHtml(text)
-                | Event::DisplayMath(text)
-                | Event::Html(text)
-                | Event::Text(text) => {
+                Event::Code(text) => {
+                    // Inline code isn't tracked on the state stack. It arrives
+                    // as a single event.
+                    current_line
+                        .spans
+                        .push(Span::styled(text.to_string(), self.theme.code));
+                }
+                Event::InlineHtml(html) | Event::Html(html) => {
+                    for piece in html_pieces(&html) {
+                        match piece {
+                            HtmlPiece::Break => {
+                                lines.push(std::mem::take(&mut current_line));
+                            }
+                            HtmlPiece::Text(text) => {
+                                current_line.spans.push(Span::raw(text));
+                            }
+                        }
+                    }
+                }
+                Event::InlineMath(text) | Event::DisplayMath(text) | Event::Text(text) => {
                     let mut style = Style::default();
 
                     // Apply style based on current state
                     for state in state_stack.iter().rev() {
                         match state {
-                            MarkdownState::Heading(_) => {
-                                // Style already applied to the line
-                                break;
+                            MarkdownState::Heading(level) => {
+                                // The heading style sits under any inline styles.
+                                style = self.theme.heading(*level).patch(style);
                             }
                             MarkdownState::Strong => {
-                                style = style.add_modifier(Modifier::BOLD);
+                                style = style.patch(self.theme.strong);
                             }
                             MarkdownState::Emphasis => {
-                                style = style.add_modifier(Modifier::ITALIC);
+                                style = style.patch(self.theme.emphasis);
+                            }
+                            MarkdownState::Strikethrough => {
+                                style = style.add_modifier(Modifier::CROSSED_OUT);
+                            }
+                            MarkdownState::Link(_) => {
+                                style = style.patch(self.theme.link);
+                            }
+                            MarkdownState::TaskDone => {
+                                style = style.add_modifier(Modifier::DIM | Modifier::CROSSED_OUT);
+                            }
+                            MarkdownState::Code(_) => {
+                                style = style.patch(self.theme.code);
+                            }
+                            MarkdownState::Image(_) => {
+                                style = style.patch(IMAGE_STYLE);
+                            }
+                            MarkdownState::DefinitionTitle => {
+                                style = style.patch(self.theme.strong);
+                            }
+                            MarkdownState::BlockQuote => {
+                                style = style.patch(self.theme.blockquote);
                             }
-                            //MarkdownState::Code => {
-                            //    style = style.fg(Color::Yellow);
-                            //}
                             _ => {}
                         }
                     }
 
-                    // Add the text with appropriate styling
-                    current_line
-                        .spans
-                        .push(Span::styled(text.to_string(), style));
+                    // Terminals can't raise or lower text so we approximate scripts.
+                    let text = match state_stack.iter().rev().find(|state| {
+                        matches!(state, MarkdownState::Superscript | MarkdownState::Subscript)
+                    }) {
+                        Some(MarkdownState::Superscript) => {
+                            self.script_text(&text, '^', to_superscript)
+                        }
+                        Some(MarkdownState::Subscript) => {
+                            self.script_text(&text, '_', to_subscript)
+                        }
+                        _ => text.to_string(),
+                    };
+
+                    let link = state_stack.iter().rev().find_map(|state| match state {
+                        MarkdownState::Link(dest) => Some(dest),
+                        _ => None,
+                    });
+                    let text = match link {
+                        Some(dest) if self.options.hyperlinks => osc8_hyperlink(dest, &text),
+                        _ => text,
+                    };
+
+                    if state_stack
+                        .iter()
+                        .any(|state| matches!(state, MarkdownState::Code(_)))
+                    {
+                        // Code blocks keep their line structure so each line of
+                        // the block gets its own line.
+                        for (idx, segment) in text.split('\n').enumerate() {
+                            if idx > 0 {
+                                // Blank lines inside a code block get an empty span
+                                // so normalization doesn't collapse them.
+                                if current_line.spans.is_empty() {
+                                    current_line.spans.push(Span::raw(""));
+                                }
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            if !segment.is_empty() {
+                                current_line
+                                    .spans
+                                    .push(Span::styled(segment.to_owned(), style));
+                            }
+                        }
+                    } else {
+                        let text = if self.options.collapse_whitespace {
+                            collapse_whitespace(&text)
+                        } else {
+                            text
+                        };
+                        if link.is_none() {
+                            // Add the text with appropriate styling
+                            self.push_autolinked(&mut current_line, text, style);
+                        } else {
+                            current_line.spans.push(Span::styled(text, style));
+                        }
+                    }
+                }
+                Event::SoftBreak if self.options.soft_break_as_newline => {
+                    lines.push(std::mem::take(&mut current_line));
                 }
                 Event::SoftBreak => {
                     current_line.spans.push(Span::raw(" "));
@@ -253,10 +1416,49 @@ impl Markdown {
                     lines.push(current_line);
                     current_line = Line::default();
                 }
-                Event::FootnoteReference(_) => {}
-                Event::Rule => {}
-                Event::TaskListMarker(_) => {}
+                Event::FootnoteReference(label) => {
+                    let number = footnote_number(&mut footnote_order, &label);
+                    current_line.spans.push(Span::styled(
+                        format!("[^{}]", number),
+                        Style::default().add_modifier(Modifier::DIM),
+                    ));
+                }
+                Event::Rule => {
+                    self.warn("horizontal rules");
+                }
+                Event::TaskListMarker(checked) => {
+                    let (box_text, style) = if checked {
+                        ("[x] ", Style::default().add_modifier(Modifier::DIM))
+                    } else {
+                        ("[ ] ", Style::default())
+                    };
+                    // The checkbox takes the place of an unordered bullet.
+                    let list_state = state_stack.iter_mut().rev().find_map(|state| match state {
+                        MarkdownState::List(list_state) => Some(list_state),
+                        _ => None,
+                    });
+                    match list_state {
+                        Some(list_state) if list_state.list_type == ListType::Unordered => {
+                            if let Some(bullet) = current_line.spans.last_mut() {
+                                let indent = "  ".repeat(list_state.nesting_level);
+                                list_state.continuation_indent = indent.len() + box_text.len();
+                                *bullet = Span::styled(format!("{}{}", indent, box_text), style);
+                            }
+                        }
+                        Some(list_state) => {
+                            list_state.continuation_indent += box_text.len();
+                            current_line.spans.push(Span::styled(box_text, style));
+                        }
+                        None => {
+                            current_line.spans.push(Span::styled(box_text, style));
+                        }
+                    }
+                    if checked {
+                        state_stack.push(MarkdownState::TaskDone);
+                    }
+                }
             }
+            item_start = starts_item;
         }
 
         // Add any remaining content
@@ -264,54 +1466,299 @@ impl Markdown {
             lines.push(current_line);
         }
 
+        // Footnote definitions go at the bottom in the order they were referenced.
+        if !footnotes.is_empty() {
+            for (label, _) in footnotes.iter() {
+                footnote_number(&mut footnote_order, label);
+            }
+            footnotes.sort_by_key(|(label, _)| footnote_number(&mut footnote_order, label));
+            lines.push(Line::raw("─".repeat(FOOTNOTE_RULE_WIDTH)));
+            for (label, mut definition) in footnotes {
+                while definition.last().map_or(false, |l| l.spans.is_empty()) {
+                    definition.pop();
+                }
+                let marker = Span::styled(
+                    format!("[^{}] ", footnote_number(&mut footnote_order, &label)),
+                    Style::default().add_modifier(Modifier::DIM),
+                );
+                if let Some(first) = definition.first_mut() {
+                    first.spans.insert(0, marker);
+                } else {
+                    definition.push(Line::from(marker));
+                }
+                lines.extend(definition);
+            }
+        }
+
+        let (mut lines, new_index) = normalize_blank_lines(lines);
+        for (_, _, line) in self.headings.iter_mut() {
+            *line = new_index[*line];
+        }
+        lines.iter_mut().for_each(trim_trailing_whitespace);
         self.parsed_text = Some(Text::from(lines));
     }
 
+    /// Record an unsupported feature once no matter how often it shows up.
+    fn warn(&mut self, feature: &str) {
+        let warning = format!("Unsupported markdown feature: {}", feature);
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// The unsupported markdown features the document uses.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Render super or subscript text as unicode characters if we can or
+    /// wrapped like `^{text}` otherwise.
+    fn script_text(&self, text: &str, marker: char, map: fn(char) -> Option<char>) -> String {
+        if self.options.unicode_scripts {
+            if let Some(mapped) = text.chars().map(map).collect::<Option<String>>() {
+                return mapped;
+            }
+        }
+        format!("{}{{{}}}", marker, text)
+    }
+
     fn handle_link_tag(&mut self, tag: &Tag<'_>) {
-        match tag {
-            Tag::Link {
-                link_type,
-                dest_url,
-                title,
-                id,
-            } => {
-                let dest = match link_type {
-                    // [foo](bar)
-                    LinkType::Inline => format!("({})", dest_url),
-                    // [foo][bar]
-                    LinkType::Reference => format!("[{}]", id),
-                    // [foo]
-                    LinkType::Shortcut => format!("[{}]", title),
-                    // These are unsupported right now
-                    LinkType::ReferenceUnknown => String::from("[unknown]"),
-                    LinkType::Collapsed => String::from("[collapsed]"),
-                    LinkType::CollapsedUnknown => String::from("[collapsed unknown]"),
-                    LinkType::ShortcutUnknown => String::from("[shortcut unknown]"),
-                    LinkType::Autolink => dest_url.to_string(),
-                    LinkType::Email => dest_url.to_string(),
-                    LinkType::WikiLink { has_pothole: _ } => String::from("[wiki]"),
-                };
-                self.links.insert(dest);
+        if let Some(dest) = link_destination(tag) {
+            self.add_link(dest);
+        }
+    }
+
+    fn add_link(&mut self, dest: String) {
+        if !self.links.contains(&dest) {
+            self.links.push(dest);
+        }
+    }
+
+    /// Push text with any bare urls in it styled as links followed by their
+    /// index.
+    fn push_autolinked(&self, line: &mut Line<'static>, text: String, style: Style) {
+        let mut last = 0;
+        for (start, end) in bare_urls(&text) {
+            let url = &text[start..end];
+            let Some(idx) = self.links.iter().position(|l| l == url) else {
+                continue;
+            };
+            if start > last {
+                line.spans
+                    .push(Span::styled(text[last..start].to_owned(), style));
             }
-            _ => { /* noop */ }
+            let url_text = if self.options.hyperlinks {
+                osc8_hyperlink(url, url)
+            } else {
+                url.to_owned()
+            };
+            line.spans
+                .push(Span::styled(url_text, style.patch(self.theme.link)));
+            line.spans
+                .push(Span::styled(format!("[{}]", idx), self.theme.link));
+            last = end;
+        }
+        if last == 0 {
+            line.spans.push(Span::styled(text, style));
+        } else if last < text.len() {
+            line.spans
+                .push(Span::styled(text[last..].to_owned(), style));
+        }
+    }
+
+    /// Handle a key press for selecting a link by its index. Returns the link
+    /// destination once one has been selected. See `select_link` for why a
+    /// key didn't select a link.
+    pub fn handle_input(&mut self, code: KeyCode) -> Option<String> {
+        match self.select_link(code) {
+            LinkSelection::Chosen(link) => Some(link),
+            _ => None,
         }
     }
 
-    pub fn handle_input(&self, code: KeyCode) -> Option<String> {
+    /// Handle a key press for selecting a link by its index.
+    ///
+    /// With ten or fewer links a single digit selects a link. With more links
+    /// digits are buffered until they can only name one link or `Enter` is
+    /// pressed.
+    pub fn select_link(&mut self, code: KeyCode) -> LinkSelection {
         let num = match code {
-            KeyCode::Char('0') => 0,
-            KeyCode::Char('1') => 1,
-            KeyCode::Char('2') => 2,
-            KeyCode::Char('3') => 3,
-            KeyCode::Char('4') => 4,
-            KeyCode::Char('5') => 5,
-            KeyCode::Char('6') => 6,
-            KeyCode::Char('7') => 7,
-            KeyCode::Char('8') => 8,
-            KeyCode::Char('9') => 9,
-            _ => return None,
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                self.link_digits.push(c);
+                let Ok(num) = self.link_digits.parse::<usize>() else {
+                    self.link_digits.clear();
+                    return LinkSelection::OutOfRange;
+                };
+                // Stop waiting once another digit can't name a link.
+                if num.saturating_mul(10) < self.links.len() {
+                    return LinkSelection::Pending;
+                }
+                num
+            }
+            KeyCode::Enter if self.has_pending_selection() => {
+                match self.link_digits.parse::<usize>() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        self.link_digits.clear();
+                        return LinkSelection::OutOfRange;
+                    }
+                }
+            }
+            _ => {
+                self.link_digits.clear();
+                return LinkSelection::NotALink;
+            }
         };
-        self.links.iter().nth(num).cloned()
+        self.link_digits.clear();
+        match self.links.get(num) {
+            Some(link) => LinkSelection::Chosen(link.clone()),
+            None => LinkSelection::OutOfRange,
+        }
+    }
+
+    /// The document's lines wrapped to `width` columns.
+    /// Colors are mapped to ones the theme's color depth can show.
+    fn wrap_lines(&self, width: usize) -> Vec<Line<'static>> {
+        let wrap = |line: &Line<'static>| wrap_line(line, width, hanging_indent(line));
+        let mut lines: Vec<Line<'static>> = match &self.parsed_text {
+            Some(parsed) => parsed.lines.iter().flat_map(wrap).collect(),
+            None => Text::raw(self.input.clone())
+                .lines
+                .iter()
+                .flat_map(wrap)
+                .collect(),
+        };
+        let depth = self.theme.color_depth;
+        if depth != ColorDepth::TrueColor {
+            for line in lines.iter_mut() {
+                line.style = depth.resolve_style(line.style);
+                for span in line.spans.iter_mut() {
+                    span.style = depth.resolve_style(span.style);
+                }
+            }
+        }
+        lines
+    }
+
+    /// The document's lines wrapped to `width` re-wrapping them only if the
+    /// cache was wrapped at a different width or for older content.
+    fn cached_lines<'c>(
+        &self,
+        cache: &'c mut Option<WrapCache>,
+        width: u16,
+    ) -> &'c [Line<'static>] {
+        let stale = cache
+            .as_ref()
+            .is_none_or(|c| c.width != width || c.generation != self.generation);
+        if stale {
+            *cache = Some(WrapCache {
+                width,
+                generation: self.generation,
+                lines: self.wrap_lines(width as usize),
+            });
+        }
+        cache
+            .as_ref()
+            .map(|c| c.lines.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Scroll the document by `delta` lines. Negative values scroll up.
+    ///
+    /// We don't know the render area's height here so the offset is only
+    /// clamped to the document. Rendering clamps it again so the last page
+    /// stays full.
+    pub fn scroll(&mut self, delta: i16) {
+        let max_offset = self
+            .parsed_text
+            .as_ref()
+            .map_or(0, |t| t.lines.len().saturating_sub(1));
+        let max_offset = u16::try_from(max_offset).unwrap_or(u16::MAX);
+        self.scroll_offset = self
+            .scroll_offset
+            .saturating_add_signed(delta)
+            .min(max_offset);
+    }
+
+    /// Highlight every case insensitive match of `query` in the rendered text.
+    /// Highlights from a previous query are cleared. Matches have to be inside
+    /// of a single span.
+    pub fn highlight(&mut self, query: &str) {
+        self.parse();
+        let query: Vec<char> = query.chars().collect();
+        if query.is_empty() {
+            return;
+        }
+        let highlight = self.theme.highlight;
+        if let Some(text) = self.parsed_text.as_mut() {
+            for line in text.lines.iter_mut() {
+                let spans = std::mem::take(&mut line.spans);
+                for span in spans {
+                    line.spans.extend(highlight_span(span, &query, highlight));
+                }
+            }
+        }
+    }
+
+    /// The link destinations in selection order. A link's index is its
+    /// position in this iterator.
+    pub fn links(&self) -> impl Iterator<Item = &str> {
+        self.links.iter().map(String::as_str)
+    }
+
+    /// The number of selectable links.
+    pub fn link_count(&self) -> usize {
+        self.links.len()
+    }
+
+    /// The level, text, and line index of each heading in the document. The
+    /// line index can be used as a scroll offset to jump to the heading as
+    /// long as the lines before it aren't wrapped.
+    pub fn headings(&self) -> Vec<(HeadingLevel, String, usize)> {
+        self.headings.clone()
+    }
+
+    /// The key value pairs from the document's front matter.
+    pub fn front_matter(&self) -> &BTreeMap<String, String> {
+        &self.front_matter
+    }
+
+    /// The urls of the images in the document in the order they appear.
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    /// Returns true if we are waiting on more digits of a link index.
+    pub fn has_pending_selection(&self) -> bool {
+        !self.link_digits.is_empty()
+    }
+
+    /// Consume the markdown returning the rendered text.
+    pub fn into_text(self) -> Text<'static> {
+        if let Some(parsed) = self.parsed_text {
+            parsed
+        } else {
+            Text::raw(self.input)
+        }
+    }
+
+    /// The rendered document without any styling. List markers and
+    /// indentation are kept so it reads the same as what is shown.
+    pub fn to_plain_text(&self) -> String {
+        match &self.parsed_text {
+            Some(parsed) => parsed
+                .lines
+                .iter()
+                .map(|line| {
+                    line.spans
+                        .iter()
+                        .map(|span| span.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            None => self.input.clone(),
+        }
     }
 
     pub fn get_text(&self) -> Text {
@@ -323,16 +1770,111 @@ impl Markdown {
     }
 }
 
+fn to_superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        _ => return None,
+    })
+}
+
+fn to_subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        _ => return None,
+    })
+}
+
+/// The indent for the wrapped continuation of a line. List items line up
+/// under the item's text rather than its marker.
+fn hanging_indent(line: &Line<'_>) -> usize {
+    let Some(first) = line.spans.first() else {
+        return 0;
+    };
+    let marker = first.content.trim_start_matches(' ');
+    // Bullets are a single character followed by a space.
+    let is_list_marker = (marker.chars().count() == 2 && marker.ends_with(' '))
+        || marker == "[ ] "
+        || marker == "[x] "
+        || marker
+            .strip_suffix(". ")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    if is_list_marker {
+        first.content.width()
+    } else {
+        first.content.chars().take_while(|c| *c == ' ').count()
+    }
+}
+
 impl Widget for Markdown {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
     where
         Self: Sized,
     {
-        if let Some(parsed) = self.parsed_text {
-            parsed.render(area, buf);
-        } else {
-            let text = Text::raw(self.input);
-            text.render(area, buf);
-        }
+        (&self).render(area, buf);
+    }
+}
+
+impl Widget for &Markdown {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let area = self.render_frame(area, buf);
+        let mut cache = self.wrap_cache.borrow_mut();
+        let lines = self.cached_lines(&mut *cache, area.width);
+        render_lines(lines, self.scroll_offset, area, buf);
+    }
+}
+
+impl StatefulWidget for &Markdown {
+    type State = MarkdownViewState;
+
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        state: &mut Self::State,
+    ) {
+        let area = self.render_frame(area, buf);
+        let lines = self.cached_lines(&mut state.wrap_cache, area.width);
+        render_lines(lines, state.scroll_offset, area, buf);
+    }
+}
+
+/// Draw the lines that fit in the area starting `scroll_offset` lines down.
+/// The offset is clamped so the last page stays full.
+fn render_lines(
+    lines: &[Line<'static>],
+    scroll_offset: u16,
+    area: ratatui::prelude::Rect,
+    buf: &mut ratatui::prelude::Buffer,
+) {
+    let max_offset = lines.len().saturating_sub(area.height as usize);
+    let scroll_offset = std::cmp::min(scroll_offset as usize, max_offset);
+    for (row, line) in lines
+        .iter()
+        .skip(scroll_offset)
+        .take(area.height as usize)
+        .enumerate()
+    {
+        buf.set_line(area.x, area.y + row as u16, line, area.width);
     }
 }
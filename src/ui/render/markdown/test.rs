@@ -0,0 +1,665 @@
+use ratatui::{buffer::Buffer, layout::Rect, style::Modifier, text::Text};
+
+use super::*;
+
+/// The text of each rendered line without styling.
+fn plain_lines(md: &Markdown) -> Vec<String> {
+    md.to_plain_text().lines().map(str::to_owned).collect()
+}
+
+/// Render the markdown and return the text of each row of the buffer.
+fn render_rows(md: &Markdown, width: u16, height: u16) -> Vec<String> {
+    let area = Rect::new(0, 0, width, height);
+    let mut buf = Buffer::empty(area);
+    Widget::render(md, area, &mut buf);
+    buffer_rows(&buf)
+}
+
+fn buffer_rows(buf: &Buffer) -> Vec<String> {
+    (0..buf.area.height)
+        .map(|y| {
+            (0..buf.area.width)
+                .map(|x| buf[(x, y)].symbol())
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// The first span in the text with exactly this content.
+fn find_span<'a>(text: &'a Text<'static>, content: &str) -> &'a Span<'static> {
+    text.lines
+        .iter()
+        .flat_map(|line| line.spans.iter())
+        .find(|span| span.content == content)
+        .unwrap_or_else(|| panic!("No span with content {:?}", content))
+}
+
+#[test]
+fn test_strikethrough() {
+    let text = Markdown::from_str("Some ~~struck~~ text").into_text();
+    let struck = find_span(&text, "struck");
+    assert!(struck.style.add_modifier.contains(Modifier::CROSSED_OUT));
+    let plain = find_span(&text, "Some ");
+    assert!(!plain.style.add_modifier.contains(Modifier::CROSSED_OUT));
+}
+
+#[test]
+fn test_inline_code_style() {
+    let text = Markdown::from_str("Run `cargo test` now").into_text();
+    assert_eq!(find_span(&text, "cargo test").style, CODE_STYLE);
+    assert_ne!(find_span(&text, "Run ").style, CODE_STYLE);
+}
+
+#[test]
+fn test_code_block_style() {
+    let md = Markdown::from_str("```\nlet a = 1;\nlet b = 2;\n```\n");
+    assert_eq!(plain_lines(&md), vec!["let a = 1;", "let b = 2;"]);
+    let text = md.into_text();
+    assert_eq!(find_span(&text, "let a = 1;").style, CODE_STYLE);
+    assert_eq!(find_span(&text, "let b = 2;").style, CODE_STYLE);
+}
+
+#[test]
+fn test_code_block_keeps_lines() {
+    let md = Markdown::from_str("```rust\nfn main() {\n    println!(\"hi\");\n}\n```\n");
+    let text = md.get_text();
+    // The language label and the three lines of the snippet.
+    assert_eq!(text.lines.len(), 4);
+    assert_eq!(
+        plain_lines(&md)[1..],
+        ["fn main() {", "    println!(\"hi\");", "}"]
+    );
+}
+
+#[test]
+fn test_code_block_language_label() {
+    let fenced = Markdown::from_str("```python\nprint(1)\n```\n");
+    assert_eq!(plain_lines(&fenced), vec!["┌─ python", "print(1)"]);
+    let indented = Markdown::from_str("Some code:\n\n    print(1)\n");
+    assert_eq!(plain_lines(&indented), vec!["Some code:", "", "print(1)"]);
+}
+
+#[test]
+fn test_table() {
+    let md = Markdown::from_str("| a | bb |\n|---|---|\n| ccc | d |\n");
+    assert_eq!(plain_lines(&md), vec!["a   │ bb", "────┼───", "ccc │ d"]);
+}
+
+#[test]
+fn test_task_list() {
+    let md = Markdown::from_str("- [x] done\n- [ ] todo\n");
+    assert_eq!(plain_lines(&md), vec!["[x] done", "[ ] todo"]);
+    let text = md.into_text();
+    assert!(
+        find_span(&text, "done")
+            .style
+            .add_modifier
+            .contains(Modifier::CROSSED_OUT)
+    );
+    assert!(
+        !find_span(&text, "todo")
+            .style
+            .add_modifier
+            .contains(Modifier::CROSSED_OUT)
+    );
+}
+
+#[test]
+fn test_footnote() {
+    let md = Markdown::from_str("Text[^1].\n\n[^1]: The note.\n");
+    assert_eq!(
+        plain_lines(&md),
+        vec![
+            "Text[^1].".to_owned(),
+            "".to_owned(),
+            "─".repeat(FOOTNOTE_RULE_WIDTH),
+            "[^1] The note.".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn test_link_text_inline() {
+    let text = Markdown::from_str("See [the docs](https://example.com) here").into_text();
+    let link = find_span(&text, "the docs");
+    assert!(link.style.add_modifier.contains(Modifier::UNDERLINED));
+    let line = &text.lines[0];
+    let idx = line
+        .spans
+        .iter()
+        .position(|span| span.content == "the docs")
+        .unwrap();
+    assert_eq!(line.spans[idx + 1].content, "[0]");
+    assert_eq!(line.spans[idx + 1].style, LINK_STYLE);
+}
+
+#[test]
+fn test_select_twelfth_link() {
+    let input: String = (0..12)
+        .map(|i| format!("[link {}](https://example.com/{})\n\n", i, i))
+        .collect();
+    let mut md = Markdown::from_str(&input);
+    assert_eq!(md.link_count(), 12);
+    assert_eq!(md.handle_input(KeyCode::Char('1')), None);
+    assert!(md.has_pending_selection());
+    assert_eq!(
+        md.handle_input(KeyCode::Char('1')),
+        Some("https://example.com/11".to_owned())
+    );
+    // A single digit waits for Enter when it could start a two digit index.
+    assert_eq!(md.handle_input(KeyCode::Char('1')), None);
+    assert_eq!(
+        md.handle_input(KeyCode::Enter),
+        Some("https://example.com/1".to_owned())
+    );
+}
+
+#[test]
+fn test_links_keep_document_order() {
+    let md = Markdown::from_str(
+        "[c](https://c.example) [a](https://a.example) [b](https://b.example) \
+         [c again](https://c.example)",
+    );
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec![
+            "https://c.example",
+            "https://a.example",
+            "https://b.example"
+        ]
+    );
+    let text = md.into_text();
+    let line = &text.lines[0];
+    // The repeated link reuses its first index.
+    let indexes: Vec<&str> = line
+        .spans
+        .iter()
+        .filter(|span| span.content.starts_with('['))
+        .map(|span| span.content.as_ref())
+        .collect();
+    assert_eq!(indexes, vec!["[0]", "[1]", "[2]", "[0]"]);
+}
+
+#[test]
+fn test_set_input_replaces_document() {
+    let mut md = Markdown::from_str("first");
+    md.set_input("[one](https://one.example)");
+    md.set_input("[two](https://two.example)");
+    assert_eq!(plain_lines(&md), vec!["two[0]"]);
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec!["https://two.example"]
+    );
+}
+
+#[test]
+fn test_one_blank_line_between_paragraphs() {
+    let md = Markdown::from_str("one\n\n\n\ntwo\n\nthree\n");
+    assert_eq!(plain_lines(&md), vec!["one", "", "two", "", "three"]);
+}
+
+#[test]
+fn test_theme_heading_style() {
+    let mut theme = MarkdownTheme::default();
+    theme.headings[0] = Style::new().fg(Color::Magenta);
+    let text = Markdown::with_theme("# Title\n\nBody", theme).into_text();
+    assert_eq!(find_span(&text, "Title").style.fg, Some(Color::Magenta));
+    assert_eq!(find_span(&text, "Body").style.fg, None);
+}
+
+#[test]
+fn test_wrap_to_render_width() {
+    let md = Markdown::from_str("one two three four five");
+    let rows = render_rows(&md, 10, 4);
+    assert_eq!(rows[0].trim_end(), "one two");
+    assert_eq!(rows[1].trim_end(), "three four");
+    assert_eq!(rows[2].trim_end(), "five");
+}
+
+#[test]
+fn test_scroll_skips_lines() {
+    let mut md = Markdown::from_str("```\nline 1\nline 2\nline 3\nline 4\nline 5\n```\n");
+    md.scroll(2);
+    let rows = render_rows(&md, 10, 3);
+    assert_eq!(rows[0].trim_end(), "line 3");
+    assert_eq!(rows[1].trim_end(), "line 4");
+    assert_eq!(rows[2].trim_end(), "line 5");
+    assert!(
+        !rows
+            .iter()
+            .any(|row| row.contains("line 1") || row.contains("line 2"))
+    );
+}
+
+#[test]
+fn test_osc8_hyperlinks() {
+    let source = "See [docs](https://example.com) here";
+    let linked = "\x1b]8;;https://example.com\x1b\\docs\x1b]8;;\x1b\\";
+    let text = Markdown::from_str(source).with_hyperlinks(true).into_text();
+    find_span(&text, linked);
+
+    let text = Markdown::from_str(source).into_text();
+    find_span(&text, "docs");
+    assert!(
+        text.lines
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .all(|span| !span.content.contains('\x1b'))
+    );
+}
+
+#[test]
+fn test_reference_link() {
+    let mut md = Markdown::from_str(
+        "Read [the guide][guide] or [this][missing].\n\n[guide]: https://guide.example\n",
+    );
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec!["https://guide.example"]
+    );
+    assert_eq!(
+        md.handle_input(KeyCode::Char('0')),
+        Some("https://guide.example".to_owned())
+    );
+}
+
+#[test]
+fn test_wikilinks() {
+    let md = Markdown::from_str("[[Target]] and [[Sheet2!A1|Label]]");
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec!["Target", "Sheet2!A1"]
+    );
+    assert_eq!(plain_lines(&md), vec!["Target[0] and Label[1]"]);
+}
+
+#[test]
+fn test_image_placeholder() {
+    let md = Markdown::from_str("![a cat](https://example.com/cat.png)");
+    assert_eq!(
+        plain_lines(&md),
+        vec!["🖼 a cat (https://example.com/cat.png)"]
+    );
+    assert_eq!(md.images(), ["https://example.com/cat.png"]);
+    assert_eq!(md.link_count(), 0);
+    let text = md.into_text();
+    assert_eq!(find_span(&text, "a cat").style, IMAGE_STYLE);
+}
+
+#[test]
+fn test_highlight_every_match() {
+    let mut md = Markdown::from_str("The cat saw another Cat.");
+    md.highlight("cat");
+    let text = md.into_text();
+    let reversed: Vec<&str> = text.lines[0]
+        .spans
+        .iter()
+        .filter(|span| span.style.add_modifier.contains(Modifier::REVERSED))
+        .map(|span| span.content.as_ref())
+        .collect();
+    assert_eq!(reversed, vec!["cat", "Cat"]);
+}
+
+#[test]
+fn test_unsupported_feature_warning() {
+    let md = Markdown::from_str("above\n\n***\n\nbelow\n\n***\n");
+    assert_eq!(
+        md.warnings(),
+        ["Unsupported markdown feature: horizontal rules"]
+    );
+    assert!(Markdown::from_str("plain text").warnings().is_empty());
+}
+
+/// The rendered lines that aren't blank.
+fn non_blank_lines(md: &Markdown) -> Vec<String> {
+    plain_lines(md)
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+#[test]
+fn test_strong_inside_heading() {
+    let text = Markdown::from_str("## Sub **bold**").into_text();
+    let bold = find_span(&text, "bold");
+    assert!(bold.style.add_modifier.contains(Modifier::BOLD));
+    assert!(bold.style.add_modifier.contains(Modifier::ITALIC));
+    let plain = find_span(&text, "Sub ");
+    assert!(plain.style.add_modifier.contains(Modifier::ITALIC));
+    assert!(!plain.style.add_modifier.contains(Modifier::BOLD));
+}
+
+#[test]
+fn test_list_item_continuation_paragraph() {
+    let md = Markdown::from_str("- first para\n\n  second para\n- next item\n");
+    assert_eq!(
+        non_blank_lines(&md),
+        vec!["* first para", "  second para", "* next item"]
+    );
+}
+
+#[test]
+fn test_ordered_list_numbering() {
+    let md = Markdown::from_str("1. one\n\n2. two\n\n3. three\n\n4. four\n\n5. five\n");
+    let markers: Vec<String> = non_blank_lines(&md)
+        .iter()
+        .filter_map(|line| line.split_once(". ").map(|(marker, _)| marker.to_owned()))
+        .collect();
+    assert_eq!(markers, vec!["1", "2", "3", "4", "5"]);
+}
+
+#[test]
+fn test_html_entities_and_breaks() {
+    let md = Markdown::from_str("Tom &amp; Jerry");
+    assert_eq!(plain_lines(&md), vec!["Tom & Jerry"]);
+    let md = Markdown::from_str("first<br>second<br/>third");
+    assert_eq!(plain_lines(&md), vec!["first", "second", "third"]);
+}
+
+#[test]
+fn test_links_in_selection_order() {
+    let md = Markdown::from_str("[one](https://one.example)\n\n[two](https://two.example)\n");
+    assert_eq!(md.link_count(), 2);
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec!["https://one.example", "https://two.example"]
+    );
+}
+
+#[test]
+fn test_definition_list() {
+    let md = Markdown::from_str("Term\n: first meaning\n: second meaning\n");
+    assert_eq!(
+        non_blank_lines(&md),
+        vec!["Term", "  first meaning", "  second meaning"]
+    );
+    let text = md.into_text();
+    assert!(
+        find_span(&text, "Term")
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD)
+    );
+}
+
+#[test]
+fn test_soft_break_modes() {
+    let source = "first line\nsecond line";
+    assert_eq!(
+        plain_lines(&Markdown::from_str(source)),
+        vec!["first line second line"]
+    );
+    assert_eq!(
+        plain_lines(&Markdown::from_str(source).with_soft_break_as_newline(true)),
+        vec!["first line", "second line"]
+    );
+}
+
+#[test]
+fn test_bare_url_leaves_out_trailing_period() {
+    let md = Markdown::from_str("See https://example.com/docs. Then continue.");
+    assert_eq!(
+        md.links().collect::<Vec<&str>>(),
+        vec!["https://example.com/docs"]
+    );
+    assert_eq!(
+        plain_lines(&md),
+        vec!["See https://example.com/docs[0]. Then continue."]
+    );
+}
+
+#[test]
+fn test_render_reuses_wrapped_lines() {
+    let md = Markdown::from_str("one two three four five");
+    let cached_lines = |md: &Markdown| {
+        let cache = md.wrap_cache.borrow();
+        let cache = cache.as_ref().expect("Render didn't fill the cache");
+        (cache.width, cache.lines.as_ptr())
+    };
+    render_rows(&md, 10, 4);
+    let first = cached_lines(&md);
+    render_rows(&md, 10, 4);
+    assert_eq!(cached_lines(&md), first);
+    render_rows(&md, 20, 4);
+    assert_eq!(cached_lines(&md).0, 20);
+}
+
+#[test]
+fn test_stateful_render_uses_view_state() {
+    let md = Markdown::from_str("```\nline 1\nline 2\nline 3\n```\n");
+    let mut state = MarkdownViewState {
+        scroll_offset: 1,
+        ..Default::default()
+    };
+    let area = Rect::new(0, 0, 10, 2);
+    let mut buf = Buffer::empty(area);
+    StatefulWidget::render(&md, area, &mut buf, &mut state);
+    let first = state.wrap_cache.as_ref().map(|c| c.lines.as_ptr());
+    assert!(first.is_some());
+    let mut buf = Buffer::empty(area);
+    StatefulWidget::render(&md, area, &mut buf, &mut state);
+    // The second render reuses the wrapped lines instead of copying the
+    // document again.
+    assert_eq!(state.wrap_cache.as_ref().map(|c| c.lines.as_ptr()), first);
+    assert!(md.wrap_cache.borrow().is_none());
+    let rows = buffer_rows(&buf);
+    assert_eq!(rows[0].trim_end(), "line 2");
+    assert_eq!(rows[1].trim_end(), "line 3");
+}
+
+#[test]
+fn test_headings() {
+    let md = Markdown::from_str("# One\n\nSome text\n\n## Two\n\n### Three\n");
+    assert_eq!(
+        plain_lines(&md),
+        vec!["One", "", "Some text", "", "Two", "", "Three"]
+    );
+    assert_eq!(
+        md.headings(),
+        vec![
+            (HeadingLevel::H1, "One".to_owned(), 0),
+            (HeadingLevel::H2, "Two".to_owned(), 4),
+            (HeadingLevel::H3, "Three".to_owned(), 6),
+        ]
+    );
+}
+
+#[test]
+fn test_ansi16_color_depth() {
+    assert_eq!(
+        ColorDepth::Ansi16.resolve(Color::Rgb(250, 0, 0)),
+        Color::LightRed
+    );
+    assert_eq!(
+        ColorDepth::TrueColor.resolve(Color::Rgb(250, 0, 0)),
+        Color::Rgb(250, 0, 0)
+    );
+
+    let mut theme = MarkdownTheme::default();
+    theme.headings[0] = Style::new().fg(Color::Rgb(250, 0, 0));
+    theme.color_depth = ColorDepth::Ansi16;
+    let md = Markdown::with_theme("# Title", theme);
+    let area = Rect::new(0, 0, 10, 1);
+    let mut buf = Buffer::empty(area);
+    Widget::render(&md, area, &mut buf);
+    assert_eq!(buf[(0, 0)].fg, Color::LightRed);
+}
+
+#[test]
+fn test_left_padding() {
+    let md = Markdown::from_str("Some text\n\n- item\n  - nested\n").with_left_padding(4);
+    let rows = render_rows(&md, 20, 5);
+    for row in rows.iter() {
+        assert!(row.starts_with("    "), "{:?} isn't padded", row);
+    }
+    assert_eq!(rows[0].trim_end(), "    Some text");
+    assert_eq!(rows[2].trim_end(), "    * item");
+    assert_eq!(rows[3].trim_end(), "      ◦ nested");
+}
+
+#[test]
+fn test_front_matter() {
+    let md = Markdown::from_str("---\ntitle: Budget\nauthor: \"Sam\"\n---\n\nBody text\n");
+    assert_eq!(
+        md.front_matter(),
+        &BTreeMap::from([
+            ("author".to_owned(), "Sam".to_owned()),
+            ("title".to_owned(), "Budget".to_owned()),
+        ])
+    );
+    assert_eq!(plain_lines(&md), vec!["Body text"]);
+}
+
+#[test]
+fn test_collapse_whitespace() {
+    let source = "a    b\n\n```\na    b\n```\n";
+    assert_eq!(
+        plain_lines(&Markdown::from_str(source).with_collapse_whitespace(true)),
+        vec!["a b", "", "a    b"]
+    );
+    assert_eq!(
+        plain_lines(&Markdown::from_str(source)),
+        vec!["a    b", "", "a    b"]
+    );
+}
+
+#[test]
+fn test_plain_text_of_list() {
+    let md = Markdown::from_str("- one\n- two\n  1. nested\n");
+    assert_eq!(md.to_plain_text(), "* one\n* two\n  1. nested");
+}
+
+#[test]
+fn test_heading_survives_emphasis_end() {
+    let text = Markdown::from_str("# Title *em* after\n\nbody").into_text();
+    for content in ["Title ", "em", " after"] {
+        assert!(
+            find_span(&text, content)
+                .style
+                .add_modifier
+                .contains(Modifier::BOLD),
+            "{:?} lost the heading style",
+            content
+        );
+    }
+    assert!(
+        find_span(&text, "em")
+            .style
+            .add_modifier
+            .contains(Modifier::ITALIC)
+    );
+    assert_eq!(find_span(&text, "body").style, Style::default());
+}
+
+#[test]
+fn test_nested_bullets() {
+    let md = Markdown::from_str("- outer\n  - inner\n");
+    assert_eq!(plain_lines(&md), vec!["* outer", "  ◦ inner"]);
+}
+
+#[test]
+fn test_no_trailing_whitespace() {
+    let text = Markdown::from_str("# Title\n\n- \n- item   \n").into_text();
+    for line in text.lines.iter() {
+        if let Some(last) = line.spans.last() {
+            assert!(!last.content.is_empty(), "{:?} ends in an empty span", line);
+            assert_eq!(last.content.trim_end(), last.content);
+        }
+    }
+    assert_eq!(text.lines[0].spans.len(), 1);
+}
+
+#[test]
+fn test_table_with_wide_characters() {
+    let md = Markdown::from_str("| 名前 | x |\n|---|---|\n| a | 日本語 |\n| 😀😀😀 | y |\n");
+    let lines = plain_lines(&md);
+    assert_eq!(lines.len(), 4);
+    // The column separator is at the same display column on every row.
+    let separator_columns: Vec<usize> = lines
+        .iter()
+        .map(|line| {
+            let idx = line
+                .find(['│', '┼'])
+                .unwrap_or_else(|| panic!("{:?} has no separator", line));
+            line[..idx].width()
+        })
+        .collect();
+    assert_eq!(separator_columns, vec![7; 4]);
+}
+
+#[test]
+fn test_builder_without_tables() {
+    let source = "| a | b |\n|---|---|\n| c | d |\n";
+    let md = Markdown::builder()
+        .options(MarkdownOptions {
+            tables: false,
+            ..Default::default()
+        })
+        .build(source);
+    assert_eq!(plain_lines(&md), vec!["| a | b | |---|---| | c | d |"]);
+    assert_eq!(
+        plain_lines(&Markdown::builder().build(source)),
+        vec!["a │ b", "──┼──", "c │ d"]
+    );
+}
+
+#[test]
+fn test_nested_block_quote_gutter() {
+    let md = Markdown::from_str("> outer\n>\n> > deep\n");
+    assert_eq!(non_blank_lines(&md), vec!["│ outer", "│ │ deep"]);
+}
+
+#[test]
+fn test_link_selection_variants() {
+    let mut md = Markdown::from_str("[one](https://one.example) [two](https://two.example)");
+    assert_eq!(
+        md.select_link(KeyCode::Char('1')),
+        LinkSelection::Chosen("https://two.example".to_owned())
+    );
+    assert_eq!(
+        md.select_link(KeyCode::Char('7')),
+        LinkSelection::OutOfRange
+    );
+    assert_eq!(md.select_link(KeyCode::Char('x')), LinkSelection::NotALink);
+    // The compatibility shim only returns chosen links.
+    assert_eq!(md.handle_input(KeyCode::Char('7')), None);
+    assert_eq!(
+        md.handle_input(KeyCode::Char('0')),
+        Some("https://one.example".to_owned())
+    );
+
+    let input: String = (0..12)
+        .map(|i| format!("[link {}](https://example.com/{})\n\n", i, i))
+        .collect();
+    let mut md = Markdown::from_str(&input);
+    assert_eq!(md.select_link(KeyCode::Char('1')), LinkSelection::Pending);
+    assert_eq!(
+        md.select_link(KeyCode::Char('5')),
+        LinkSelection::OutOfRange
+    );
+    assert!(!md.has_pending_selection());
+}
+
+#[test]
+fn test_render_inside_block() {
+    let md = Markdown::from_str("hello world").with_block(Block::bordered());
+    let rows = render_rows(&md, 10, 4);
+    assert_eq!(rows[0], "┌────────┐");
+    // The text wraps to the inner width of the block.
+    assert_eq!(rows[1], "│hello   │");
+    assert_eq!(rows[2], "│world   │");
+    assert_eq!(rows[3], "└────────┘");
+}
+
+#[test]
+fn test_table_alignment_rule() {
+    let md = Markdown::from_str("| left | center | right |\n|:--|:-:|--:|\n| a | b | c |\n");
+    assert_eq!(
+        plain_lines(&md),
+        vec![
+            "left │ center │ right",
+            "├────┼─├────┤─┼─────┤",
+            "a    │   b    │     c",
+        ]
+    );
+}
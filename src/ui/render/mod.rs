@@ -1,8 +1,8 @@
 use ratatui::{
     self,
-    layout::Rect,
+    layout::{Margin, Rect},
     text::{Line, Text},
-    widgets::{Block, Paragraph, Tabs, Widget},
+    widgets::{Block, Clear, Paragraph, Tabs, Widget},
     Frame,
 };
 
@@ -12,6 +12,9 @@ pub mod viewport;
 pub use viewport::Viewport;
 pub mod dialog;
 pub mod markdown;
+pub mod wrap;
+#[cfg(test)]
+mod test;
 
 impl<'ws> Workspace<'ws> {
     fn get_render_parts(
@@ -40,6 +43,11 @@ impl<'ws> Workspace<'ws> {
             Box::new(|rect: Rect, buf: &mut Buffer, ws: &mut Self| {
                 let [text_rect, info_rect] =
                     Layout::horizontal(vec![Constraint::Fill(1), Constraint::Fill(1)]).areas(rect);
+                // The text area doubles as a formula bar showing the raw
+                // contents of the current cell.
+                ws.text_area.set_block(
+                    Block::bordered().title_top(ws.book.location.to_range_part()),
+                );
                 ws.text_area.render(text_rect, buf);
                 let hint = Paragraph::new(vec![
                     Line::from(""),
@@ -59,8 +67,13 @@ impl<'ws> Workspace<'ws> {
                     },
                 )
                 .with_selected(ws.book.location.clone())
+                .with_search_matches(&ws.state.search.matches)
+                .with_error_style(ws.state.error_style)
                 .block(table_block);
                 StatefulWidget::render(viewport, rect, buf, &mut ws.state.viewport_state);
+                if let Some(note) = ws.book.get_note(&ws.book.location) {
+                    render_note_popover(note, rect, buf);
+                }
             }),
         ];
 
@@ -75,6 +88,22 @@ impl<'ws> Workspace<'ws> {
                 )
             }));
         }
+        if self.state.modality() == &Modality::Search {
+            cs.push(Constraint::Max(1));
+            rs.push(Box::new(|rect: Rect, buf: &mut Buffer, ws: &mut Self| {
+                let title = if ws.state.search.case_sensitive {
+                    "Search (case sensitive)"
+                } else {
+                    "Search"
+                };
+                StatefulWidget::render(
+                    TextPrompt::from(title),
+                    rect,
+                    buf,
+                    &mut ws.state.search_prompt,
+                )
+            }));
+        }
         let rects: Vec<Rect> = Vec::from(
             Layout::vertical(cs)
                 .vertical_margin(2)
@@ -91,6 +120,84 @@ impl<'ws> Workspace<'ws> {
     }
 }
 
+/// The maximum width of the note popover including its border.
+const NOTE_POPOVER_WIDTH: u16 = 40;
+
+/// Render a note as a popover in the bottom right corner of the area.
+fn render_note_popover(note: &str, area: Rect, buf: &mut Buffer) {
+    let md = Markdown::from_str(note);
+    let inner = area.inner(Margin::new(1, 1));
+    let width = std::cmp::min(NOTE_POPOVER_WIDTH, inner.width);
+    let height = std::cmp::min(md.get_text().height() as u16 + 2, inner.height / 2);
+    let popover = Rect {
+        x: inner.x + inner.width - width,
+        y: inner.y + inner.height - height,
+        width,
+        height,
+    };
+    Clear.render(popover, buf);
+    let block = Block::bordered().title_top("Note");
+    let content_area = block.inner(popover);
+    block.render(popover, buf);
+    md.render(content_area, buf);
+}
+
+impl<'ws> Workspace<'ws> {
+    /// The status message if there is one otherwise the sum, average, and
+    /// count for the selected range if there is one.
+    fn range_stats_text(&self) -> String {
+        if let Some(msg) = &self.state.status_message {
+            return msg.clone();
+        }
+        if self.state.modality() != &Modality::RangeSelect {
+            return String::new();
+        }
+        let Some((start, end)) = self.state.range_select.get_range() else {
+            return String::new();
+        };
+        match self.book.get_range_stats(&AddressRange {
+            start: &start,
+            end: &end,
+        }) {
+            Ok(stats) => format!(
+                "Sum: {} Avg: {} Count: {}",
+                format_stat(stats.sum),
+                stats
+                    .average()
+                    .map(format_stat)
+                    .unwrap_or_else(|| String::from("-")),
+                stats.count
+            ),
+            Err(_) => String::new(),
+        }
+    }
+}
+
+/// The status for the cell under the cursor like `A1 | =SUM(B1:B3) = 42`.
+/// The computed value is left off when it is the same as the contents. Only
+/// the first line of multi line contents is shown.
+fn cell_status(address: &Address, raw: &str, computed: &str) -> String {
+    let name = address.to_range_part();
+    let raw = raw.lines().next().unwrap_or("");
+    let computed = computed.lines().next().unwrap_or("");
+    if raw.is_empty() {
+        name
+    } else if raw == computed {
+        format!("{} | {}", name, raw)
+    } else {
+        format!("{} | {} = {}", name, raw, computed)
+    }
+}
+
+/// Format a statistic trimming the floating point noise.
+fn format_stat(n: f64) -> String {
+    let formatted = format!("{:.4}", n);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
 impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
     where
@@ -105,6 +212,13 @@ impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
                 .unwrap_or_else(|| Text::raw("Popup message here"));
             let popup = dialog::Dialog::new(lines, "Help").scroll(self.state.dialog_scroll);
             popup.render(area, buf);
+        } else if self.state.modality() == &Modality::Reload {
+            let popup = dialog::Dialog::new(
+                Text::raw("File changed on disk. Reload it and discard your edits?"),
+                "Reload",
+            )
+            .with_bottom_title("Y/N");
+            popup.render(area, buf);
         } else if self.state.modality() == &Modality::Quit {
             let popup =
                 dialog::Dialog::new(Text::raw("File is not yet saved. Save it first?"), "Quit")
@@ -122,14 +236,18 @@ impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
                     Modality::Navigate => "navigate",
                     Modality::CellEdit => "edit",
                     Modality::Command => "command",
+                    Modality::Search => "search",
                     Modality::Dialog => "",
                     Modality::RangeSelect => "range-copy",
                     Modality::Quit => "",
+                    Modality::Reload => "",
                 })
+                .title_bottom(Line::from(self.range_stats_text()).centered())
                 .title_bottom(
-                    Line::from(format!(
-                        "{},{}",
-                        self.book.location.row, self.book.location.col
+                    Line::from(cell_status(
+                        &self.book.location,
+                        &self.book.get_current_cell_contents().unwrap_or_default(),
+                        &self.book.get_current_cell_rendered().unwrap_or_default(),
                     ))
                     .right_aligned(),
                 );
@@ -0,0 +1,443 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier},
+    widgets::{Block, ScrollbarState, StatefulWidget},
+};
+
+use super::viewport::ViewportState;
+use super::*;
+use crate::book::{
+    conditional::{Condition, ConditionalFormat},
+    format::CellAlignment,
+};
+
+/// The x position of the first column when rendered in a bordered block.
+const FIRST_COL_X: u16 = 6;
+/// The y position of the first row when rendered in a bordered block.
+const FIRST_ROW_Y: u16 = 2;
+
+fn set(book: &mut Book, row: usize, col: usize, value: &str) {
+    book.update_cell(&Address::new(row, col), value)
+        .expect("Failed to update cell");
+}
+
+fn render_viewport(book: &Book, width: u16, height: u16, state: &mut ViewportState) -> Buffer {
+    let area = Rect::new(0, 0, width, height);
+    let mut buf = Buffer::empty(area);
+    let viewport = Viewport::new(book, None)
+        .with_selected(book.location.clone())
+        .block(Block::bordered());
+    StatefulWidget::render(viewport, area, &mut buf, state);
+    buf
+}
+
+#[test]
+fn test_error_cell_uses_error_color() {
+    let mut book = Book::default();
+    set(&mut book, 1, 1, "=1/0");
+    set(&mut book, 1, 2, "5");
+    book.set_col_size(1, 10).expect("Failed to set column size");
+    book.set_col_size(2, 10).expect("Failed to set column size");
+    book.evaluate();
+    // Keep the cursor off of the cells we are checking.
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 60, 10, &mut ViewportState::default());
+    assert_eq!(buf[(FIRST_COL_X, FIRST_ROW_Y)].symbol(), "#");
+    assert_eq!(buf[(FIRST_COL_X, FIRST_ROW_Y)].fg, Color::Red);
+    assert_eq!(buf[(FIRST_COL_X + 10, FIRST_ROW_Y)].symbol(), "5");
+    assert_ne!(buf[(FIRST_COL_X + 10, FIRST_ROW_Y)].fg, Color::Red);
+}
+
+#[test]
+fn test_cell_status_shows_formula_and_value() {
+    let addr = Address::new(1, 1);
+    assert_eq!(cell_status(&addr, "=1+2", "3"), "A1 | =1+2 = 3");
+    assert_eq!(cell_status(&addr, "hello", "hello"), "A1 | hello");
+    assert_eq!(cell_status(&addr, "", ""), "A1");
+    assert_eq!(
+        cell_status(&addr, "line one\nline two", "line one\nline two"),
+        "A1 | line one"
+    );
+}
+
+#[test]
+fn test_large_sheet_only_visits_visible_cells() {
+    let mut book = Book::default();
+    for row in 1..=1000 {
+        set(&mut book, row, 1, &row.to_string());
+    }
+    set(&mut book, 100_000, 200, "far away");
+    book.location = Address::new(500, 3);
+    let state = ViewportState::default();
+    let viewport = Viewport::new(&book, None).with_selected(book.location.clone());
+    let columns = viewport
+        .get_visible_columns(80, &state)
+        .expect("Failed to get visible columns");
+    assert!(columns.iter().any(|vc| vc.idx == 3));
+    assert!(columns.iter().map(|vc| vc.length).sum::<u16>() < 80);
+    let rows = viewport.get_visible_rows(20, &columns, &state);
+    // One line goes to the header.
+    assert_eq!(rows, (482..=500).collect::<Vec<usize>>());
+
+    let mut state = ViewportState::default();
+    render_viewport(&book, 82, 22, &mut state);
+    assert_eq!(state.corner().row, 482);
+}
+
+/// The text of a line of the buffer.
+fn buffer_line(buf: &Buffer, y: u16) -> String {
+    (0..buf.area.width).map(|x| buf[(x, y)].symbol()).collect()
+}
+
+#[test]
+fn test_hidden_column_is_not_rendered() {
+    let mut book = Book::default();
+    for col in 1..=4 {
+        book.set_col_size(col, 5)
+            .expect("Failed to set column size");
+    }
+    set(&mut book, 1, 1, "1");
+    set(&mut book, 1, 2, "2");
+    set(&mut book, 1, 3, "3");
+    set(&mut book, 1, 4, "=SUM(A1:C1)");
+    book.hide_columns(2, 1);
+    book.evaluate();
+    // Hidden columns still count in formulas.
+    assert_eq!(
+        book.get_cell_addr_rendered(&Address::new(1, 4)).unwrap(),
+        "6"
+    );
+
+    let state = ViewportState::default();
+    let viewport = Viewport::new(&book, None).with_selected(book.location.clone());
+    let columns = viewport
+        .get_visible_columns(30, &state)
+        .expect("Failed to get visible columns");
+    let indexes: Vec<usize> = columns.iter().map(|vc| vc.idx).collect();
+    assert_eq!(&indexes[..3], &[1, 3, 4]);
+    assert!(!indexes.contains(&2));
+
+    let buf = render_viewport(&book, 32, 6, &mut ViewportState::default());
+    let header = buffer_line(&buf, FIRST_ROW_Y - 1);
+    assert!(header.contains('A'));
+    assert!(!header.contains('B'));
+    assert!(header.contains('C'));
+    let row = buffer_line(&buf, FIRST_ROW_Y);
+    assert!(row.contains('3'));
+    assert!(row.contains('6'));
+    assert!(!row.contains('2'));
+}
+
+/// The visible (rows, columns) of a viewport 40 columns wide and 11 lines
+/// tall with the cursor at `selected`.
+fn visible_cells(
+    book: &Book,
+    selected: Address,
+    state: &ViewportState,
+) -> (Vec<usize>, Vec<usize>) {
+    let viewport = Viewport::new(book, None).with_selected(selected);
+    let columns = viewport
+        .get_visible_columns(40, state)
+        .expect("Failed to get visible columns");
+    let rows = viewport.get_visible_rows(11, &columns, state);
+    (rows, columns.iter().map(|vc| vc.idx).collect())
+}
+
+#[test]
+fn test_frozen_header_stays_visible() {
+    let mut book = Book::default();
+    for col in 1..=25 {
+        book.set_col_size(col, 5)
+            .expect("Failed to set column size");
+    }
+    book.freeze(1, 1);
+    let mut state = ViewportState::default();
+    // Scrolling down and right keeps the first row and column in view.
+    let (rows, cols) = visible_cells(&book, Address::new(50, 20), &state);
+    assert_eq!(rows[0], 1);
+    assert_eq!(rows[1..], (42..=50).collect::<Vec<usize>>());
+    assert_eq!(cols, vec![1, 16, 17, 18, 19, 20]);
+
+    // Moving into the frozen cells leaves the scrolled cells where they were.
+    state.set_corner(Address::new(42, 16));
+    let (rows, cols) = visible_cells(&book, Address::new(1, 1), &state);
+    assert_eq!(rows[0], 1);
+    assert_eq!(rows[1..], (42..=50).collect::<Vec<usize>>());
+    assert_eq!(cols, vec![1, 16, 17, 18, 19, 20]);
+
+    // Without frozen cells the first row scrolls away.
+    book.freeze(0, 0);
+    let (rows, cols) = visible_cells(&book, Address::new(50, 20), &ViewportState::default());
+    assert_eq!(rows, (41..=50).collect::<Vec<usize>>());
+    assert_eq!(cols, vec![15, 16, 17, 18, 19, 20]);
+}
+
+#[test]
+fn test_address_at_with_frozen_header() {
+    let mut book = Book::default();
+    for col in 1..=25 {
+        book.set_col_size(col, 5)
+            .expect("Failed to set column size");
+    }
+    book.freeze(1, 1);
+    book.location = Address::new(50, 20);
+    let mut state = ViewportState::default();
+    // The inside of the border is 40 columns wide and 11 lines tall.
+    render_viewport(&book, 42, 13, &mut state);
+    let at = |x, y| state.address_at(0, x, y).map(|a| (a.row, a.col));
+    // The frozen cell A1 is in the top left.
+    assert_eq!(at(FIRST_COL_X, FIRST_ROW_Y), Some((1, 1)));
+    assert_eq!(at(FIRST_COL_X + 4, FIRST_ROW_Y), Some((1, 1)));
+    // The scrolled cells come right after the frozen ones.
+    assert_eq!(at(FIRST_COL_X + 5, FIRST_ROW_Y), Some((1, 16)));
+    assert_eq!(at(FIRST_COL_X, FIRST_ROW_Y + 1), Some((42, 1)));
+    assert_eq!(at(FIRST_COL_X + 5, FIRST_ROW_Y + 1), Some((42, 16)));
+    assert_eq!(at(FIRST_COL_X + 29, FIRST_ROW_Y + 9), Some((50, 20)));
+    // The header, the gutter, and the border aren't cells.
+    assert_eq!(at(FIRST_COL_X, FIRST_ROW_Y - 1), None);
+    assert_eq!(at(FIRST_COL_X - 1, FIRST_ROW_Y), None);
+    assert_eq!(at(FIRST_COL_X, FIRST_ROW_Y + 10), None);
+    assert_eq!(at(41, FIRST_ROW_Y), None);
+}
+
+#[test]
+fn test_center_line_padding() {
+    let centered = |text: &'static str, width| {
+        let line = wrap::center_line(&Line::from(text), width);
+        line.spans
+            .iter()
+            .map(|s| s.content.as_ref())
+            .collect::<String>()
+    };
+    assert_eq!(centered("ab", 10), "    ab");
+    // Odd leftover space puts the extra column on the right.
+    assert_eq!(centered("abc", 10), "   abc");
+    assert_eq!(centered("日本", 9), "  日本");
+    assert_eq!(centered("too wide", 4), "too wide");
+}
+
+#[test]
+fn test_cell_alignment() {
+    let mut book = Book::default();
+    book.set_col_size(1, 10).expect("Failed to set column size");
+    let alignments = [
+        CellAlignment::Left,
+        CellAlignment::Center,
+        CellAlignment::Right,
+    ];
+    for (idx, alignment) in alignments.into_iter().enumerate() {
+        let row = idx + 1;
+        set(&mut book, row, 1, "abc");
+        let area = Area {
+            sheet: 0,
+            row: row as i32,
+            column: 1,
+            width: 1,
+            height: 1,
+        };
+        book.set_cell_alignment(alignment, &area)
+            .expect("Failed to align cell");
+    }
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 60, 10, &mut ViewportState::default());
+    let start_of = |row: u16| {
+        let line = buffer_line(&buf, FIRST_ROW_Y + row);
+        let x = line.find("abc").expect("Missing cell text");
+        line[..x].chars().count() as u16 - FIRST_COL_X
+    };
+    assert_eq!(start_of(0), 0);
+    assert_eq!(start_of(1), 3);
+    assert_eq!(start_of(2), 7);
+}
+
+#[test]
+fn test_wrapped_cell_grows_its_row() {
+    let mut book = Book::default();
+    book.set_col_size(1, 5).expect("Failed to set column size");
+    book.set_col_size(2, 5).expect("Failed to set column size");
+    set(&mut book, 1, 1, "aaaa bbbb cccc");
+    set(&mut book, 1, 2, "x");
+    set(&mut book, 2, 1, "next");
+    set(&mut book, 2, 2, "y");
+    let area = Area {
+        sheet: 0,
+        row: 1,
+        column: 1,
+        width: 1,
+        height: 1,
+    };
+    book.set_cell_style(&[("alignment.wrap_text", "true")], &area)
+        .expect("Failed to wrap cell");
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 40, 10, &mut ViewportState::default());
+    let cell = |col: u16, line: u16| -> String {
+        let x = FIRST_COL_X + col * 5;
+        (x..x + 5)
+            .map(|x| buf[(x, FIRST_ROW_Y + line)].symbol())
+            .collect::<String>()
+            .trim_end()
+            .to_owned()
+    };
+    // The wrapped cell takes up three lines of its row.
+    assert_eq!(cell(0, 0), "aaaa");
+    assert_eq!(cell(0, 1), "bbbb");
+    assert_eq!(cell(0, 2), "cccc");
+    // Its neighbor stays on the first line of the row.
+    assert_eq!(cell(1, 0), "x");
+    assert_eq!(cell(1, 1), "");
+    // The next row starts below the wrapped lines with its cells lined up.
+    assert_eq!(cell(0, 3), "next");
+    assert_eq!(cell(1, 3), "y");
+    assert!(buffer_line(&buf, FIRST_ROW_Y + 3).contains('2'));
+}
+
+#[test]
+fn test_conditional_format_colors_matching_cells() {
+    let mut book = Book::default();
+    book.set_col_size(1, 10).expect("Failed to set column size");
+    set(&mut book, 1, 1, "5");
+    set(&mut book, 2, 1, "50");
+    book.add_conditional_format(ConditionalFormat {
+        start: Address::new(1, 1),
+        end: Address::new(2, 1),
+        condition: Condition::GreaterThan(10.0),
+        bg_color: Some("blue".to_owned()),
+        fg_color: Some("yellow".to_owned()),
+    });
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 60, 10, &mut ViewportState::default());
+    let matched = &buf[(FIRST_COL_X, FIRST_ROW_Y + 1)];
+    assert_eq!(matched.symbol(), "5");
+    assert_eq!(matched.bg, Color::Blue);
+    assert_eq!(matched.fg, Color::Yellow);
+    let unmatched = &buf[(FIRST_COL_X, FIRST_ROW_Y)];
+    assert_eq!(unmatched.symbol(), "5");
+    assert_ne!(unmatched.bg, Color::Blue);
+    assert_ne!(unmatched.fg, Color::Yellow);
+}
+
+#[test]
+fn test_markdown_cell_renders_bold() {
+    let mut book = Book::default();
+    book.set_col_size(1, 15).expect("Failed to set column size");
+    set(&mut book, 1, 1, "md:**bold** text");
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 60, 10, &mut ViewportState::default());
+    assert!(buffer_line(&buf, FIRST_ROW_Y).contains("bold text"));
+    let bold = &buf[(FIRST_COL_X, FIRST_ROW_Y)];
+    assert_eq!(bold.symbol(), "b");
+    assert!(bold.modifier.contains(Modifier::BOLD));
+    let plain = &buf[(FIRST_COL_X + 5, FIRST_ROW_Y)];
+    assert_eq!(plain.symbol(), "t");
+    assert!(!plain.modifier.contains(Modifier::BOLD));
+}
+
+#[test]
+fn test_scrollbar_state() {
+    let state = |first, visible, extent| viewport::scrollbar_state(first, visible, extent);
+    assert_eq!(
+        state(1, 10, 100),
+        ScrollbarState::new(100)
+            .position(0)
+            .viewport_content_length(10)
+    );
+    assert_eq!(
+        state(50, 10, 100),
+        ScrollbarState::new(100)
+            .position(49)
+            .viewport_content_length(10)
+    );
+    // Scrolling past the data grows the content to include the viewport.
+    assert_eq!(
+        state(200, 10, 100),
+        ScrollbarState::new(209)
+            .position(199)
+            .viewport_content_length(10)
+    );
+    // An empty sheet is all viewport.
+    assert_eq!(
+        state(1, 10, 0),
+        ScrollbarState::new(10)
+            .position(0)
+            .viewport_content_length(10)
+    );
+}
+
+#[test]
+fn test_scrollbar_thumb_follows_viewport() {
+    let mut book = Book::default();
+    for row in 1..=1000 {
+        set(&mut book, row, 1, &row.to_string());
+    }
+    book.evaluate();
+    // The vertical scrollbar is drawn over the right border.
+    let thumb = |book: &Book| {
+        let buf = render_viewport(book, 60, 20, &mut ViewportState::default());
+        (0..buf.area.height)
+            .find(|y| buf[(buf.area.width - 1, *y)].symbol() == "█")
+            .expect("Missing scrollbar thumb")
+    };
+    book.location = Address::new(1, 1);
+    let top = thumb(&book);
+    book.location = Address::new(1000, 1);
+    let bottom = thumb(&book);
+    assert!(top < bottom);
+}
+
+#[test]
+fn test_column_name() {
+    assert_eq!(viewport::column_name(1), "A");
+    assert_eq!(viewport::column_name(26), "Z");
+    assert_eq!(viewport::column_name(27), "AA");
+    assert_eq!(viewport::column_name(52), "AZ");
+    assert_eq!(viewport::column_name(53), "BA");
+    assert_eq!(viewport::column_name(702), "ZZ");
+    assert_eq!(viewport::column_name(703), "AAA");
+}
+
+#[test]
+fn test_markdown_cell_is_cut_off_at_the_column_width() {
+    let mut book = Book::default();
+    book.set_col_size(1, 6).expect("Failed to set column size");
+    book.set_col_size(2, 6).expect("Failed to set column size");
+    set(&mut book, 1, 1, "md:**bold words**");
+    set(&mut book, 1, 2, "next");
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 40, 10, &mut ViewportState::default());
+    let line = buffer_line(&buf, FIRST_ROW_Y);
+    let start = line
+        .char_indices()
+        .nth(FIRST_COL_X as usize)
+        .map(|(i, _)| i)
+        .expect("Line is too short");
+    // The bold text stops at the column and the next cell is left alone.
+    assert!(line[start..].starts_with("bold wnext"));
+    for x in FIRST_COL_X..FIRST_COL_X + 6 {
+        assert!(buf[(x, FIRST_ROW_Y)].modifier.contains(Modifier::BOLD));
+    }
+    assert!(
+        !buf[(FIRST_COL_X + 6, FIRST_ROW_Y)]
+            .modifier
+            .contains(Modifier::BOLD)
+    );
+}
+
+#[test]
+fn test_narrow_markdown_cell_falls_back_to_plain_text() {
+    let mut book = Book::default();
+    book.set_col_size(1, 3).expect("Failed to set column size");
+    set(&mut book, 1, 1, "md:**bold**");
+    book.evaluate();
+    book.location = Address::new(5, 1);
+    let buf = render_viewport(&book, 40, 10, &mut ViewportState::default());
+    let cell = &buf[(FIRST_COL_X, FIRST_ROW_Y)];
+    assert_eq!(cell.symbol(), "b");
+    assert!(!cell.modifier.contains(Modifier::BOLD));
+    assert_eq!(buf[(FIRST_COL_X + 2, FIRST_ROW_Y)].symbol(), "l");
+}
@@ -1,13 +1,24 @@
+use std::collections::VecDeque;
+
 use anyhow::Result;
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Flex, Rect},
-    style::{Color, Stylize},
-    text::{Line, Text},
-    widgets::{Block, Cell, Row, StatefulWidget, Table, Widget},
+    layout::{Constraint, Flex, Margin, Rect},
+    style::{Color, Style, Styled, Stylize},
+    text::{Line, Span, Text},
+    widgets::{
+        Block, Cell, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Table,
+        Widget,
+    },
 };
 
-use super::{Address, Book, RangeSelection};
+use ironcalc::base::types::HorizontalAlignment;
+
+use super::{
+    Address, Book, RangeSelection,
+    markdown::Markdown,
+    wrap::{center_line, line_width, truncate_line, wrap_str},
+};
 use crate::book;
 
 /// A visible column to show in our Viewport.
@@ -26,6 +37,52 @@ impl<'a> From<&'a VisibleColumn> for Constraint {
 #[derive(Debug, Default)]
 pub struct ViewportState {
     prev_corner: Address,
+    /// The number of (rows, columns) visible in the last render.
+    visible: (usize, usize),
+    /// Where the table was drawn in the last render.
+    area: Rect,
+    /// The (row, height) of each row drawn in the last render in order.
+    rows: Vec<(usize, u16)>,
+    /// The columns drawn in the last render in order.
+    columns: Vec<VisibleColumn>,
+}
+
+impl ViewportState {
+    /// The top left corner of the viewport from the last render.
+    pub fn corner(&self) -> &Address {
+        &self.prev_corner
+    }
+
+    /// Set the top left corner of the viewport for the next render.
+    pub fn set_corner(&mut self, corner: Address) {
+        self.prev_corner = corner;
+    }
+
+    /// The address of the cell drawn at a terminal position in the last
+    /// render. Positions on the header, the gutter, or past the last cell
+    /// have no address.
+    pub fn address_at(&self, sheet: u32, x: u16, y: u16) -> Option<Address> {
+        let area = self.area;
+        if x < area.x + GUTTER_WIDTH || y <= area.y || x >= area.right() || y >= area.bottom() {
+            return None;
+        }
+        let mut left = area.x + GUTTER_WIDTH;
+        let col = self.columns.iter().find(|vc| {
+            left += vc.length;
+            x < left
+        })?;
+        // The header takes up the first line.
+        let mut top = area.y + 1;
+        let (row, _) = self.rows.iter().find(|(_, height)| {
+            top += height;
+            y < top
+        })?;
+        Some(Address {
+            sheet,
+            row: *row,
+            col: col.idx,
+        })
+    }
 }
 
 /// A renderable viewport over a book.
@@ -34,12 +91,49 @@ pub struct Viewport<'ws> {
     book: &'ws Book<'ws>,
     range_selection: Option<&'ws RangeSelection>,
     block: Option<Block<'ws>>,
+    error_style: Style,
+    /// Cells matching the current search in row then column order.
+    search_matches: &'ws [Address],
 }
 
-pub(crate) const COLNAMES: [&'static str; 26] = [
-    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
-    "T", "U", "V", "W", "X", "Y", "Z",
-];
+/// Cells with content starting with this prefix are rendered as markdown.
+pub(crate) const MARKDOWN_CELL_PREFIX: &'static str = "md:";
+
+/// The width of the row number gutter on the left of the table.
+const GUTTER_WIDTH: u16 = 5;
+
+/// Columns narrower than this show markdown cells as plain text.
+const MIN_MARKDOWN_CELL_WIDTH: u16 = 4;
+
+/// Marker drawn in the top right corner of cells that have a note attached.
+pub(crate) const NOTE_MARKER: &'static str = "◥";
+
+/// The default foreground color for cells whose formula failed to evaluate.
+pub(crate) const DEFAULT_ERROR_COLOR: Color = Color::Red;
+
+/// Colors for the gutter label of the row and column the cursor is in.
+const CURSOR_GUTTER_BG: Color = Color::LightBlue;
+const CURSOR_GUTTER_FG: Color = Color::Black;
+
+/// The background color for cells matching a search.
+const SEARCH_MATCH_BG: Color = Color::Yellow;
+
+/// The background color for locked cells without a color of their own. It is
+/// a little darker than the default background.
+const LOCKED_CELL_BG: Color = Color::Rgb(24, 23, 36);
+
+/// The letter name of a 1 based column index. After `Z` come `AA`, `AB`, ...
+/// `AZ`, `BA` and so on.
+pub(crate) fn column_name(col: usize) -> String {
+    let mut name = Vec::new();
+    let mut n = col;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        name.push((b'A' + rem as u8) as char);
+        n = (n - 1) / 26;
+    }
+    name.iter().rev().collect()
+}
 
 impl<'ws> Viewport<'ws> {
     pub fn new(book: &'ws Book, app_state: Option<&'ws RangeSelection>) -> Self {
@@ -48,34 +142,122 @@ impl<'ws> Viewport<'ws> {
             range_selection: app_state,
             selected: Default::default(),
             block: None,
+            error_style: Style::default().fg(DEFAULT_ERROR_COLOR),
+            search_matches: &[],
         }
     }
 
+    /// Highlight the cells that match a search. `matches` must be sorted.
+    pub fn with_search_matches(mut self, matches: &'ws [Address]) -> Self {
+        self.search_matches = matches;
+        self
+    }
+
+    /// Set the style patched onto cells whose formula failed to evaluate.
+    pub fn with_error_style(mut self, style: Style) -> Self {
+        self.error_style = style;
+        self
+    }
+
     pub fn with_selected(mut self, location: Address) -> Self {
         self.selected = location;
         self
     }
 
-    pub(crate) fn get_visible_rows(&self, height: u16, state: &ViewportState) -> Vec<usize> {
-        // NOTE(jeremy): For now the row default height is 1. We'll have
-        // to adjust that if this changes.
-        let mut length = 1;
-        let start_row = std::cmp::min(self.selected.row, state.prev_corner.row);
-        let mut start = start_row;
-        let mut end = start_row;
-        for row_idx in start_row..=(book::LAST_ROW as usize) {
-            let updated_length = length + 1;
-            if updated_length <= height {
-                length = updated_length;
-                end = row_idx;
-            } else if self.selected.row >= row_idx {
-                start = start + 1;
-                end = row_idx;
-            } else {
+    pub(crate) fn get_visible_rows(
+        &self,
+        height: u16,
+        columns: &[VisibleColumn],
+        state: &ViewportState,
+    ) -> Vec<usize> {
+        // One line of the height is taken by the header.
+        let lines = std::cmp::max(height.saturating_sub(1) as usize, 1);
+        let sheet = self.book.location.sheet;
+        // Frozen rows are always shown first and the rest scroll below them.
+        let (frozen, _) = self.book.get_frozen(sheet);
+        let (mut rows, used) = self.take_fitting(
+            (1..=frozen).filter(|r| !self.book.is_row_hidden(sheet, *r)),
+            lines,
+            columns,
+        );
+        let lines = lines.saturating_sub(used);
+        let first = frozen + 1;
+        // NOTE(zaphar): Moving into the frozen rows leaves the scrolled rows
+        // where they were.
+        let start = if self.selected.row < first {
+            std::cmp::max(state.prev_corner.row, first)
+        } else {
+            std::cmp::max(
+                std::cmp::min(self.selected.row, state.prev_corner.row),
+                first,
+            )
+        };
+        let (mut scrolled, _) = self.take_fitting(
+            (start..=(book::LAST_ROW as usize)).filter(|r| !self.book.is_row_hidden(sheet, *r)),
+            lines,
+            columns,
+        );
+        if scrolled
+            .last()
+            .map_or(false, |last| self.selected.row > *last)
+        {
+            // Slide the window down just far enough to show the selected row.
+            (scrolled, _) = self.take_fitting(
+                (first..=self.selected.row)
+                    .rev()
+                    .filter(|r| !self.book.is_row_hidden(sheet, *r)),
+                lines,
+                columns,
+            );
+            scrolled.reverse();
+        }
+        rows.extend(scrolled);
+        return rows;
+    }
+
+    /// Take rows while their heights fit in `lines`. The first row is taken
+    /// whenever there are any lines so a row taller than the viewport still
+    /// shows. Returns the rows and the number of lines they use.
+    fn take_fitting(
+        &self,
+        rows: impl Iterator<Item = usize>,
+        lines: usize,
+        columns: &[VisibleColumn],
+    ) -> (Vec<usize>, usize) {
+        let mut taken = Vec::new();
+        let mut used = 0;
+        for row in rows {
+            let height = self.row_height(row, columns);
+            if lines == 0 || (!taken.is_empty() && used + height > lines) {
                 break;
             }
+            taken.push(row);
+            used += height;
         }
-        return (start..=end).collect();
+        (taken, used)
+    }
+
+    /// The number of lines a row takes up. Wrapped cells in the visible
+    /// columns grow the height of their row.
+    fn row_height(&self, row: usize, columns: &[VisibleColumn]) -> usize {
+        columns
+            .iter()
+            .filter_map(|vc| {
+                let address = Address {
+                    sheet: self.book.location.sheet,
+                    row,
+                    col: vc.idx,
+                };
+                if !self.book.is_cell_wrapped(&address) {
+                    return None;
+                }
+                let content = self.book.get_cell_addr_rendered(&address).ok()?;
+                if content.starts_with(MARKDOWN_CELL_PREFIX) {
+                    return None;
+                }
+                Some(wrap_str(&content, vc.length as usize).len())
+            })
+            .fold(1, std::cmp::max)
     }
 
     pub(crate) fn get_visible_columns(
@@ -83,43 +265,65 @@ impl<'ws> Viewport<'ws> {
         width: u16,
         state: &ViewportState,
     ) -> Result<Vec<VisibleColumn>> {
-        let mut visible = Vec::new();
-        // TODO(zaphar): This should be a shared constant with our first column.
-        // We start out with a length of 5 already reserved
-        let mut length = 5;
-        let start_idx = std::cmp::min(self.selected.col, state.prev_corner.col);
+        let mut visible = VecDeque::new();
+        // We start out with the gutter already reserved
+        let mut length = GUTTER_WIDTH;
+        let sheet = self.book.location.sheet;
+        // Frozen columns are always shown first and the rest scroll after them.
+        let (_, frozen_count) = self.book.get_frozen(sheet);
+        let mut frozen = Vec::new();
+        for idx in 1..=frozen_count {
+            if self.book.is_col_hidden(sheet, idx) {
+                continue;
+            }
+            let size = self.book.get_col_size(idx)? as u16;
+            if length + size >= width {
+                break;
+            }
+            length += size;
+            frozen.push(VisibleColumn { idx, length: size });
+        }
+        let first_scrolled = frozen_count + 1;
+        let start_idx = if self.selected.col < first_scrolled {
+            std::cmp::max(state.prev_corner.col, first_scrolled)
+        } else {
+            std::cmp::max(
+                std::cmp::min(self.selected.col, state.prev_corner.col),
+                first_scrolled,
+            )
+        };
         for idx in start_idx..=(book::LAST_COLUMN as usize) {
+            if self.book.is_col_hidden(self.book.location.sheet, idx) {
+                continue;
+            }
             let size = self.book.get_col_size(idx)? as u16;
             let updated_length = length + size;
             let col = VisibleColumn { idx, length: size };
             if updated_length < width {
                 length = updated_length;
-                visible.push(col);
+                visible.push_back(col);
             } else if self.selected.col >= col.idx {
                 // We need a sliding window now
-                if let Some(first) = visible.first() {
+                if let Some(first) = visible.pop_front() {
                     // subtract the first columns size.
                     length = length - first.length;
-                    // remove the first column.
-                    // TODO(jwall): This is a bit inefficient. Can we do better?
-                    visible = visible.into_iter().skip(1).collect();
                 }
                 // Add this col to the visible.
                 length += size;
-                visible.push(col);
+                visible.push_back(col);
                 // What if the length is still too long?
                 if length > width {
-                    if let Some(first) = visible.first() {
+                    if let Some(first) = visible.pop_front() {
                         // subtract the first columns size.
                         length = length - first.length;
                     }
-                    visible = visible.into_iter().skip(1).collect();
                 }
             } else {
                 break;
             }
         }
-        return Ok(visible);
+        frozen.extend(visible);
+        return Ok(frozen);
     }
 
     pub fn block(mut self, block: Block<'ws>) -> Self {
@@ -134,54 +338,99 @@ impl<'ws> Viewport<'ws> {
         state: &mut ViewportState,
     ) -> Result<Table<'widget>> {
         let visible_columns = self.get_visible_columns(width, state)?;
-        let visible_rows = self.get_visible_rows(height, state);
-        if let Some(vc) = visible_columns.first() {
+        let visible_rows = self.get_visible_rows(height, &visible_columns, state);
+        state.visible = (visible_rows.len(), visible_columns.len());
+        // The corner is where the scrolled part of the sheet starts.
+        let (frozen_rows, frozen_cols) = self.book.get_frozen(self.book.location.sheet);
+        if let Some(vc) = visible_columns.iter().find(|vc| vc.idx > frozen_cols) {
             state.prev_corner.col = vc.idx
         }
-        if let Some(vr) = visible_rows.first() {
+        if let Some(vr) = visible_rows.iter().find(|vr| **vr > frozen_rows) {
             state.prev_corner.row = *vr;
         }
+        let mut row_layout = Vec::with_capacity(visible_rows.len());
         let rows: Vec<Row> =
             visible_rows
                 .into_iter()
                 .map(|ri| {
-                    let mut cells = vec![Cell::new(Text::from(ri.to_string()))];
+                    let mut height = 1;
+                    let mut gutter = Cell::new(Text::from(ri.to_string()));
+                    if ri == self.book.location.row {
+                        gutter = gutter.bg(CURSOR_GUTTER_BG).fg(CURSOR_GUTTER_FG).bold();
+                    }
+                    let mut cells = vec![gutter];
                     cells.extend(visible_columns.iter().map(
-                        |VisibleColumn { idx: ci, length: _ }| {
-                            let content = self
-                                .book
-                                .get_cell_addr_rendered(&Address {
-                                    row: ri,
-                                    col: *ci,
-                                    sheet: self.book.location.sheet,
-                                })
-                                .unwrap();
-                            self.compute_cell_style(ri, *ci, Cell::new(Text::raw(content)))
+                        |VisibleColumn { idx: ci, length }| {
+                            let address = Address {
+                                row: ri,
+                                col: *ci,
+                                sheet: self.book.location.sheet,
+                            };
+                            let content = self.book.get_cell_addr_rendered(&address).unwrap();
+                            let is_error = book::is_error_value(&content);
+                            let mut text = if let Some(md) =
+                                content.strip_prefix(MARKDOWN_CELL_PREFIX)
+                            {
+                                markdown_cell_text(md, *length)
+                            } else if self.book.is_cell_wrapped(&address) {
+                                let lines = wrap_str(&content, *length as usize);
+                                height = std::cmp::max(height, lines.len());
+                                Text::from(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+                            } else {
+                                Text::raw(content)
+                            };
+                            text = match self.book.get_cell_alignment(&address) {
+                                Some(HorizontalAlignment::Right) => text.right_aligned(),
+                                Some(HorizontalAlignment::Center) => {
+                                    text.lines = text
+                                        .lines
+                                        .iter()
+                                        .map(|line| center_line(line, *length as usize))
+                                        .collect();
+                                    text
+                                }
+                                _ => text,
+                            };
+                            if self.book.get_note(&address).is_some() {
+                                text = with_note_marker(text, *length);
+                            }
+                            let cell = self.compute_cell_style(ri, *ci, Cell::new(text));
+                            if is_error {
+                                let style = Styled::style(&cell).patch(self.error_style);
+                                cell.style(style)
+                            } else {
+                                cell
+                            }
                         },
                     ));
-                    Row::new(cells)
+                    row_layout.push((ri, height as u16));
+                    Row::new(cells).height(height as u16)
                 })
                 .collect();
+        state.rows = row_layout;
+        state.columns = visible_columns.clone();
         let constraints: Vec<Constraint> = visible_columns
             .iter()
             .map(|vc| Constraint::from(vc))
             .collect();
-        let end_idx = visible_columns.last().unwrap().idx;
         let mut header = Vec::with_capacity(constraints.len());
         header.push(Cell::new(""));
-        header.extend((state.prev_corner.col..=end_idx).map(|i| {
-            let count = if i == 26 { 1 } else { (i / 26) + 1 };
+        header.extend(visible_columns.iter().map(|&VisibleColumn { idx: i, .. }| {
             let even = i % 2 == 0;
-            Cell::new(Line::raw(COLNAMES[(i - 1) % 26].repeat(count)).centered())
-                .bg(if even {
+            let cell = Cell::new(Line::raw(column_name(i)).centered());
+            if i == self.book.location.col {
+                cell.bg(CURSOR_GUTTER_BG).fg(CURSOR_GUTTER_FG).bold()
+            } else {
+                cell.bg(if even {
                     Color::Rgb(57, 61, 71)
                 } else {
                     Color::Rgb(165, 169, 160)
                 })
                 .fg(if even { Color::White } else { Color::Black })
                 .bold()
+            }
         }));
-        let mut col_constraints = vec![Constraint::Length(5)];
+        let mut col_constraints = vec![Constraint::Length(GUTTER_WIDTH)];
         col_constraints.extend(constraints.into_iter());
         Ok(Table::new(rows, col_constraints)
             .header(Row::new(header).underlined())
@@ -189,6 +438,25 @@ impl<'ws> Viewport<'ws> {
             .flex(Flex::Start))
     }
 
+    /// Render scrollbars over the right and bottom edges of the area showing
+    /// where the viewport is in the full extent of the sheet.
+    fn render_scrollbars(&self, area: Rect, buf: &mut Buffer, state: &mut ViewportState) {
+        let (row_count, col_count) = self.book.get_size().unwrap_or_default();
+        let (visible_rows, visible_cols) = state.visible;
+        let mut vertical = scrollbar_state(state.prev_corner.row, visible_rows, row_count);
+        let mut horizontal = scrollbar_state(state.prev_corner.col, visible_cols, col_count);
+        Scrollbar::new(ScrollbarOrientation::VerticalRight).render(
+            area.inner(Margin::new(0, 1)),
+            buf,
+            &mut vertical,
+        );
+        Scrollbar::new(ScrollbarOrientation::HorizontalBottom).render(
+            area.inner(Margin::new(1, 0)),
+            buf,
+            &mut horizontal,
+        );
+    }
+
     fn compute_cell_style<'widget>(
         &self,
         ri: usize,
@@ -217,13 +485,33 @@ impl<'ws> Viewport<'ws> {
     ) -> Cell<'widget> {
         let bg_color = map_color(style.fill.bg_color.as_ref(), Color::Rgb(35, 33, 54));
         let fg_color = map_color(style.fill.fg_color.as_ref(), Color::White);
-        if let Some((start, end)) = &self.range_selection.map_or(None, |r| r.get_range()) {
-            if ri >= start.row && ri <= end.row && ci >= start.col && ci <= end.col {
-                // This is a selected range
-                cell = cell.fg(Color::Black).bg(Color::LightBlue)
+        let address = Address {
+            sheet: self.book.location.sheet,
+            row: ri,
+            col: ci,
+        };
+        cell = cell.bg(bg_color).fg(fg_color);
+        if style.fill.bg_color.is_none() && self.book.is_locked(&address) {
+            cell = cell.bg(LOCKED_CELL_BG);
+        }
+        if let Some(format) = self.book.get_conditional_format(&address) {
+            if let Some(bg) = format.bg_color.as_ref() {
+                cell = cell.bg(map_color(Some(bg), bg_color));
+            }
+            if let Some(fg) = format.fg_color.as_ref() {
+                cell = cell.fg(map_color(Some(fg), fg_color));
             }
-        } else {
-            cell = cell.bg(bg_color).fg(fg_color);
+        }
+        if self.search_matches.binary_search(&address).is_ok() {
+            cell = cell.bg(SEARCH_MATCH_BG).fg(Color::Black);
+        }
+        if self
+            .range_selection
+            .and_then(|r| r.selection())
+            .is_some_and(|selection| selection.contains(&address))
+        {
+            // This is a selected range
+            cell = cell.reversed();
         }
         cell = match (self.book.location.row == ri, self.book.location.col == ci) {
             (true, true) => cell.fg(Color::White).bg(Color::Rgb(57, 61, 71)),
@@ -234,6 +522,52 @@ impl<'ws> Viewport<'ws> {
     }
 }
 
+/// Render markdown cell content with its inline styles cut off at the column
+/// width. Columns too narrow to be worth styling get the plain text.
+fn markdown_cell_text(md: &str, width: u16) -> Text<'static> {
+    let md = Markdown::from_str(md);
+    if width < MIN_MARKDOWN_CELL_WIDTH {
+        return Text::raw(md.to_plain_text());
+    }
+    let mut text = md.into_text();
+    text.lines = text
+        .lines
+        .iter()
+        .map(|line| truncate_line(line, width as usize))
+        .collect();
+    text
+}
+
+/// Pad or truncate the first line of content to the column width leaving room
+/// for the note marker in the last column.
+fn with_note_marker(mut text: Text<'static>, width: u16) -> Text<'static> {
+    let width = (width as usize).saturating_sub(1);
+    let first = if text.lines.is_empty() {
+        Line::default()
+    } else {
+        text.lines.remove(0)
+    };
+    // Wide characters take up two columns so we measure the display width.
+    let mut line = truncate_line(&first, width);
+    let padding = width.saturating_sub(line_width(&line));
+    line.spans.push(Span::raw(" ".repeat(padding)));
+    line.spans.push(Span::raw(NOTE_MARKER).fg(Color::Yellow));
+    text.lines.insert(0, line);
+    text
+}
+
+/// Compute the scrollbar state for a viewport starting at the 1 based index
+/// `first` showing `visible` items of a sheet that has data out to `extent`.
+/// The extent always includes the viewport itself so you can scroll past the
+/// end of the data.
+pub(crate) fn scrollbar_state(first: usize, visible: usize, extent: usize) -> ScrollbarState {
+    let position = first.saturating_sub(1);
+    let content_length = std::cmp::max(extent, position + visible);
+    ScrollbarState::new(content_length)
+        .position(position)
+        .viewport_content_length(visible)
+}
+
 pub(crate) fn map_color(color: Option<&String>, otherwise: Color) -> Color {
     color
         .map(|s| match s.to_lowercase().as_str() {
@@ -289,9 +623,11 @@ impl<'ws> StatefulWidget for Viewport<'ws> {
         let mut table = self
             .to_table(area.width - 2, area.height - 2, state)
             .expect("Failed to turn viewport into a table.");
-        if let Some(block) = self.block {
+        state.area = area.inner(Margin::new(1, 1));
+        if let Some(block) = self.block.clone() {
             table = table.block(block);
         }
         Widget::render(table, area, buf);
+        self.render_scrollbars(area, buf, state);
     }
 }
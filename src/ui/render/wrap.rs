@@ -0,0 +1,218 @@
+//! Word wrapping helpers shared by our widgets.
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Wrap text into lines no wider than `width` columns.
+///
+/// Lines are broken at whitespace where possible. Words longer than `width`
+/// are split across lines. Existing newlines in the text are preserved. Wide
+/// characters like CJK take up two columns.
+pub fn wrap_str(text: &str, width: usize) -> Vec<String> {
+    if width == 0 {
+        return vec![text.to_owned()];
+    }
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        let mut current_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let sep = if current_len == 0 { 0 } else { 1 };
+            let word_width = chars_width(&word);
+            if current_len + sep + word_width <= width {
+                if sep == 1 {
+                    current.push(' ');
+                }
+                current.extend(word.iter());
+                current_len += sep + word_width;
+                continue;
+            }
+            if current_len > 0 {
+                lines.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            // Split any words that can't fit on a line of their own.
+            while chars_width(&word) > width {
+                let take = std::cmp::max(fit_chars(&word, width), 1);
+                lines.push(word.drain(..take).collect());
+            }
+            current.extend(word.iter());
+            current_len = chars_width(&word);
+        }
+        lines.push(current);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Wrap a styled line into lines no wider than `width` columns.
+///
+/// Like `wrap_str` lines are broken at whitespace where possible and each
+/// piece of text keeps the style of the span it came from. Lines after the
+/// first are indented by `indent` columns. Whitespace at the start of the line
+/// is kept as is.
+pub fn wrap_line(line: &Line<'static>, width: usize, indent: usize) -> Vec<Line<'static>> {
+    let indent = if indent < width { indent } else { 0 };
+    if width == 0 || line_width(line) <= width {
+        return vec![line.clone()];
+    }
+    let mut words: Vec<Word> = Vec::new();
+    for span in line.spans.iter() {
+        let mut chars = span.content.chars().peekable();
+        while let Some(c) = chars.next() {
+            let space = c.is_whitespace();
+            let mut text = String::from(c);
+            while let Some(next) = chars.next_if(|n| n.is_whitespace() == space) {
+                text.push(next);
+            }
+            match words.last_mut() {
+                // Text with no whitespace between spans is still one word.
+                Some(word) if !space && !word.space => word.pieces.push((text, span.style)),
+                _ => words.push(Word {
+                    pieces: vec![(text, span.style)],
+                    space,
+                }),
+            }
+        }
+    }
+    // Leading whitespace is indentation so we treat it like a word.
+    if let Some(first) = words.first_mut() {
+        first.space = false;
+    }
+
+    let new_line = |indent: usize| {
+        let mut wrapped = Line::default().style(line.style);
+        wrapped.alignment = line.alignment;
+        if indent > 0 {
+            wrapped.spans.push(Span::raw(" ".repeat(indent)));
+        }
+        wrapped
+    };
+    let mut lines = Vec::new();
+    let mut current = new_line(0);
+    let mut current_len = 0;
+    // Whitespace we only want to keep if the next word fits on the same line.
+    let mut pending: Option<Word> = None;
+    for word in words {
+        if word.space {
+            pending = Some(word);
+            continue;
+        }
+        let sep = pending.as_ref().map_or(0, Word::width);
+        let len = word.width();
+        let line_start = if lines.is_empty() { 0 } else { indent };
+        if current_len + sep + len > width && current_len > line_start {
+            lines.push(std::mem::replace(&mut current, new_line(indent)));
+            current_len = indent;
+        } else if let Some(space) = pending {
+            current.spans.extend(space.into_spans());
+            current_len += sep;
+        }
+        pending = None;
+        for (text, style) in word.pieces {
+            let mut chars: Vec<char> = text.chars().collect();
+            // Split any words that can't fit on a line of their own.
+            while current_len + chars_width(&chars) > width {
+                let line_start = if lines.is_empty() { 0 } else { indent };
+                let mut take = fit_chars(&chars, width.saturating_sub(current_len));
+                if take == 0 && current_len == line_start {
+                    // A character wider than the line still has to go somewhere.
+                    take = 1;
+                }
+                if take > 0 {
+                    current
+                        .spans
+                        .push(Span::styled(chars.drain(..take).collect::<String>(), style));
+                }
+                lines.push(std::mem::replace(&mut current, new_line(indent)));
+                current_len = indent;
+            }
+            current_len += chars_width(&chars);
+            current
+                .spans
+                .push(Span::styled(chars.into_iter().collect::<String>(), style));
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+/// Cut a styled line off at `width` columns. Each piece of text keeps the
+/// style of the span it came from.
+pub fn truncate_line(line: &Line<'static>, width: usize) -> Line<'static> {
+    let mut truncated = Line::default().style(line.style);
+    truncated.alignment = line.alignment;
+    let mut used = 0;
+    for span in line.spans.iter() {
+        let chars: Vec<char> = span.content.chars().collect();
+        let take = fit_chars(&chars, width - used);
+        used += chars_width(&chars[..take]);
+        truncated.spans.push(Span::styled(
+            chars[..take].iter().collect::<String>(),
+            span.style,
+        ));
+        if take < chars.len() {
+            break;
+        }
+    }
+    truncated
+}
+
+/// Center a line in `width` columns by padding it on the left. When the
+/// leftover space is odd the extra column goes on the right so the same text
+/// always lands in the same place.
+pub fn center_line(line: &Line<'static>, width: usize) -> Line<'static> {
+    let padding = width.saturating_sub(line_width(line)) / 2;
+    let mut centered = line.clone();
+    centered.alignment = None;
+    if padding > 0 {
+        centered.spans.insert(0, Span::raw(" ".repeat(padding)));
+    }
+    centered
+}
+
+/// The number of columns a line takes up.
+pub fn line_width(line: &Line<'_>) -> usize {
+    line.spans.iter().map(|s| s.content.width()).sum()
+}
+
+/// The number of columns some characters take up.
+fn chars_width(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// The number of characters from the start that fit in `width` columns.
+fn fit_chars(chars: &[char], width: usize) -> usize {
+    let mut used = 0;
+    chars
+        .iter()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .count()
+}
+
+/// A run of text without whitespace or a run of whitespace that may span
+/// several styles.
+struct Word {
+    pieces: Vec<(String, Style)>,
+    space: bool,
+}
+
+impl Word {
+    fn width(&self) -> usize {
+        self.pieces.iter().map(|(t, _)| t.width()).sum()
+    }
+
+    fn into_spans(self) -> impl Iterator<Item = Span<'static>> {
+        self.pieces
+            .into_iter()
+            .map(|(text, style)| Span::styled(text, style))
+    }
+}
@@ -0,0 +1,693 @@
+use crossterm::event::{KeyEvent, KeyModifiers};
+use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+use super::*;
+
+fn new_workspace() -> Workspace<'static> {
+    Workspace::new_empty("en", "America/New_York").expect("Failed to create workspace")
+}
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+/// Send each character of `keys` to the workspace as a key press.
+fn type_keys(ws: &mut Workspace, keys: &str) {
+    for c in keys.chars() {
+        ws.handle_input(key(KeyCode::Char(c)))
+            .expect("Failed to handle input");
+    }
+}
+
+fn contents(ws: &Workspace, row: usize, col: usize) -> String {
+    ws.book
+        .get_cell_addr_contents(&Address::new(row, col))
+        .expect("Failed to get cell contents")
+}
+
+/// A path in the temp directory for a test file that doesn't exist yet.
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sheetui-test-{}-{}", std::process::id(), name));
+    remove_with_sidecar(&path);
+    path
+}
+
+fn remove_with_sidecar(path: &std::path::Path) {
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(Sidecar::path_for(path));
+}
+
+/// Render the workspace and return the contents of the screen.
+fn render_to_string(ws: &mut Workspace) -> String {
+    let area = Rect::new(0, 0, 100, 30);
+    let mut buf = Buffer::empty(area);
+    Widget::render(&mut *ws, area, &mut buf);
+    buf.content().iter().map(|cell| cell.symbol()).collect()
+}
+
+#[test]
+fn test_add_display_and_remove_note() {
+    let mut ws = new_workspace();
+    let addr = Address::new(1, 1);
+    ws.handle_command("note remember the milk".to_owned())
+        .expect("Failed to add note");
+    assert_eq!(ws.book.get_note(&addr), Some("remember the milk"));
+
+    let screen = render_to_string(&mut ws);
+    assert!(screen.contains(render::viewport::NOTE_MARKER));
+    assert!(screen.contains("remember the milk"));
+
+    ws.handle_command("clear-note".to_owned())
+        .expect("Failed to clear note");
+    assert_eq!(ws.book.get_note(&addr), None);
+
+    let screen = render_to_string(&mut ws);
+    assert!(!screen.contains(render::viewport::NOTE_MARKER));
+    assert!(!screen.contains("remember the milk"));
+}
+
+#[test]
+fn test_record_and_replay_macro() {
+    let mut ws = new_workspace();
+    // Record appending an x to the current cell and moving down.
+    type_keys(&mut ws, "qaex");
+    ws.handle_input(key(KeyCode::Enter))
+        .expect("Failed to handle input");
+    type_keys(&mut ws, "jq");
+    assert_eq!(contents(&ws, 1, 1), "x");
+    assert_eq!(ws.book.location, Address::new(2, 1));
+    assert!(ws.state.macros.contains_key(&'a'));
+
+    type_keys(&mut ws, "@a");
+    assert_eq!(contents(&ws, 2, 1), "x");
+    assert_eq!(ws.book.location, Address::new(3, 1));
+
+    // A count replays the macro that many times.
+    type_keys(&mut ws, "2@a");
+    assert_eq!(contents(&ws, 3, 1), "x");
+    assert_eq!(contents(&ws, 4, 1), "x");
+    assert_eq!(contents(&ws, 5, 1), "");
+    assert_eq!(ws.book.location, Address::new(5, 1));
+}
+
+#[test]
+fn test_save_and_restore_position() {
+    let path = temp_path("position.csv");
+    let mut ws = Workspace::new(Book::default(), path.clone());
+    ws.book
+        .update_cell(&Address::new(1, 1), "x")
+        .expect("Failed to update cell");
+    ws.book.set_note(&Address::new(1, 1), "a note");
+    ws.book
+        .move_to(&Address::new(40, 3))
+        .expect("Failed to move");
+    ws.state.viewport_state.set_corner(Address::new(30, 2));
+    ws.save_file().expect("Failed to save");
+
+    let restored = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    remove_with_sidecar(&path);
+    assert_eq!(restored.book.location, Address::new(40, 3));
+    assert_eq!(restored.state.viewport_state.corner(), &Address::new(30, 2));
+    assert_eq!(contents(&restored, 1, 1), "x");
+    assert!(!restored.book.dirty);
+}
+
+#[test]
+fn test_position_alone_does_not_write_sidecar() {
+    let path = temp_path("no-sidecar.csv");
+    let mut ws = Workspace::new(Book::default(), path.clone());
+    ws.book
+        .move_to(&Address::new(4, 2))
+        .expect("Failed to move");
+    ws.save_file().expect("Failed to save");
+    let sidecar_exists = Sidecar::path_for(&path).exists();
+    remove_with_sidecar(&path);
+    assert!(!sidecar_exists);
+}
+
+#[test]
+fn test_corrupt_sidecar_is_ignored() {
+    let path = temp_path("corrupt.csv");
+    std::fs::write(&path, "a,b\n").expect("Failed to write test file");
+    std::fs::write(Sidecar::path_for(&path), "{ not json").expect("Failed to write sidecar");
+    let ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    remove_with_sidecar(&path);
+    assert_eq!(ws.book.location, Address::default());
+    assert_eq!(contents(&ws, 1, 2), "b");
+}
+
+#[test]
+fn test_edit_locked_cell_is_rejected() {
+    let mut ws = new_workspace();
+    ws.handle_command("lock".to_owned())
+        .expect("Failed to lock cell");
+    type_keys(&mut ws, "e");
+    assert_eq!(ws.state.modality(), &Modality::Navigate);
+    assert_eq!(
+        ws.state.status_message.as_deref(),
+        Some("Cell A1 is locked")
+    );
+
+    type_keys(&mut ws, "lex");
+    ws.handle_input(key(KeyCode::Enter))
+        .expect("Failed to handle input");
+    assert_eq!(ws.state.status_message, None);
+    assert_eq!(contents(&ws, 1, 1), "");
+    assert_eq!(contents(&ws, 1, 2), "x");
+}
+
+#[test]
+fn test_formula_bar_shows_raw_formula() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(1, 1), "=1+2")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    ws.handle_movement_change();
+    assert_eq!(ws.text_area.lines(), ["=1+2"]);
+    assert_eq!(ws.book.get_current_cell_rendered().unwrap(), "3");
+    let screen = render_to_string(&mut ws);
+    assert!(screen.contains("A1 | =1+2 = 3"));
+}
+
+#[test]
+fn test_file_watch_debounces_changes() {
+    use std::time::{Duration, Instant, SystemTime};
+    let start = Instant::now();
+    let saved = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+    let first_write = saved + Duration::from_secs(1);
+    let second_write = saved + Duration::from_secs(2);
+    let mut watch = watch::FileWatch::new(Some(saved));
+    assert!(!watch.observe(Some(saved), start));
+    assert!(!watch.observe(Some(first_write), start));
+    assert!(!watch.observe(Some(first_write), start + Duration::from_millis(100)));
+    // Another write restarts the debounce.
+    assert!(!watch.observe(Some(second_write), start + watch::DEBOUNCE));
+    assert!(!watch.observe(Some(second_write), start + watch::DEBOUNCE * 3 / 2));
+    assert!(watch.observe(Some(second_write), start + watch::DEBOUNCE * 2));
+    // The change is only reported once.
+    assert!(!watch.observe(Some(second_write), start + watch::DEBOUNCE * 3));
+}
+
+#[test]
+fn test_reload_on_external_change() {
+    use std::time::{Duration, Instant};
+    let path = temp_path("reload.csv");
+    std::fs::write(&path, "a,b\n").expect("Failed to write test file");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    ws.enable_watch();
+    std::fs::write(&path, "c,d\n").expect("Failed to write test file");
+    // Simulate the change event rather than waiting on the filesystem.
+    let modified = watch::modified_time(&path).map(|t| t + Duration::from_secs(1));
+    let now = Instant::now();
+    ws.handle_file_observed(modified, now);
+    assert_eq!(contents(&ws, 1, 1), "a");
+    ws.handle_file_observed(modified, now + watch::DEBOUNCE);
+    remove_with_sidecar(&path);
+    assert_eq!(contents(&ws, 1, 1), "c");
+    assert_eq!(contents(&ws, 1, 2), "d");
+}
+
+#[test]
+fn test_reload_with_unsaved_edits_asks_first() {
+    use std::time::{Duration, Instant};
+    let path = temp_path("reload-dirty.csv");
+    std::fs::write(&path, "a,b\n").expect("Failed to write test file");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    ws.enable_watch();
+    ws.book
+        .update_cell(&Address::new(1, 1), "edited")
+        .expect("Failed to update cell");
+    assert!(ws.book.dirty);
+    std::fs::write(&path, "c,d\n").expect("Failed to write test file");
+    let modified = watch::modified_time(&path).map(|t| t + Duration::from_secs(1));
+    let now = Instant::now();
+    ws.handle_file_observed(modified, now);
+    ws.handle_file_observed(modified, now + watch::DEBOUNCE);
+    assert_eq!(ws.state.modality(), &Modality::Reload);
+    assert_eq!(contents(&ws, 1, 1), "edited");
+
+    type_keys(&mut ws, "y");
+    remove_with_sidecar(&path);
+    assert_eq!(ws.state.modality(), &Modality::Navigate);
+    assert_eq!(contents(&ws, 1, 1), "c");
+}
+
+#[test]
+fn test_reload_after_moving_does_not_ask() {
+    use std::time::{Duration, Instant};
+    let path = temp_path("reload-moved.csv");
+    std::fs::write(&path, "a,b\n").expect("Failed to write test file");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    ws.enable_watch();
+    // Moving the cursor isn't an unsaved edit.
+    type_keys(&mut ws, "jl");
+    assert!(!ws.book.dirty);
+    std::fs::write(&path, "c,d\n").expect("Failed to write test file");
+    let modified = watch::modified_time(&path).map(|t| t + Duration::from_secs(1));
+    let now = Instant::now();
+    ws.handle_file_observed(modified, now);
+    ws.handle_file_observed(modified, now + watch::DEBOUNCE);
+    remove_with_sidecar(&path);
+    assert_eq!(ws.state.modality(), &Modality::Navigate);
+    assert_eq!(contents(&ws, 1, 1), "c");
+    assert_eq!(ws.book.location, Address::new(2, 2));
+}
+
+#[test]
+fn test_deleted_file_is_not_reloaded() {
+    use std::time::Instant;
+    let path = temp_path("reload-deleted.csv");
+    std::fs::write(&path, "a,b\n").expect("Failed to write test file");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    ws.enable_watch();
+    remove_with_sidecar(&path);
+    let now = Instant::now();
+    ws.handle_file_observed(watch::modified_time(&path), now);
+    ws.handle_file_observed(watch::modified_time(&path), now + watch::DEBOUNCE);
+    assert_eq!(contents(&ws, 1, 1), "a");
+    assert_eq!(contents(&ws, 1, 2), "b");
+}
+
+#[test]
+fn test_failed_reload_keeps_book() {
+    use std::time::Instant;
+    let path = temp_path("reload-broken.xlsx");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("Failed to load");
+    ws.book
+        .update_cell(&Address::new(1, 1), "mine")
+        .expect("Failed to update cell");
+    ws.book.dirty = false;
+    ws.enable_watch();
+    // A file caught partway through a write doesn't parse.
+    std::fs::write(&path, "not a workbook").expect("Failed to write test file");
+    let modified = watch::modified_time(&path);
+    let now = Instant::now();
+    ws.handle_file_observed(modified, now);
+    ws.handle_file_observed(modified, now + watch::DEBOUNCE);
+    remove_with_sidecar(&path);
+    assert_eq!(contents(&ws, 1, 1), "mine");
+    assert!(
+        ws.state
+            .status_message
+            .as_deref()
+            .is_some_and(|msg| msg.starts_with("Unable to reload"))
+    );
+}
+
+#[test]
+fn test_selection_contains() {
+    // A 3x2 block from B2 to C4 chosen from the bottom right corner.
+    let selection = Selection::new(Address::new(4, 3), Address::new(2, 2));
+    assert_eq!(selection.rows(), 2..=4);
+    assert_eq!(selection.cols(), 2..=3);
+    for (row, col) in [(2, 2), (2, 3), (4, 2), (4, 3), (3, 2)] {
+        assert!(selection.contains(&Address::new(row, col)));
+    }
+    for (row, col) in [(1, 2), (5, 3), (2, 1), (4, 4), (1, 1)] {
+        assert!(!selection.contains(&Address::new(row, col)));
+    }
+    let other_sheet = Address {
+        sheet: 1,
+        ..Address::new(3, 2)
+    };
+    assert!(!selection.contains(&other_sheet));
+}
+
+#[test]
+fn test_shift_arrow_starts_selection() {
+    let mut ws = new_workspace();
+    ws.handle_input(Event::Key(KeyEvent::new(
+        KeyCode::Down,
+        KeyModifiers::SHIFT,
+    )))
+    .expect("Failed to handle input");
+    assert_eq!(ws.state.modality(), &Modality::RangeSelect);
+    assert_eq!(
+        ws.state.range_select.selection(),
+        Some(Selection::new(Address::new(1, 1), Address::new(2, 1)))
+    );
+}
+
+#[test]
+fn test_paste_range_clips_at_sheet_edge() {
+    let mut ws = new_workspace();
+    let last_col = book::LAST_COLUMN as usize;
+    ws.book
+        .move_to(&Address::new(1, last_col - 1))
+        .expect("Failed to move");
+    ws.state.clipboard = Some(ClipboardContents::Range(vec![
+        vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+        vec!["d".to_owned(), "e".to_owned(), "f".to_owned()],
+    ]));
+    ws.paste_range(false).expect("Failed to paste");
+    assert_eq!(contents(&ws, 1, last_col - 1), "a");
+    assert_eq!(contents(&ws, 1, last_col), "b");
+    assert_eq!(contents(&ws, 2, last_col - 1), "d");
+    assert_eq!(contents(&ws, 2, last_col), "e");
+}
+
+// With the clipboard feature this would read whatever is on the system
+// clipboard.
+#[cfg(not(feature = "clipboard"))]
+#[test]
+fn test_paste_empty_clipboard_is_noop() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(1, 1), "keep")
+        .expect("Failed to update cell");
+    ws.paste_range(false).expect("Failed to paste");
+    assert_eq!(contents(&ws, 1, 1), "keep");
+    assert!(ws.state.clipboard.is_none());
+}
+
+#[test]
+fn test_undo_and_redo_keys() {
+    let mut ws = new_workspace();
+    type_keys(&mut ws, "ex");
+    ws.handle_input(key(KeyCode::Enter))
+        .expect("Failed to handle input");
+    assert_eq!(contents(&ws, 1, 1), "x");
+    type_keys(&mut ws, "u");
+    assert_eq!(contents(&ws, 1, 1), "");
+    ws.handle_input(Event::Key(KeyEvent::new(
+        KeyCode::Char('r'),
+        KeyModifiers::CONTROL,
+    )))
+    .expect("Failed to handle input");
+    assert_eq!(contents(&ws, 1, 1), "x");
+}
+
+#[test]
+fn test_import_csv_cmd() {
+    assert_eq!(
+        cmd::parse("import-csv data.csv"),
+        Ok(Some(Cmd::ImportCsv("data.csv", None)))
+    );
+    assert_eq!(
+        cmd::parse("import-csv my data.tsv tab"),
+        Ok(Some(Cmd::ImportCsv("my data.tsv", Some(b'\t'))))
+    );
+    assert!(cmd::parse("import-csv").is_err());
+}
+
+#[test]
+fn test_export_csv_cmd() {
+    assert_eq!(
+        cmd::parse("export-csv out.csv"),
+        Ok(Some(Cmd::ExportCsv("out.csv", None, true)))
+    );
+    assert_eq!(
+        cmd::parse("export-csv out.tsv tab no-header"),
+        Ok(Some(Cmd::ExportCsv("out.tsv", Some(b'\t'), false)))
+    );
+    assert_eq!(
+        cmd::parse("export-csv out.csv no-header semicolon"),
+        Ok(Some(Cmd::ExportCsv("out.csv", Some(b';'), false)))
+    );
+}
+
+#[test]
+fn test_address_from_a1() {
+    let parse = |text: &str| Address::from_a1(0, text).map(|a| (a.row, a.col));
+    assert_eq!(parse("A1").unwrap(), (1, 1));
+    assert_eq!(parse("Z100").unwrap(), (100, 26));
+    assert_eq!(parse("AA1").unwrap(), (1, 27));
+    assert_eq!(parse("ab7").unwrap(), (7, 28));
+    assert_eq!(parse("XFD1048576").unwrap(), (1_048_576, 16_384));
+    for bad in ["", "A", "7", "1A", "A0", "A1x", "XFE1", "A1048577"] {
+        assert!(parse(bad).is_err(), "{:?} parsed", bad);
+    }
+    assert_eq!(Address::from_a1(2, "B3").unwrap().sheet, 2);
+}
+
+#[test]
+fn test_goto_cmd() {
+    assert_eq!(cmd::parse("Z100"), Ok(Some(Cmd::Goto("Z100"))));
+    assert_eq!(cmd::parse("goto AB7"), Ok(Some(Cmd::Goto("AB7"))));
+    assert!(cmd::parse("goto").is_err());
+    assert!(cmd::parse("goto ").is_err());
+
+    let mut ws = new_workspace();
+    ws.handle_command("goto AB7".to_owned())
+        .expect("Failed to goto");
+    assert_eq!(ws.book.location, Address::new(7, 28));
+    // A bad address reports an error and leaves the cursor alone.
+    ws.handle_command("goto A0".to_owned())
+        .expect("Failed to goto");
+    assert_eq!(ws.book.location, Address::new(7, 28));
+    assert!(ws.state.status_message.is_some());
+}
+
+#[test]
+fn test_search_next_match_wraps() {
+    let search = SearchState {
+        query: "apple".to_owned(),
+        case_sensitive: false,
+        matches: vec![Address::new(1, 1), Address::new(2, 2), Address::new(3, 3)],
+    };
+    let next = |row, col, forward| search.next_match(&Address::new(row, col), forward).cloned();
+    assert_eq!(next(2, 2, true), Some(Address::new(3, 3)));
+    assert_eq!(next(3, 3, true), Some(Address::new(1, 1)));
+    assert_eq!(next(1, 2, true), Some(Address::new(2, 2)));
+    assert_eq!(next(2, 2, false), Some(Address::new(1, 1)));
+    assert_eq!(next(1, 1, false), Some(Address::new(3, 3)));
+    assert_eq!(
+        SearchState::default().next_match(&Address::new(1, 1), true),
+        None
+    );
+}
+
+fn rendered(ws: &Workspace, row: usize, col: usize) -> String {
+    ws.book
+        .get_cell_addr_rendered(&Address::new(row, col))
+        .expect("Failed to get rendered cell")
+}
+
+#[test]
+fn test_dd_deletes_row() {
+    let mut ws = new_workspace();
+    for (row, value) in ["1", "2", "=A2*2", "=A1+1"].iter().enumerate() {
+        ws.book
+            .update_cell(&Address::new(row + 1, 1), value)
+            .expect("Failed to update cell");
+    }
+    ws.book.evaluate();
+    ws.book
+        .move_to(&Address::new(2, 1))
+        .expect("Failed to move");
+    type_keys(&mut ws, "dd");
+    assert_eq!(contents(&ws, 1, 1), "1");
+    // The formula that pointed into the deleted row can't find it anymore.
+    assert_eq!(rendered(&ws, 2, 1), "#REF!");
+    assert_eq!(contents(&ws, 3, 1), "=A1+1");
+    assert_eq!(rendered(&ws, 3, 1), "2");
+    assert_eq!(contents(&ws, 4, 1), "");
+}
+
+#[test]
+fn test_insert_rows_cmd() {
+    assert_eq!(cmd::parse("insert-rows"), Ok(Some(Cmd::InsertRows(1))));
+    assert_eq!(cmd::parse("ir 3"), Ok(Some(Cmd::InsertRows(3))));
+    assert_eq!(cmd::parse("delete-rows 2"), Ok(Some(Cmd::DeleteRows(2))));
+    assert_eq!(cmd::parse("dc"), Ok(Some(Cmd::DeleteColumns(1))));
+    assert!(cmd::parse("insert-rows many").is_err());
+
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(2, 1), "5")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(3, 1), "=A2*2")
+        .expect("Failed to update cell");
+    ws.handle_command("insert-rows".to_owned())
+        .expect("Failed to insert rows");
+    assert_eq!(contents(&ws, 3, 1), "5");
+    assert_eq!(contents(&ws, 4, 1), "=A3*2");
+    assert_eq!(rendered(&ws, 4, 1), "10");
+}
+
+#[test]
+fn test_paste_skips_locked_cells() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(1, 2), "keep")
+        .expect("Failed to update cell");
+    ws.book.lock_range(&Address::new(1, 2), &Address::new(1, 2));
+    ws.state.clipboard = Some(ClipboardContents::Range(vec![vec![
+        "a".to_owned(),
+        "b".to_owned(),
+        "c".to_owned(),
+    ]]));
+    ws.paste_range(false).expect("Failed to paste");
+    assert_eq!(contents(&ws, 1, 1), "a");
+    assert_eq!(contents(&ws, 1, 2), "keep");
+    assert_eq!(contents(&ws, 1, 3), "c");
+
+    // Pasting a single cell onto a locked cell says why nothing happened.
+    ws.book
+        .move_to(&Address::new(1, 2))
+        .expect("Failed to move");
+    ws.state.clipboard = Some(ClipboardContents::Cell("x".to_owned()));
+    ws.paste_range(false).expect("Failed to paste");
+    assert_eq!(contents(&ws, 1, 2), "keep");
+    assert_eq!(
+        ws.state.status_message.as_deref(),
+        Some("Cell B1 is locked")
+    );
+}
+
+#[test]
+fn test_parse_key() {
+    use keymap::parse_key;
+    assert_eq!(
+        parse_key("j"),
+        Some((KeyCode::Char('j'), KeyModifiers::NONE))
+    );
+    assert_eq!(
+        parse_key("ctrl-n"),
+        Some((KeyCode::Char('n'), KeyModifiers::CONTROL))
+    );
+    assert_eq!(
+        parse_key("alt-h"),
+        Some((KeyCode::Char('h'), KeyModifiers::ALT))
+    );
+    assert_eq!(
+        parse_key("shift-tab"),
+        Some((KeyCode::Tab, KeyModifiers::SHIFT))
+    );
+    assert_eq!(
+        parse_key("Ctrl-Alt-x"),
+        Some((
+            KeyCode::Char('x'),
+            KeyModifiers::CONTROL | KeyModifiers::ALT
+        ))
+    );
+    assert_eq!(parse_key("up"), Some((KeyCode::Up, KeyModifiers::NONE)));
+    assert_eq!(
+        parse_key("pageup"),
+        Some((KeyCode::PageUp, KeyModifiers::NONE))
+    );
+    assert_eq!(
+        parse_key("space"),
+        Some((KeyCode::Char(' '), KeyModifiers::NONE))
+    );
+    assert_eq!(parse_key(""), None);
+    assert_eq!(parse_key("foo"), None);
+}
+
+#[test]
+fn test_remapped_navigation_key() {
+    let mut ws = new_workspace();
+    ws.state.keymap = KeyMap::from_config_str("# move with w and x\nw = move-up\nx = move-down\n")
+        .expect("Failed to parse keymap");
+    type_keys(&mut ws, "xx");
+    assert_eq!(ws.book.location, Address::new(3, 1));
+    type_keys(&mut ws, "w");
+    assert_eq!(ws.book.location, Address::new(2, 1));
+    // Default bindings that weren't overridden still work.
+    type_keys(&mut ws, "j");
+    assert_eq!(ws.book.location, Address::new(3, 1));
+    // Unmapped keys fall through to the hardcoded handling.
+    assert_eq!(
+        ws.state
+            .keymap
+            .get(&KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE)),
+        None
+    );
+}
+
+#[test]
+fn test_keymap_config_errors() {
+    assert!(KeyMap::from_config_str("w move-up").is_err());
+    assert!(KeyMap::from_config_str("foo = move-up").is_err());
+    assert!(KeyMap::from_config_str("w = fly-away").is_err());
+}
+
+#[test]
+fn test_wrap_text_cmd() {
+    assert_eq!(cmd::parse("wrap-text"), Ok(Some(Cmd::WrapText)));
+    assert!(cmd::parse("wrap-text now").is_err());
+    // The short form of write still works.
+    assert_eq!(cmd::parse("w"), Ok(Some(Cmd::Write(None))));
+    assert_eq!(
+        cmd::parse("w out.xlsx"),
+        Ok(Some(Cmd::Write(Some("out.xlsx"))))
+    );
+}
+
+#[test]
+fn test_paste_transposed() {
+    let mut ws = new_workspace();
+    ws.state.clipboard = Some(ClipboardContents::Range(vec![
+        vec!["1".to_owned(), "2".to_owned(), "3".to_owned()],
+        vec!["4".to_owned(), "5".to_owned(), "6".to_owned()],
+    ]));
+    ws.paste_range(true).expect("Failed to paste");
+    for (row, values) in [["1", "4"], ["2", "5"], ["3", "6"]].iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            assert_eq!(&contents(&ws, row + 1, col + 1), value);
+        }
+    }
+    assert_eq!(contents(&ws, 1, 3), "");
+    // The whole paste is undone in one step.
+    type_keys(&mut ws, "u");
+    for row in 1..=3 {
+        for col in 1..=2 {
+            assert_eq!(contents(&ws, row, col), "");
+        }
+    }
+}
+
+#[test]
+fn test_gt_switches_sheets() {
+    let mut ws = new_workspace();
+    ws.book.new_sheet(Some("Two")).expect("Failed to add sheet");
+    ws.book
+        .new_sheet(Some("Three"))
+        .expect("Failed to add sheet");
+    assert_eq!(ws.book.location.sheet, 0);
+    type_keys(&mut ws, "gt");
+    assert_eq!(ws.book.location.sheet, 1);
+    // The last sheet wraps around to the first.
+    type_keys(&mut ws, "gtgt");
+    assert_eq!(ws.book.location.sheet, 0);
+    // And backwards from the first to the last.
+    type_keys(&mut ws, "gT");
+    assert_eq!(ws.book.location.sheet, 2);
+    type_keys(&mut ws, "gT");
+    assert_eq!(ws.book.location.sheet, 1);
+    // A count jumps straight to that sheet.
+    type_keys(&mut ws, "3gt");
+    assert_eq!(ws.book.location.sheet, 2);
+}
+
+#[test]
+fn test_alt_r_starts_range_select_in_both_modes() {
+    let alt_r = || Event::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT));
+    let mut ws = new_workspace();
+    ws.handle_input(alt_r()).expect("Failed to handle input");
+    assert_eq!(ws.state.modality(), &Modality::RangeSelect);
+
+    let mut ws = new_workspace();
+    type_keys(&mut ws, "e");
+    assert_eq!(ws.state.modality(), &Modality::CellEdit);
+    ws.handle_input(alt_r()).expect("Failed to handle input");
+    assert_eq!(ws.state.modality(), &Modality::RangeSelect);
+}
+
+#[test]
+fn test_q_can_be_bound_to_quit() {
+    let mut ws = new_workspace();
+    // By default `q` starts recording a macro.
+    type_keys(&mut ws, "qa");
+    assert!(ws.state.recording.is_some());
+    type_keys(&mut ws, "q");
+    assert!(ws.state.recording.is_none());
+
+    ws.state.keymap = KeyMap::from_config_str("q = quit").expect("Failed to parse keymap");
+    let code = ws
+        .handle_input(key(KeyCode::Char('q')))
+        .expect("Failed to handle input");
+    assert_eq!(code, Some(ExitCode::SUCCESS));
+}
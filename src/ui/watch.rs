@@ -0,0 +1,65 @@
+//! Watch the workbook file for changes made outside of sheetui.
+//!
+//! We poll the file's modification time instead of relying on platform file
+//! notifications. Editors often write a file in several steps so a change is
+//! only reported once the modification time has held steady for `DEBOUNCE`.
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long a modification time must be stable before we report the change.
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often the ui loop should check the file while idle.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Tracks the modification time of a file to detect external changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileWatch {
+    known: Option<SystemTime>,
+    pending: Option<(Option<SystemTime>, Instant)>,
+}
+
+impl FileWatch {
+    pub fn new(modified: Option<SystemTime>) -> Self {
+        Self {
+            known: modified,
+            pending: None,
+        }
+    }
+
+    /// Record a modification time we caused ourselves, like after a save, so
+    /// that it isn't reported as an external change.
+    pub fn acknowledge(&mut self, modified: Option<SystemTime>) {
+        self.known = modified;
+        self.pending = None;
+    }
+
+    /// Observe the file's modification time at `now`. Returns true once a new
+    /// modification time has been stable for `DEBOUNCE` and the file should be
+    /// reloaded.
+    pub fn observe(&mut self, modified: Option<SystemTime>, now: Instant) -> bool {
+        if modified == self.known {
+            self.pending = None;
+            return false;
+        }
+        match self.pending {
+            Some((pending, since)) if pending == modified => {
+                if now.duration_since(since) >= DEBOUNCE {
+                    self.acknowledge(modified);
+                    return true;
+                }
+            }
+            _ => {
+                self.pending = Some((modified, now));
+            }
+        }
+        false
+    }
+}
+
+/// The modification time of a file if we can get it.
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}